@@ -0,0 +1,66 @@
+//! Where the progress iterators get the time from.
+
+use core::time::Duration;
+
+/// A source of the current time for the progress iterators, e.g. a hardware tick counter on an
+/// embedded device, with `ProgressRecorderIter::with_clock(...)`. With the `std` feature,
+/// `StdClock` uses `std::time::Instant`, and is what `.progress()` etc. use.
+///
+/// ```
+/// # extern crate core;
+/// # use iter_progress::Clock;
+/// # use core::time::Duration;
+/// /// Milliseconds since boot
+/// struct Ticks(u64);
+///
+/// impl Clock for Ticks {
+///     type Instant = u64;
+///     fn now(&self) -> u64 {
+///         self.0
+///     }
+///     fn duration_between(&self, earlier: u64, later: u64) -> Duration {
+///         Duration::from_millis(later.saturating_sub(earlier))
+///     }
+/// }
+/// ```
+pub trait Clock {
+    /// A point in time
+    type Instant: Copy;
+
+    /// The current time
+    fn now(&self) -> Self::Instant;
+
+    /// How long it is from `earlier` to `later`, or 0 if `later` is before `earlier`
+    fn duration_between(&self, earlier: Self::Instant, later: Self::Instant) -> Duration;
+}
+
+/// A shared clock, e.g. so you can still change a fake clock while it's being used
+impl<C: Clock> Clock for &C {
+    type Instant = C::Instant;
+
+    fn now(&self) -> Self::Instant {
+        (*self).now()
+    }
+
+    fn duration_between(&self, earlier: Self::Instant, later: Self::Instant) -> Duration {
+        (*self).duration_between(earlier, later)
+    }
+}
+
+/// The `std::time::Instant` clock, which `.progress()` etc. use.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdClock;
+
+#[cfg(feature = "std")]
+impl Clock for StdClock {
+    type Instant = std::time::Instant;
+
+    fn now(&self) -> Self::Instant {
+        std::time::Instant::now()
+    }
+
+    fn duration_between(&self, earlier: Self::Instant, later: Self::Instant) -> Duration {
+        later.saturating_duration_since(earlier)
+    }
+}
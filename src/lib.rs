@@ -9,6 +9,7 @@
 //! # Example
 //!
 //! ```
+//! # #[cfg(feature = "std")] {
 //! use iter_progress::ProgressableIter;
 //! // Create an iterator that goes from 0 to 1,000
 //! let my_iter = 0..1_000;
@@ -27,11 +28,13 @@
 //!
 //! // We are 0.1% the way through
 //! assert_eq!(state.percent(), Some(0.1));
+//! # }
 //! ```
 //!
 //! Another usage:
 //!
 //! ```
+//! # #[cfg(feature = "std")] {
 //! use iter_progress::ProgressableIter;
 //! # let my_big_vec = vec![false; 100];
 //!
@@ -43,144 +46,1242 @@
 //!
 //!     // Do something to process `val`
 //! }
+//! # }
 //! ```
 //!
 //! `.do_every_n_sec` is a "best effort" attempt. It's single threaded, so will be called if the
 //! last time that was called was more than N sec ago. `.do_every_n_items` is called every N items.
+//!
+//! # `no_std`
+//!
+//! Turn off the default `std` feature to use this without `std` (it still needs `alloc`). Then
+//! there's no `.progress()`, since there's no `std::time::Instant`, so make the iterator with
+//! `ProgressRecorderIter::with_clock(iter, clock)`, where `clock` is your own `Clock`, e.g. a
+//! hardware timer. Printing, writing files, threads, and the wall clock time all need `std`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+// `no_std` adds this itself, so the `core::...` paths work either way
+#[cfg(feature = "std")]
+extern crate core;
+// Without `std`, `format!` & `vec!` come from here
+#[cfg_attr(not(feature = "std"), macro_use)]
+extern crate alloc;
+#[cfg(all(any(feature = "interrupt", feature = "terminal"), unix))]
+extern crate libc;
+#[cfg(feature = "log")]
+extern crate log;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+#[cfg(feature = "tracing")]
+extern crate tracing;
 
-use std::iter::Iterator;
-use std::ops::{Deref, DerefMut};
-use std::time::{Duration, Instant};
+#[cfg(not(feature = "std"))]
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+#[cfg(not(feature = "std"))]
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+use core::ops::{Deref, DerefMut};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::cell::Cell;
+#[cfg(feature = "std")]
+use std::fs::OpenOptions;
+#[cfg(feature = "std")]
+use std::io::{self, BufRead, Read, Write};
+#[cfg(feature = "std")]
+use std::iter::{Iterator, Take};
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "std")]
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+#[cfg(feature = "std")]
+use std::sync::{mpsc, Mutex};
+#[cfg(feature = "std")]
+use std::thread;
+#[cfg(feature = "std")]
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
-#[cfg(test)]
+mod clock;
+#[cfg(all(test, feature = "std"))]
 mod tests;
 
+pub use clock::*;
+
 /// Every step of the underlying iterator, one of these is generated. It contains all the
 /// information of how this iterator is progresing. Use the methods to access data on it.
-#[derive(Debug)]
-pub struct ProgressRecord {
-    /// How many elements before this
-    num: usize,
+///
+/// The times are `T`, the `Instant` of the `Clock` the iterator uses, which is
+/// `std::time::Instant` unless `ProgressRecorderIter::with_clock(...)` was used.
+///
+/// With the `serde` feature, it's `Serialize`, as the main numbers: `num`, `total`, `fraction`,
+/// `percent`, `rate`, `eta_secs` & `elapsed_secs`. Durations are in seconds, and unknown values
+/// are `null`.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(into = "SerializedRecord", bound = "T: Copy")
+)]
+pub struct ProgressRecord<#[cfg(feature = "std")] T = Instant, #[cfg(not(feature = "std"))] T> {
+    /// How many elements before this. Stored as a `u64` so that it can't overflow on 32-bit
+    /// platforms.
+    num: u64,
 
     /// How long since we started iterating.
     iterating_for: Duration,
 
+    /// When this record was generated
+    tm: T,
+
     /// Value of underlying iterator's `.size_hint()`
     size_hint: (usize, Option<usize>),
 
-    /// If `.assumed_size(...)` was set on `ProgressableIter`, return that.
-    assumed_size: Option<usize>,
+    /// If `.assumed_size(...)` was set on `ProgressableIter`, return that. A `u64`, so byte
+    /// totals from a `ProgressReader` fit on 32-bit platforms.
+    assumed_size: Option<u64>,
 
     /// If we have overridden the calculated fraction
     assumed_fraction: Option<f64>,
 
     /// The timestamp of when the previous record was created. Will be None if this is first.
-    previous_record_tm: Option<Instant>,
+    previous_record_tm: Option<T>,
+
+    /// How long it's been since the previous record, if there was one
+    since_previous: Option<Duration>,
 
     /// When the iteration started
-    started_iterating: Instant,
+    started_iterating: T,
 
     /// The rolling average duration, if calculated
     rolling_average_duration: Option<Duration>,
 
     /// The exponential average duration, if calculated
     exp_average_duration: Option<Duration>,
+
+    /// The linearly weighted rolling average duration, if calculated
+    weighted_rolling_average_duration: Option<Duration>,
+
+    /// The rate over the last `.with_windowed_rate(...)`, if calculated
+    windowed_rate: Option<f64>,
+
+    /// How the `.eta()` should be stabilized
+    eta_settings: EtaSettings,
+
+    /// The `.eta()` of the previous record, if there was one
+    previous_eta: Option<Duration>,
+
+    /// Total weight of all items so far, if weighted with `.with_weights(...)`
+    weight_done: f64,
+
+    /// Total weight of all the items, if weighted with `.with_weights(...)`
+    total_weight: Option<f64>,
+
+    /// The ETA from a model, e.g. `.with_eta_from_weighted_regression(...)` or
+    /// `.with_cost_model(...)`, if known
+    model_eta: Option<Duration>,
+
+    /// How long the iteration should take at most, from `.with_time_budget(...)`
+    time_budget: Option<Duration>,
+
+    /// The total to show to people, from `.with_display_total(...)`
+    display_total: Option<usize>,
+
+    /// The wall clock time when the iteration started
+    #[cfg(feature = "std")]
+    started_wall: SystemTime,
+
+    /// How `.human_eta()` is shown
+    #[cfg(feature = "std")]
+    eta_display: EtaDisplay,
+
+    /// The `.rate()` smoothed by `.with_display_damping(...)`, if enabled
+    damped_rate: Option<f64>,
+
+    /// How many units each item is, from `.with_count_multiplier(...)`
+    count_multiplier: u64,
+
+    /// How much `num` has gone up by since the previous record
+    num_since_previous: u64,
+
+    /// Total time spent idle, from `.with_idle_threshold(...)`
+    idle_time: Duration,
+
+    /// How many items were `Err`, from `.with_error_counting()`
+    num_errors: u64,
+
+    /// The unit for `.rate_best_unit()`, if chosen by `.with_auto_rate_unit()`
+    rate_unit: Option<RateUnit>,
+
+    /// Never return infinite or NaN numbers, from `.with_safe_math()`
+    safe_math: bool,
+
+    /// How many items are in a buffer, and its capacity, from `.set_buffer_depth(...)`
+    buffer_depth: Option<(usize, usize)>,
+
+    /// Number of bytes so far, if counted with `.with_bytes(...)` or `progress_lines(...)`
+    bytes_done: Option<u64>,
+
+    /// Total number of bytes, if known, from `.with_bytes(...)`
+    byte_total: Option<u64>,
+
+    /// How much weight `.smoothed_rate()` gives the averages rather than the `.rate()`, if
+    /// `.with_smoothing_bootstrap()` is used
+    smoothing_weight: Option<f64>,
 }
 
-impl ProgressRecord {
+/// What a `ProgressRecord` is serialized as, with the `serde` feature
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct SerializedRecord {
+    num: u64,
+    total: Option<u64>,
+    fraction: Option<f64>,
+    percent: Option<f64>,
+    rate: f64,
+    eta_secs: Option<f64>,
+    elapsed_secs: f64,
+}
+
+#[cfg(feature = "serde")]
+impl<T: Copy> From<ProgressRecord<T>> for SerializedRecord {
+    fn from(record: ProgressRecord<T>) -> SerializedRecord {
+        let fields = record.fields();
+        SerializedRecord {
+            num: fields.num_done,
+            total: fields.total,
+            fraction: fields.fraction,
+            percent: fields.percent,
+            rate: fields.rate,
+            eta_secs: fields.eta.map(|eta| eta.as_secs_f64()),
+            elapsed_secs: fields.elapsed.as_secs_f64(),
+        }
+    }
+}
+
+/// How the ETA is shown by `ProgressRecord::human_eta()`. Set with `.with_eta_display(...)`
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EtaDisplay {
+    /// How long is left, e.g. `3m 12s`
+    #[default]
+    Remaining,
+
+    /// The (UTC) time of day it should finish at, e.g. `14:32:01`
+    FinishAt,
+
+    /// Both, e.g. `3m 12s (14:32:01)`
+    Both,
+}
+
+/// Where the iterator's own messages (e.g. the `.with_start_message(...)`, and the
+/// `.with_summary_if_longer_than(...)` & `.with_interrupt_summary()` summaries) are written. Set
+/// with `.with_output(...)`.
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub enum Output {
+    /// Standard output, the default
+    #[default]
+    Stdout,
+
+    /// Standard error
+    Stderr,
+
+    /// Any other `Write`, e.g. a file or a buffer
+    Writer(Box<dyn Write + Send + Sync>),
+
+    /// Nowhere, e.g. to turn off all output in tests or CI
+    Sink,
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Debug for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Output::Stdout => write!(f, "Stdout"),
+            Output::Stderr => write!(f, "Stderr"),
+            Output::Writer(_) => write!(f, "Writer(..)"),
+            Output::Sink => write!(f, "Sink"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stdout => io::stdout().write(buf),
+            Output::Stderr => io::stderr().write(buf),
+            Output::Writer(w) => w.write(buf),
+            Output::Sink => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout => io::stdout().flush(),
+            Output::Stderr => io::stderr().flush(),
+            Output::Writer(w) => w.flush(),
+            Output::Sink => Ok(()),
+        }
+    }
+}
+
+/// The time unit a rate is shown in, from `ProgressRecord::rate_best_unit()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateUnit {
+    /// Items per second
+    PerSecond,
+
+    /// Items per minute
+    PerMinute,
+
+    /// Items per hour
+    PerHour,
+}
+
+/// How far past the boundary between 2 units the rate must be before `.with_auto_rate_unit()`
+/// switches unit
+const RATE_UNIT_HYSTERESIS: f64 = 2.;
+
+impl RateUnit {
+    /// How many seconds are in this unit
+    pub fn secs(&self) -> f64 {
+        match self {
+            RateUnit::PerSecond => 1.,
+            RateUnit::PerMinute => 60.,
+            RateUnit::PerHour => 3600.,
+        }
+    }
+
+    /// The suffix to show after the number, e.g. `/s`
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            RateUnit::PerSecond => "/s",
+            RateUnit::PerMinute => "/min",
+            RateUnit::PerHour => "/h",
+        }
+    }
+
+    /// The unit for a rate of `rate` items per second, where it's at least 1 (unless it's less
+    /// than 1 per hour). If the `previous` unit was something else, it's kept unless the rate is
+    /// `RATE_UNIT_HYSTERESIS` times past the boundary.
+    fn choose(rate: f64, previous: Option<RateUnit>) -> RateUnit {
+        if let Some(previous) = previous {
+            let value = rate * previous.secs();
+            let too_low = previous != RateUnit::PerHour && value < 1. / RATE_UNIT_HYSTERESIS;
+            let too_high = previous != RateUnit::PerSecond && value >= 60. * RATE_UNIT_HYSTERESIS;
+            if !(too_low || too_high) {
+                return previous;
+            }
+        }
+        if rate >= 1. {
+            RateUnit::PerSecond
+        } else if rate * 60. >= 1. {
+            RateUnit::PerMinute
+        } else {
+            RateUnit::PerHour
+        }
+    }
+}
+
+/// Whether the recent rate is faster or slower than the overall rate, from
+/// `ProgressRecord::throughput_trend(...)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trend {
+    /// Recently going faster than the overall rate
+    Increasing,
+
+    /// Recently going at about the overall rate
+    Steady,
+
+    /// Recently going slower than the overall rate
+    Decreasing,
+}
+
+/// All the metrics of a `ProgressRecord`, from `.fields()`
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct ProgressFields {
+    /// `.num_done()`
+    pub num_done: u64,
+
+    /// Total number of items, if known
+    pub total: Option<u64>,
+
+    /// `.fraction()`
+    pub fraction: Option<f64>,
+
+    /// `.percent()`
+    pub percent: Option<f64>,
+
+    /// `.rate()`
+    pub rate: f64,
+
+    /// `.smoothed_rate()`
+    pub smoothed_rate: Option<f64>,
+
+    /// `.eta()`
+    pub eta: Option<Duration>,
+
+    /// `.duration_since_start()`
+    pub elapsed: Duration,
+}
+
+/// The main numbers of a `ProgressRecord` as plain data, e.g. to send to other threads, or to
+/// combine several with `aggregate_progress(...)`.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressSnapshot {
+    /// Number of items done
+    pub num_done: u64,
+
+    /// Total number of items, if known
+    pub total: Option<u64>,
+
+    /// When the iteration started
+    pub started_iterating: Instant,
+
+    /// How long it had been iterating for when this was taken
+    pub elapsed: Duration,
+}
+
+#[cfg(feature = "std")]
+impl ProgressSnapshot {
+    /// How far through, as a fraction, if the total is known
+    pub fn fraction(&self) -> Option<f64> {
+        self.total.map(|total| self.num_done as f64 / total as f64)
+    }
+
+    /// Percentage progress, if the total is known
+    pub fn percent(&self) -> Option<f64> {
+        self.fraction().map(|f| f * 100.)
+    }
+
+    /// Number of items per second
+    pub fn rate(&self) -> f64 {
+        self.num_done as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// The combined rate of several concurrent jobs, i.e. the sum of each one's `.rate()`.
+#[cfg(feature = "std")]
+pub fn aggregate_rate(snapshots: &[ProgressSnapshot]) -> f64 {
+    snapshots.iter().map(|s| s.rate()).sum()
+}
+
+/// Combine several snapshots (e.g. of jobs running in parallel) into one. The `num_done` and
+/// `total` are summed (the `total` is `None` if any are unknown), the `started_iterating` is the
+/// earliest start, and the `elapsed` runs from then to the latest snapshot. So the `.rate()` of
+/// the result is the overall rate since the first job started, which can be lower than
+/// `aggregate_rate(...)` if the jobs didn't all start together. `None` if there are no snapshots.
+///
+/// ```
+/// # use iter_progress::{aggregate_progress, ProgressableIter};
+/// let (a, _) = (0..10).progress().next().unwrap();
+/// let (b, _) = (0..30).progress().next().unwrap();
+/// let combined = aggregate_progress(&[a.snapshot(), b.snapshot()]).unwrap();
+/// assert_eq!(combined.num_done, 2);
+/// assert_eq!(combined.total, Some(40));
+/// ```
+#[cfg(feature = "std")]
+pub fn aggregate_progress(snapshots: &[ProgressSnapshot]) -> Option<ProgressSnapshot> {
+    let started_iterating = snapshots.iter().map(|s| s.started_iterating).min()?;
+    let finished = snapshots
+        .iter()
+        .map(|s| s.started_iterating + s.elapsed)
+        .max()?;
+    Some(ProgressSnapshot {
+        num_done: snapshots.iter().map(|s| s.num_done).sum(),
+        total: snapshots.iter().map(|s| s.total).sum(),
+        started_iterating,
+        elapsed: finished - started_iterating,
+    })
+}
+
+/// The `pct` percentile (from 0 to 100, clamped) of the `sorted` values, interpolating between
+/// the nearest values. `sorted` mustn't be empty.
+fn percentile(sorted: &[f64], pct: f64) -> f64 {
+    let rank = (pct.clamp(0., 100.) / 100.) * (sorted.len() - 1) as f64;
+    // `floor()` & `ceil()` need `std`, but `rank` isn't negative, so `as` rounds it down
+    let below = rank as usize;
+    let above = (below + 1).min(sorted.len() - 1);
+    sorted[below] + (sorted[above] - sorted[below]) * (rank - below as f64)
+}
+
+/// A number as JSON, which has no NaN or infinity, so they (and `None`) are `null`
+fn json_f64(value: Option<f64>) -> String {
+    match value {
+        Some(value) if value.is_finite() => value.to_string(),
+        _ => "null".to_string(),
+    }
+}
+
+/// Format a duration like `1h 2m 3s`, leaving out leading zero units, or `<1s`.
+fn human_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, mins, secs) = (secs / 3600, (secs / 60) % 60, secs % 60);
+    if hours > 0 {
+        format!("{}h {}m {}s", hours, mins, secs)
+    } else if mins > 0 {
+        format!("{}m {}s", mins, secs)
+    } else if secs > 0 {
+        format!("{}s", secs)
+    } else {
+        "<1s".to_string()
+    }
+}
+
+/// Format a number of bytes with binary units, like `512 B`, `1.5 KiB` or `3.2 GiB`, using the
+/// largest unit where it's at least 1.
+fn human_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+    if !bytes.is_finite() || bytes < 1024. {
+        return format!("{:.0} B", bytes);
+    }
+    let mut value = bytes;
+    let mut unit = UNITS[0];
+    for &next_unit in UNITS.iter() {
+        if value < 1024. {
+            break;
+        }
+        value /= 1024.;
+        unit = next_unit;
+    }
+    format!("{:.1} {}", value, unit)
+}
+
+/// Format the (UTC) time of day, like `14:32:01`
+#[cfg(feature = "std")]
+fn format_time_of_day(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        % (24 * 60 * 60);
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60
+    )
+}
+
+/// A bar `width` characters wide, with the `fraction` (clamped to `0.0..=1.0`) filled in with `#`,
+/// and the rest `-`.
+fn render_bar(fraction: f64, width: usize) -> String {
+    // `as` rounds down, so add 0.5 to round to the nearest (`round()` needs `std`)
+    let filled = (fraction.clamp(0., 1.) * width as f64 + 0.5) as usize;
+    let mut bar = "#".repeat(filled);
+    bar.push_str(&"-".repeat(width - filled));
+    bar
+}
+
+/// Send a `tracing` event at `level` for `state`, for `.trace_every_n_sec(...)`
+#[cfg(feature = "tracing")]
+fn trace_progress<T: Copy>(level: tracing::Level, state: &ProgressRecord<T>) {
+    // The level of an event has to be a constant
+    macro_rules! event_at {
+        ($level:expr) => {
+            tracing::event!(
+                $level,
+                percent = state.percent(),
+                rate = state.rate(),
+                eta_secs = state.eta().map(|eta| eta.as_secs_f64()),
+                num = state.num_done_u64(),
+                "progress"
+            )
+        };
+    }
+    match level {
+        tracing::Level::ERROR => event_at!(tracing::Level::ERROR),
+        tracing::Level::WARN => event_at!(tracing::Level::WARN),
+        tracing::Level::INFO => event_at!(tracing::Level::INFO),
+        tracing::Level::DEBUG => event_at!(tracing::Level::DEBUG),
+        tracing::Level::TRACE => event_at!(tracing::Level::TRACE),
+    }
+}
+
+/// Set when there has been a SIGINT (Ctrl-C), after `.with_interrupt_summary()`
+#[cfg(feature = "std")]
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Only sets a flag, since little else is safe to do in a signal handler
+#[cfg(all(feature = "interrupt", unix))]
+extern "C" fn on_sigint(_signum: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// How many iterators are catching SIGINT, and the action from before the first one did
+#[cfg(all(feature = "interrupt", unix))]
+static SIGINT_HANDLER: Mutex<(usize, Option<libc::sigaction>)> = Mutex::new((0, None));
+
+/// Catch SIGINT, so that `INTERRUPTED` is set, rather than the process being stopped, until the
+/// returned guard is dropped.
+#[cfg(all(feature = "interrupt", unix))]
+fn install_sigint_handler() -> SigintGuard {
+    let mut handler = SIGINT_HANDLER.lock().unwrap_or_else(|err| err.into_inner());
+    let (users, previous) = &mut *handler;
+    if *users == 0 {
+        // Safe because both structs are initialised before they're used, and the handler only
+        // touches an atomic
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            let mut old_action: libc::sigaction = std::mem::zeroed();
+            // If it can't be caught, Ctrl-C works as before, and there's nothing to put back
+            *previous = if libc::sigaction(libc::SIGINT, &action, &mut old_action) == 0 {
+                Some(old_action)
+            } else {
+                None
+            };
+        }
+    }
+    *users += 1;
+    SigintGuard(())
+}
+
+/// Puts the SIGINT action from before `install_sigint_handler()` back when dropped, once no
+/// other iterator is catching SIGINT.
+#[cfg(all(feature = "interrupt", unix))]
+#[derive(Debug)]
+struct SigintGuard(());
+
+#[cfg(all(feature = "interrupt", unix))]
+impl Drop for SigintGuard {
+    fn drop(&mut self) {
+        let mut handler = SIGINT_HANDLER.lock().unwrap_or_else(|err| err.into_inner());
+        let (users, previous) = &mut *handler;
+        *users -= 1;
+        if *users == 0 {
+            if let Some(previous) = previous.take() {
+                // Safe because it's the action which was there before
+                unsafe {
+                    libc::sigaction(libc::SIGINT, &previous, std::ptr::null_mut());
+                }
+            }
+        }
+    }
+}
+
+/// Now that we've caught SIGINT, pass it on to the action from before, which by default stops
+/// the process. If that returns (e.g. it was ignored, or the program's own handler), SIGINT is
+/// caught again.
+#[cfg(all(feature = "interrupt", unix))]
+fn pass_on_sigint() {
+    let handler = SIGINT_HANDLER.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some(previous) = &handler.1 {
+        // Safe because these are the actions from `install_sigint_handler()`
+        unsafe {
+            let mut ours: libc::sigaction = std::mem::zeroed();
+            libc::sigaction(libc::SIGINT, previous, &mut ours);
+            libc::raise(libc::SIGINT);
+            libc::sigaction(libc::SIGINT, &ours, std::ptr::null_mut());
+        }
+    }
+}
+
+/// The width of the terminal, if it can be found.
+#[cfg(feature = "std")]
+fn terminal_width() -> Option<usize> {
+    #[cfg(all(feature = "terminal", unix))]
+    {
+        if let Some(width) = ioctl_terminal_width() {
+            return Some(width);
+        }
+    }
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.trim().parse().ok())
+        .filter(|&columns| columns > 0)
+}
+
+/// Ask the terminal on stdout how wide it is.
+#[cfg(all(feature = "terminal", unix))]
+fn ioctl_terminal_width() -> Option<usize> {
+    // Safe because `size` is a `winsize`, which `TIOCGWINSZ` fills in, and lives for the call
+    let size = unsafe {
+        let mut size: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) != 0 {
+            return None;
+        }
+        size
+    };
+    if size.ws_col > 0 {
+        Some(size.ws_col as usize)
+    } else {
+        None
+    }
+}
+
+/// Expected cost of each item, from `.with_cost_model(...)`
+struct CostModel {
+    cost: Box<dyn Fn(usize) -> f64 + Send + Sync>,
+    /// How many items' costs are in `cost_done`
+    num_costed: u64,
+    cost_done: f64,
+    /// How many items' costs are in `total_cost`, i.e. the total number of items last time
+    num_in_total: u64,
+    /// The cost of all items, updated as the total changes
+    total_cost: f64,
+}
+
+impl CostModel {
+    /// The ETA when `num` items have been done in `elapsed`, out of `total`
+    fn eta(&mut self, num: u64, total: Option<u64>, elapsed: Duration) -> Option<Duration> {
+        while self.num_costed < num {
+            self.cost_done += (self.cost)(self.num_costed as usize);
+            self.num_costed += 1;
+        }
+        let total = total?;
+        // Only the items added to (or taken off) the total since last time, so a growing total
+        // (e.g. `.add_discovered(...)`) doesn't mean summing every item for every record
+        while self.num_in_total < total {
+            self.total_cost += (self.cost)(self.num_in_total as usize);
+            self.num_in_total += 1;
+        }
+        while self.num_in_total > total {
+            self.num_in_total -= 1;
+            self.total_cost -= (self.cost)(self.num_in_total as usize);
+        }
+        if self.cost_done <= 0. {
+            return None;
+        }
+        let secs_per_cost = elapsed.as_secs_f64() / self.cost_done;
+        Some(Duration::from_secs_f64(
+            ((self.total_cost - self.cost_done) * secs_per_cost).max(0.),
+        ))
+    }
+}
+
+/// Settings to apply to several iterators at once, with `.progress_with_config(...)`. Each is
+/// the same as the builder method of the same name, and with the `Default`, there is a record
+/// for every item, like `.progress()`.
+///
+/// ```
+/// # use iter_progress::{ProgressableIter, ProgressConfig};
+/// let config = ProgressConfig {
+///     rolling_average: Some(10),
+///     ..ProgressConfig::default()
+/// };
+/// let mut progressor = (0..100).progress_with_config(config);
+/// let (state, _) = progressor.next().unwrap();
+/// assert!(state.is_some());
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ProgressConfig {
+    /// `.with_rolling_average(...)`
+    pub rolling_average: Option<usize>,
+
+    /// `.with_exp_average(...)`
+    pub exp_average: Option<f64>,
+
+    /// `.assume_size(...)`
+    pub assumed_size: Option<usize>,
+
+    /// `.with_min_update_interval(...)`
+    pub min_update_interval: Option<Duration>,
+}
+
+/// Settings to make the `.eta()` less jumpy. See `.with_stable_eta()`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct EtaSettings {
+    /// Don't return an ETA until this many items have been seen
+    min_samples: u64,
+
+    /// ETAs longer than this are treated as unknown
+    cap: Option<Duration>,
+
+    /// If the gap since the previous record is longer than this, keep the previous ETA
+    hold_on_stall: Option<Duration>,
+
+    /// Round the ETA to the nearest multiple of this
+    granularity: Option<Duration>,
+
+    /// Count the ETA down from the previous one, see `.with_monotonic_eta_countdown()`
+    monotonic_countdown: bool,
+}
+
+/// With `.with_monotonic_eta_countdown()`, how far (as a fraction) the ETA must be from the
+/// countdown from the previous ETA, before it's used instead
+const MONOTONIC_ETA_TOLERANCE: f64 = 0.1;
+
+impl<T: Copy> ProgressRecord<T> {
+    /// A record for `num` items at `now` by the `clock`, with nothing else known.
+    fn new<C: Clock<Instant = T>>(
+        num: u64,
+        clock: &C,
+        started_iterating: T,
+        previous_record_tm: Option<T>,
+        now: T,
+    ) -> ProgressRecord<T> {
+        ProgressRecord {
+            num,
+            iterating_for: clock.duration_between(started_iterating, now),
+            tm: now,
+            size_hint: (0, None),
+            assumed_size: None,
+            assumed_fraction: None,
+            previous_record_tm,
+            since_previous: previous_record_tm
+                .map(|previous_tm| clock.duration_between(previous_tm, now)),
+            started_iterating,
+            rolling_average_duration: None,
+            exp_average_duration: None,
+            weighted_rolling_average_duration: None,
+            windowed_rate: None,
+            eta_settings: EtaSettings::default(),
+            previous_eta: None,
+            weight_done: 0.,
+            total_weight: None,
+            model_eta: None,
+            time_budget: None,
+            display_total: None,
+            #[cfg(feature = "std")]
+            started_wall: SystemTime::now(),
+            #[cfg(feature = "std")]
+            eta_display: EtaDisplay::default(),
+            damped_rate: None,
+            count_multiplier: 1,
+            num_since_previous: 0,
+            idle_time: Duration::ZERO,
+            smoothing_weight: None,
+            num_errors: 0,
+            rate_unit: None,
+            safe_math: false,
+            buffer_depth: None,
+            bytes_done: None,
+            byte_total: None,
+        }
+    }
+
     /// Duration since iteration started
     pub fn duration_since_start(&self) -> Duration {
         self.iterating_for
     }
 
+    /// The `.duration_since_start()`, shown like `1h 2m 3s`, `45s` or `<1s`, the same way as
+    /// `.human_eta()`.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..10).progress();
+    /// let (state, _) = progressor.next().unwrap();
+    /// println!("Running for {}", state.elapsed_human());
+    /// # }
+    /// ```
+    pub fn elapsed_human(&self) -> String {
+        human_duration(self.duration_since_start())
+    }
+
     /// Number of items we've generated so far. Will be 0 for the first element
     ///
     /// ```rust
+    /// # #[cfg(feature = "std")] {
     /// # use iter_progress::ProgressableIter;
     /// let mut progressor = (0..1_000).progress();
     /// let (state, num) = progressor.next().unwrap();
     /// assert_eq!(state.num_done(), 1);
+    /// # }
     /// ```
     ///
     ///
     /// ```rust
+    /// # #[cfg(feature = "std")] {
     /// # use iter_progress::ProgressableIter;
     /// let mut progressor = (0..1_000).progress().skip(10);
     /// let (state, num) = progressor.next().unwrap();
     /// assert_eq!(state.num_done(), 11);
+    /// # }
     /// ```
+    ///
+    /// On 32-bit platforms, `usize` can only count up to ~4 billion items. If you expect to
+    /// process more than that, use `.num_done_u64()`. In debug builds this will panic when the
+    /// count doesn't fit in a `usize`.
     pub fn num_done(&self) -> usize {
+        debug_assert!(
+            self.num <= usize::MAX as u64,
+            "num_done() overflows usize, use num_done_u64()"
+        );
+        self.num as usize
+    }
+
+    /// Number of items we've generated so far, as a `u64`. This will not overflow on 32-bit
+    /// platforms, unlike `.num_done()`.
+    ///
+    /// ```rust
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..1_000).progress();
+    /// let (state, num) = progressor.next().unwrap();
+    /// assert_eq!(state.num_done_u64(), 1);
+    /// # }
+    /// ```
+    pub fn num_done_u64(&self) -> u64 {
         self.num
     }
 
+    /// Whether this is the first record, i.e. there is no `.previous_record_tm()`, e.g. to print
+    /// a header. With `.optional_progress(n)`, the first record isn't for the first item.
+    pub fn is_first(&self) -> bool {
+        self.previous_record_tm.is_none()
+    }
+
+    /// Whether this is for the last item, i.e. the `.num_done()` is the total, e.g. to print a
+    /// summary. `None` if the total isn't known.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// for (state, _) in (0..3).progress() {
+    ///     if state.is_first() {
+    ///         println!("Starting");
+    ///     }
+    ///     if state.is_last() == Some(true) {
+    ///         println!("Done {} items", state.num_done());
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn is_last(&self) -> Option<bool> {
+        self.resolved_total()
+            .map(|total| self.num_done_u64() == total)
+    }
+
+    /// How many of the items so far were `Err`, if `.with_error_counting()` was used. 0 otherwise.
+    /// They are also counted in `.num_done()`.
+    pub fn num_errors(&self) -> u64 {
+        self.num_errors
+    }
+
+    /// The fraction (from 0 to 1) of the items so far which were `Err`, if
+    /// `.with_error_counting()` was used.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let results = vec![Ok(1), Err("bad"), Ok(2), Ok(3)];
+    /// let (state, _) = results.into_iter().progress().with_error_counting().last().unwrap();
+    /// println!("processed {} ({} errors, {:.2}%)", state.num_done(), state.num_errors(), state.error_rate() * 100.);
+    /// assert_eq!(state.num_errors(), 1);
+    /// assert_eq!(state.error_rate(), 0.25);
+    /// # }
+    /// ```
+    pub fn error_rate(&self) -> f64 {
+        let items = self.num / self.count_multiplier;
+        if items == 0 {
+            return 0.;
+        }
+        self.num_errors as f64 / items as f64
+    }
+
     /// The `Instant` for when the previous record was generated. None if there was no previous
     /// record.
     ///
     /// This can be useful for calculating fine-grained rates
-    pub fn previous_record_tm(&self) -> Option<Instant> {
+    pub fn previous_record_tm(&self) -> Option<T> {
         self.previous_record_tm
     }
 
     /// Return the time `Instant` that this iterator started
-    pub fn started_iterating(&self) -> Instant {
+    pub fn started_iterating(&self) -> T {
         self.started_iterating
     }
 
-    /// Number of items per second, calculated from the start
-    pub fn rate(&self) -> f64 {
-        // number of items per second
-        (self.num_done() as f64) / self.duration_since_start().as_secs_f64()
+    /// The time `Instant` this record was generated at, e.g. to match it up with other log
+    /// lines, or for the exact time since the `.previous_record_tm()`.
+    pub fn timestamp(&self) -> T {
+        self.tm
     }
 
-    /// How far through the iterator as a fraction, if known.
-    /// First looks at the `assumed_fraction` if you have overridden that.
-    /// Uses the underlying iterator's `.size_hint()` method if that is an exact value, falling
-    /// back to any assumed size (set with `.assume_size(...)`). Otherwise returns `None`.
+    /// Whether it took longer than `threshold` to get from the previous record to this one, e.g.
+    /// a network read hanging. Always `false` for the first record.
     ///
     /// ```
-    /// use iter_progress::ProgressableIter;
-    /// let mut progressor = (0..1_000).progress().skip(120);
-    /// let (state, num) = progressor.next().unwrap();
-    /// assert_eq!(num, 120);
-    /// assert_eq!(state.fraction(), Some(0.121));
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// use std::time::Duration;
+    /// for (state, _) in (0..10).progress() {
+    ///     if state.stalled(Duration::from_secs(30)) {
+    ///         eprintln!("Item {} took a long time", state.num_done());
+    ///     }
+    /// }
+    /// # }
     /// ```
+    pub fn stalled(&self, threshold: Duration) -> bool {
+        self.since_previous.is_some_and(|gap| gap > threshold)
+    }
+
+    /// The underlying iterator's `.size_hint()` after this item, i.e. for the items still to
+    /// come. This is what the `.fraction()` uses if it's exact (`.1 == Some(.0)`), and is
+    /// returned as is, so you can tell an exact hint from a lower bound.
     ///
-    /// Returns `None` if we cannot know, e.g. for an infinite iterator
     /// ```
+    /// # #[cfg(feature = "std")] {
     /// # use iter_progress::ProgressableIter;
-    /// let mut progressor = (0..).progress().skip(120);
-    /// let (state, num) = progressor.next().unwrap();
-    /// assert_eq!(state.fraction(), None);
+    /// let mut progressor = (0..10).progress();
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.size_hint(), (9, Some(9)));
+    ///
+    /// let mut progressor = (0..10).filter(|i| i % 2 == 0).progress();
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.size_hint(), (0, Some(9)));
+    /// # }
     /// ```
-    pub fn fraction(&self) -> Option<f64> {
-        if self.assumed_fraction.is_some() {
-            return self.assumed_fraction;
-        }
+    pub fn size_hint(&self) -> (usize, Option<usize>) {
+        self.size_hint
+    }
 
-        let total = if self.size_hint.1 == Some(self.size_hint.0) {
-            // use that directly
-            Some(self.size_hint.0 + self.num_done())
-        } else if self.assumed_size.is_some() {
-            self.assumed_size
+    /// Number of items per second, calculated from the start.
+    ///
+    /// If `.with_display_damping(...)` was set, this is smoothed, so it's easier to read.
+    pub fn rate(&self) -> f64 {
+        let rate = self.damped_rate.unwrap_or_else(|| self.raw_rate());
+        if self.safe_math && !rate.is_finite() {
+            0.
         } else {
-            None
-        };
-
-        match total {
-            None => None,
-            Some(total) => {
-                let done = self.num_done();
-                Some((done as f64) / (total as f64))
-            }
+            rate
         }
     }
 
+    /// Number of items per second since the previous record, which reacts straight away when the
+    /// speed changes, unlike `.rate()`. This counts all the items since the previous record, e.g.
+    /// `n` with `.optional_progress(n)`, or the ones skipped by `.nth(...)`. `None` for the first
+    /// record, or if no time has passed.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..10).progress();
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.rate_recent(), None);
+    /// let (state, _) = progressor.next().unwrap();
+    /// println!("{:?} items per sec", state.rate_recent());
+    /// # }
+    /// ```
+    pub fn rate_recent(&self) -> Option<f64> {
+        self.num_per_sec_since_previous()
+            .filter(|rate| rate.is_finite())
+    }
+
+    /// The items since the previous record, per second of the time from the
+    /// `.previous_record_tm()` to this record's `.timestamp()`, so it's always there after the
+    /// first record, without any settings (unlike `.rolling_average_rate()`). `None` for the
+    /// first record. If no time has passed, it's infinite (or `NaN`), unless
+    /// `.with_safe_math()` is used. `.rate_recent()` is the same, but `None` then.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// for (state, _) in (0..10).progress() {
+    ///     if let Some(recent) = state.num_per_sec_since_previous() {
+    ///         println!("{:.1} per sec now, {:.1} per sec overall", recent, state.rate());
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn num_per_sec_since_previous(&self) -> Option<f64> {
+        let gap = self.since_previous?;
+        Some(self.num_since_previous as f64 / gap.as_secs_f64()).filter(|&rate| self.allowed(rate))
+    }
+
+    /// Whether `value` may be returned, i.e. it's finite, or `.with_safe_math()` isn't used
+    fn allowed(&self, value: f64) -> bool {
+        !self.safe_math || value.is_finite()
+    }
+
+    /// The `.rate()` in the unit which makes it easiest to read, i.e. per second, unless that's
+    /// less than 1, then per minute, or per hour. Returns the rate in that unit, and the unit.
+    ///
+    /// With `.with_auto_rate_unit()`, the unit is only changed when the rate is well past the
+    /// boundary (twice, or half, the rate where it would change), so it doesn't flip back & forth
+    /// when the rate is near one.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..10).progress().with_auto_rate_unit();
+    /// let (state, _) = progressor.next().unwrap();
+    /// let (rate, unit) = state.rate_best_unit();
+    /// println!("{:.1}{}", rate, unit.suffix());
+    /// # }
+    /// ```
+    pub fn rate_best_unit(&self) -> (f64, RateUnit) {
+        let rate = self.rate();
+        let unit = self
+            .rate_unit
+            .unwrap_or_else(|| RateUnit::choose(rate, None));
+        (rate * unit.secs(), unit)
+    }
+
+    /// Number of items per second, calculated from the start, without any damping
+    fn raw_rate(&self) -> f64 {
+        // number of items per second
+        (self.num_done_u64() as f64) / self.active_duration().as_secs_f64()
+    }
+
+    /// A confidence interval for the rate (since the start), treating the items as arriving
+    /// randomly (Poisson), using the normal approximation: `rate ± z × √num_done / elapsed`.
+    /// e.g. `z = 1.96` for 95%. The lower bound is at least 0. `None` if no time has passed.
+    ///
+    /// ```
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..1_000).progress();
+    /// let (state, _) = progressor.nth(99).unwrap();
+    /// let (low, high) = state.rate_ci(1.96).unwrap();
+    /// assert!(low <= state.rate() && state.rate() <= high);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn rate_ci(&self, z: f64) -> Option<(f64, f64)> {
+        let elapsed = self.active_duration().as_secs_f64();
+        if elapsed == 0. {
+            return None;
+        }
+        let count = self.num_done_u64() as f64;
+        let rate = count / elapsed;
+        let margin = z * count.sqrt() / elapsed;
+        Some(((rate - margin).max(0.), rate + margin))
+    }
+
+    /// How long we have been iterating for, not counting any `.idle_time()`
+    fn active_duration(&self) -> Duration {
+        self.iterating_for.saturating_sub(self.idle_time)
+    }
+
+    /// How long was spent idle, i.e. the total of all the gaps between items which were longer
+    /// than the `.with_idle_threshold(...)`. This time isn't included in the `.rate()` or
+    /// `.eta()`. Zero if that's not enabled.
+    pub fn idle_time(&self) -> Duration {
+        self.idle_time
+    }
+
+    /// How far through the iterator as a fraction, if known.
+    /// First looks at the `assumed_fraction` if you have overridden that.
+    /// Uses the underlying iterator's `.size_hint()` method if that is an exact value, falling
+    /// back to any assumed size (set with `.assume_size(...)`). Otherwise returns `None`.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..1_000).progress().skip(120);
+    /// let (state, num) = progressor.next().unwrap();
+    /// assert_eq!(num, 120);
+    /// assert_eq!(state.fraction(), Some(0.121));
+    /// # }
+    /// ```
+    ///
+    /// Returns `None` if we cannot know, e.g. for an infinite iterator
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..).progress().skip(120);
+    /// let (state, num) = progressor.next().unwrap();
+    /// assert_eq!(state.fraction(), None);
+    /// # }
+    /// ```
+    pub fn fraction(&self) -> Option<f64> {
+        let fraction = if self.assumed_fraction.is_some() {
+            self.assumed_fraction
+        } else {
+            match self.resolved_total() {
+                None => None,
+                Some(total) => {
+                    let done = self.num_done_u64();
+                    Some((done as f64) / (total as f64))
+                }
+            }
+        };
+        fraction.filter(|&fraction| self.allowed(fraction))
+    }
+
+    /// The total number of items the iterator is expected to yield, i.e. what the `.fraction()`
+    /// is out of, e.g. for "item 42 of 1000". This is from the underlying iterator's
+    /// `.size_hint()` if that is exact, otherwise the `.assume_size(...)`. `None` if neither is
+    /// known. (An `.assume_fraction(...)` doesn't change this.)
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..1_000).progress();
+    /// let (state, _) = progressor.nth(41).unwrap();
+    /// assert_eq!(state.total(), Some(1_000));
+    ///
+    /// let mut progressor = (0..).progress().assume_size(50);
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.total(), Some(50));
+    /// # }
+    /// ```
+    pub fn total(&self) -> Option<usize> {
+        self.resolved_total().map(|total| total as usize)
+    }
+
+    /// The total number of items, from the exact `.size_hint()`, falling back to the assumed
+    /// size. A `u64`, like `.num_done_u64()`, so `.fraction()` works for huge totals on 32-bit
+    /// platforms.
+    fn resolved_total(&self) -> Option<u64> {
+        if self.size_hint.1 == Some(self.size_hint.0) {
+            // use that directly
+            Some(self.size_hint.0 as u64 * self.count_multiplier + self.num_done_u64())
+        } else {
+            self.assumed_size
+        }
+    }
+
+    /// How many items are left, if the total is known.
+    fn items_remaining(&self) -> Option<u64> {
+        self.resolved_total()
+            .map(|total| total.saturating_sub(self.num_done_u64()))
+    }
+
+    /// How many items are left, e.g. for "3,402 items remaining". The total is the same as for
+    /// the `.fraction()`, and if more items than that have been done, it's 0. `None` if the
+    /// total isn't known.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..10).progress();
+    /// let (state, _) = progressor.nth(2).unwrap();
+    /// assert_eq!(state.remaining(), Some(7));
+    /// # }
+    /// ```
+    pub fn remaining(&self) -> Option<usize> {
+        self.items_remaining().map(|remaining| remaining as usize)
+    }
+
+    /// The total number of items to show to people, e.g. in "N of M". This is the
+    /// `.with_display_total(...)` if set, otherwise the actual total, if known. It is not used for
+    /// the `.fraction()`.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..9_876).progress().with_display_total(10_000);
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.display_total(), Some(10_000));
+    /// assert_eq!(state.fraction(), Some(1. / 9_876.));
+    /// # }
+    /// ```
+    pub fn display_total(&self) -> Option<usize> {
+        self.display_total.or_else(|| self.total())
+    }
+
     /// Assume that this is actually at this fraction through
     /// If the underlying Iterator doesn't provide a useful `size_hint`, but you "know" the real
     /// fraction (e.g. if reading from a file), you can override the value for this
     /// `ProgressRecord`. This new value is used for rate & time calculations.
     ///
     /// ```
+    /// # #[cfg(feature = "std")] {
     /// # use iter_progress::ProgressableIter;
     /// let mut progressor = (0..).progress();
     /// let (mut state, _num) = progressor.next().unwrap();
@@ -188,6 +1289,7 @@ impl ProgressRecord {
     /// // Be we know we're 12% the way through
     /// state.assume_fraction(0.12);
     /// assert_eq!(state.fraction(), Some(0.12));
+    /// # }
     /// ```
     pub fn assume_fraction(&mut self, f: impl Into<f64>) {
         self.assumed_fraction = Some(f.into())
@@ -196,26 +1298,71 @@ impl ProgressRecord {
     /// Percentage progress through the iterator, if known.
     ///
     /// ```
+    /// # #[cfg(feature = "std")] {
     /// use iter_progress::ProgressableIter;
     /// let mut progressor = (0..1_000).progress().skip(120);
     /// let (state, num) = progressor.next().unwrap();
     /// assert_eq!(state.percent(), Some(12.1));
+    /// # }
     /// ```
     ///
     /// Returns `None` if we cannot know, e.g. for an infinite iterator
     /// ```
+    /// # #[cfg(feature = "std")] {
     /// # use iter_progress::ProgressableIter;
     /// let mut progressor = (0..).progress().skip(120);
     /// let (state, num) = progressor.next().unwrap();
     /// assert_eq!(state.percent(), None);
+    /// # }
     /// ```
     pub fn percent(&self) -> Option<f64> {
         self.fraction().map(|f| f * 100.)
     }
 
+    /// The `.percent()`, rounded to `decimals` decimal places, e.g. for showing it without
+    /// floating point noise like `12.100000000000001`.
+    ///
+    /// ```
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..3).progress();
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.percent_rounded(1), Some(33.3));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn percent_rounded(&self, decimals: u32) -> Option<f64> {
+        let scale = 10_f64.powi(decimals as i32);
+        self.percent()
+            .map(|percent| (percent * scale).round() / scale)
+    }
+
+    /// The `.fraction()`, but kept within `0.0..=1.0`, e.g. when there are more items than the
+    /// `.assume_size(...)`, so it doesn't show more than 100%.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..).progress().assume_size(2);
+    /// let (state, _) = progressor.nth(2).unwrap();
+    /// assert_eq!(state.fraction(), Some(1.5));
+    /// assert_eq!(state.fraction_clamped(), Some(1.));
+    /// # }
+    /// ```
+    pub fn fraction_clamped(&self) -> Option<f64> {
+        self.fraction().map(|fraction| fraction.clamp(0., 1.))
+    }
+
+    /// The `.percent()`, but kept within `0.0..=100.0`, like `.fraction_clamped()`.
+    pub fn percent_clamped(&self) -> Option<f64> {
+        self.fraction_clamped().map(|f| f * 100.)
+    }
+
     /// Print out `msg`, but only if there has been `n` seconds since last printout. (uses
     /// `print!()`, so newline not included)
-    pub fn print_every_n_sec<T: std::fmt::Display>(&self, n: f32, msg: T) {
+    #[deprecated(
+        note = "use the iterator's `.print_every_n_sec(...)`, which writes to the `.with_output(...)`"
+    )]
+    #[cfg(feature = "std")]
+    pub fn print_every_n_sec<M: std::fmt::Display>(&self, n: f32, msg: M) {
         if self.should_do_every_n_sec(n) {
             print!("{}", msg);
         }
@@ -229,6 +1376,25 @@ impl ProgressRecord {
         }
     }
 
+    /// Like `.do_every_n_sec(...)`, but `f` can change what it captures, e.g. to count how many
+    /// times it was called.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut logged = vec![];
+    /// for (state, _) in (0..150).progress() {
+    ///     state.do_every_n_sec_mut(1., |state| logged.push(state.num_done()));
+    /// }
+    /// # }
+    /// ```
+    pub fn do_every_n_sec_mut<F: FnMut(&Self)>(&self, n: impl Into<f32>, f: F) {
+        let mut f = f;
+        if self.should_do_every_n_sec(n) {
+            f(self);
+        }
+    }
+
     /// If we want to do every `n` sec, should we do it now?
     pub fn should_do_every_n_sec(&self, n: impl Into<f32>) -> bool {
         let n: f32 = n.into();
@@ -237,364 +1403,4352 @@ impl ProgressRecord {
         let secs_since_start: f32 = duration_since_start.as_secs() as f32
             + duration_since_start.subsec_nanos() as f32 / 1_000_000_000.0;
 
-        match self.previous_record_tm() {
+        match self.since_previous {
             None => {
                 // This iteration is the first time, so we should print if more than `n` seconds
                 // have gone past
                 secs_since_start > n
             }
-            Some(last_time) => {
-                let last_time_offset = last_time - self.started_iterating();
+            Some(since_previous) => {
+                let last_time_offset = duration_since_start.saturating_sub(since_previous);
                 let last_time_offset: f32 = last_time_offset.as_secs() as f32
                     + last_time_offset.subsec_nanos() as f32 / 1_000_000_000.0;
 
                 let current_step = secs_since_start / n;
                 let last_step = last_time_offset / n;
 
-                current_step.trunc() > last_step.trunc()
-            }
+                current_step as u64 > last_step as u64
+            }
+        }
+    }
+
+    /// If we want to do every `n` items, should we do it now?
+    ///
+    /// This counts the items from the iterator, even with `.with_count_multiplier(...)`. Always
+    /// `false` if no items have been counted (e.g. with `.with_count_predicate(...)`).
+    pub fn should_do_every_n_items(&self, n: usize) -> bool {
+        match self.num_done_u64() / self.count_multiplier {
+            0 => false,
+            items => (items - 1).is_multiple_of(n as u64),
+        }
+    }
+
+    /// Print out `msg`, but only if there has been `n` items.
+    /// Often you want to print out a debug message every 1,000 items or so. This function does
+    /// that.
+    #[deprecated(
+        note = "use the iterator's `.print_every_n_items(...)`, which writes to the `.with_output(...)`"
+    )]
+    #[cfg(feature = "std")]
+    pub fn print_every_n_items<M: std::fmt::Display>(&self, n: usize, msg: M) {
+        if self.should_do_every_n_items(n) {
+            print!("{}", msg);
+        }
+    }
+
+    /// Do thing but only every `n` items.
+    /// Could be a print statement.
+    ///
+    /// takes 2 arguments, `n` and the function (`f`) which takes a `&ProgressState`. `f` will only
+    /// be called every `n` items that pass through the iterator.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// for (state, _) in (0..150).progress() {
+    ///    state.do_every_n_items(5, |state| {
+    ///        println!("Current progress: {}%", state.percent().unwrap());
+    ///    });
+    /// }
+    /// # }
+    /// ```
+    pub fn do_every_n_items<F: Fn(&Self)>(&self, n: usize, f: F) {
+        if self.should_do_every_n_items(n) {
+            f(self);
+        }
+    }
+
+    /// Like `.do_every_n_items(...)`, but `f` can change what it captures.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut times = 0;
+    /// for (state, _) in (0..150).progress() {
+    ///     state.do_every_n_items_mut(5, |_| times += 1);
+    /// }
+    /// assert_eq!(times, 30);
+    /// # }
+    /// ```
+    pub fn do_every_n_items_mut<F: FnMut(&Self)>(&self, n: usize, f: F) {
+        let mut f = f;
+        if self.should_do_every_n_items(n) {
+            f(self);
+        }
+    }
+
+    /// Rolling average time to process each item this iterator is processing if it is recording
+    /// that. None if it's not being recorded, or it's too soon to know (e.g. for the first item).
+    pub fn rolling_average_duration(&self) -> &Option<Duration> {
+        &self.rolling_average_duration
+    }
+
+    /// Rolling average number of items per second this iterator is processing if it is recording
+    /// that. None if it's not being recorded, or it's too soon to know (e.g. for the first item).
+    pub fn rolling_average_rate(&self) -> Option<f64> {
+        self.rolling_average_duration
+            .map(|d| 1. / d.as_secs_f64())
+            .filter(|&rate| self.allowed(rate))
+    }
+
+    /// Exponential average time to process each item this iterator is processing if it is recording
+    /// that. None if it's not being recorded, or it's too soon to know (e.g. for the first item).
+    pub fn exp_average_duration(&self) -> &Option<Duration> {
+        &self.exp_average_duration
+    }
+
+    /// Exponential average number of items per second this iterator is processing if it is recording
+    /// that. None if it's not being recorded, or it's too soon to know (e.g. for the first item).
+    pub fn exp_average_rate(&self) -> Option<f64> {
+        self.exp_average_duration
+            .map(|d| 1. / d.as_secs_f64())
+            .filter(|&rate| self.allowed(rate))
+    }
+
+    /// Linearly weighted rolling average time to process each item, from
+    /// `.with_weighted_rolling_average(...)`. None if it's not being recorded, or it's too soon
+    /// to know (e.g. for the first item).
+    pub fn weighted_rolling_average_duration(&self) -> &Option<Duration> {
+        &self.weighted_rolling_average_duration
+    }
+
+    /// Linearly weighted rolling average number of items per second, from
+    /// `.with_weighted_rolling_average(...)`. None if it's not being recorded, or it's too soon
+    /// to know (e.g. for the first item).
+    pub fn weighted_rolling_rate(&self) -> Option<f64> {
+        self.weighted_rolling_average_duration
+            .map(|d| 1. / d.as_secs_f64())
+            .filter(|&rate| self.allowed(rate))
+    }
+
+    /// The exponential average rate, or if that's not recorded, the rolling average rate.
+    ///
+    /// With `.with_smoothing_bootstrap()`, this falls back to the since-start `.rate()` until
+    /// the averages have data, and then blends them in. For the rolling average, the average's
+    /// share is how full the window is. For the exponential average, it's `1 - (1 - rate)^n`
+    /// after `n` samples, i.e. how much of the average comes from those samples.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..10).progress().with_rolling_average(5);
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.smoothed_rate(), None);
+    ///
+    /// let mut progressor = (0..10)
+    ///     .progress()
+    ///     .with_rolling_average(5)
+    ///     .with_smoothing_bootstrap();
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert!(state.smoothed_rate().is_some());
+    /// # }
+    /// ```
+    pub fn smoothed_rate(&self) -> Option<f64> {
+        let smoothed = self
+            .exp_average_rate()
+            .or_else(|| self.rolling_average_rate());
+        let weight = match self.smoothing_weight {
+            None => return smoothed,
+            Some(weight) => weight,
+        };
+        let rate = Some(self.raw_rate()).filter(|rate| rate.is_finite());
+        match (smoothed, rate) {
+            (Some(smoothed), Some(rate)) => Some(smoothed * weight + rate * (1. - weight)),
+            (smoothed, rate) => smoothed.or(rate),
+        }
+    }
+
+    /// Number of items per second over the last `.with_windowed_rate(...)` of time, i.e. between
+    /// the oldest record in that window and this one. None if it's not being recorded, or there
+    /// is no earlier record in the window.
+    pub fn windowed_rate(&self) -> Option<f64> {
+        self.windowed_rate.filter(|&rate| self.allowed(rate))
+    }
+
+    /// Is it going faster or slower recently? Compares the recent rate (the `.smoothed_rate()`,
+    /// or else the `.weighted_rolling_rate()`) to the rate since the start. It's `Steady` if the
+    /// recent rate is within `tolerance` (e.g. `0.1` for ±10%) of the overall rate. `None` if
+    /// there's no recent rate (i.e. no rolling or exponential average), or no overall rate yet.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::{ProgressableIter, Trend};
+    /// let mut progressor = (0..10).progress().with_rolling_average(5);
+    /// let (state, _) = progressor.nth(5).unwrap();
+    /// match state.throughput_trend(0.1) {
+    ///     Some(Trend::Increasing) => println!("↑"),
+    ///     Some(Trend::Steady) => println!("→"),
+    ///     Some(Trend::Decreasing) => println!("↓"),
+    ///     None => {}
+    /// }
+    /// # }
+    /// ```
+    pub fn throughput_trend(&self, tolerance: f64) -> Option<Trend> {
+        let recent = self
+            .smoothed_rate()
+            .or_else(|| self.weighted_rolling_rate())?;
+        let overall = self.raw_rate();
+        if !(overall.is_finite() && overall > 0. && recent.is_finite()) {
+            return None;
+        }
+        let ratio = recent / overall;
+        Some(if ratio > 1. + tolerance {
+            Trend::Increasing
+        } else if ratio < 1. - tolerance {
+            Trend::Decreasing
+        } else {
+            Trend::Steady
+        })
+    }
+
+    /// If the total size is know (i.e. we know the `.fraction()`), calculate the estimated time
+    /// to arrival, i.e. how long before this is finished.
+    ///
+    /// If `.with_eta_from_weighted_regression(...)` or `.with_cost_model(...)` is used, that ETA
+    /// is returned instead.
+    ///
+    /// If `.with_stable_eta()` (or any of the individual `with_eta_*` options) were set on the
+    /// iterator, they are applied here.
+    pub fn eta(&self) -> Option<Duration> {
+        self.eta_for_fraction(self.fraction())
+    }
+
+    /// The `.eta()`, when the `.fraction()` is `fraction`
+    fn eta_for_fraction(&self, fraction: Option<f64>) -> Option<Duration> {
+        let settings = &self.eta_settings;
+        if self.num < settings.min_samples {
+            return None;
+        }
+
+        if let (Some(stall), Some(since_previous), Some(previous_eta)) = (
+            settings.hold_on_stall,
+            self.since_previous,
+            self.previous_eta,
+        ) {
+            if since_previous > stall {
+                return Some(previous_eta);
+            }
+        }
+
+        let mut eta = match self.model_eta {
+            Some(eta) => eta,
+            None => self
+                .total_time_for_fraction(self.active_duration(), fraction?)?
+                .saturating_sub(self.active_duration()),
+        };
+
+        if settings.monotonic_countdown {
+            if let (Some(since_previous), Some(previous_eta)) =
+                (self.since_previous, self.previous_eta)
+            {
+                let countdown = previous_eta.saturating_sub(since_previous);
+                // Only leave the countdown if it's really slowed down or sped up
+                let tolerance = countdown.mul_f64(MONOTONIC_ETA_TOLERANCE);
+                if eta <= countdown + tolerance && eta + tolerance >= countdown {
+                    eta = countdown;
+                }
+            }
+        }
+
+        if settings.cap.is_some_and(|cap| eta > cap) {
+            return None;
+        }
+
+        match settings.granularity {
+            None => Some(eta),
+            Some(granularity) => {
+                // To the nearest step, like `render_bar(...)`
+                let steps = (eta.as_secs_f64() / granularity.as_secs_f64() + 0.5) as u64;
+                Some(granularity.mul_f64(steps as f64))
+            }
+        }
+    }
+
+    /// How many more items should be done in the next `duration`, at the `.rate()`, e.g. for
+    /// "how much will be done in the next 5 minutes?". This isn't capped at the total.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// # use std::time::Duration;
+    /// let mut progressor = (0..100).progress();
+    /// let (state, _) = progressor.nth(9).unwrap();
+    /// let in_5_min = state.items_in(Duration::from_secs(300));
+    /// assert_eq!(in_5_min, state.rate() * 300.);
+    /// # }
+    /// ```
+    pub fn items_in(&self, duration: Duration) -> f64 {
+        self.rate() * duration.as_secs_f64()
+    }
+
+    /// Like `.items_in(duration)`, but at the recent `.smoothed_rate()`, so it follows changes
+    /// of speed. `None` if there is no smoothed rate.
+    pub fn items_in_smoothed(&self, duration: Duration) -> Option<f64> {
+        self.smoothed_rate()
+            .map(|rate| rate * duration.as_secs_f64())
+    }
+
+    /// How long the remaining items would take at `rate` items per second (e.g. `.rate()`,
+    /// `.exp_average_rate()` or your own value). `None` if the total isn't known, or `rate` isn't
+    /// positive.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// # use std::time::Duration;
+    /// let mut progressor = (0..100).progress();
+    /// let (state, _) = progressor.next().unwrap();
+    /// // 99 items left
+    /// assert_eq!(state.remaining_at_rate(10.), Some(Duration::from_secs_f64(9.9)));
+    /// assert_eq!(state.remaining_at_rate(0.), None);
+    /// # }
+    /// ```
+    pub fn remaining_at_rate(&self, rate: f64) -> Option<Duration> {
+        if rate <= 0. || rate.is_nan() {
+            return None;
+        }
+        self.items_remaining()
+            .map(|remaining| Duration::from_secs_f64(remaining as f64 / rate))
+    }
+
+    /// The `.rate()`, when the items are bytes (e.g. from `.progress_bytes()`), with binary
+    /// units, like `512 B/s`, `1.5 KiB/s`, `20.0 MiB/s` or `1.1 GiB/s`.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableRead;
+    /// use std::io::Read;
+    /// let mut reader = (&[0u8; 4096][..]).progress_bytes();
+    /// reader.read_to_end(&mut Vec::new()).unwrap();
+    /// let state = reader.record();
+    /// println!("Read {} at {}", state.bytes_done_human(), state.bytes_rate_human());
+    /// # }
+    /// ```
+    pub fn bytes_rate_human(&self) -> String {
+        format!("{}/s", human_bytes(self.rate()))
+    }
+
+    /// The `.num_done()`, when the items are bytes, with binary units like
+    /// `.bytes_rate_human()`, e.g. `4.0 KiB`.
+    pub fn bytes_done_human(&self) -> String {
+        human_bytes(self.num_done_u64() as f64)
+    }
+
+    /// Number of bytes so far, if `.with_bytes(...)` or `progress_lines(...)` was used. 0
+    /// otherwise.
+    pub fn bytes_done(&self) -> u64 {
+        self.bytes_done.unwrap_or(0)
+    }
+
+    /// Total number of bytes, if `.with_bytes(...)` was used and given the total, or from
+    /// `progress_lines(...)`.
+    pub fn byte_total(&self) -> Option<u64> {
+        self.byte_total
+    }
+
+    /// How far through the bytes (from 0 to 1), if `.with_bytes(...)` was used and given the
+    /// total. This is separate from the `.fraction()`, which counts items.
+    pub fn byte_fraction(&self) -> Option<f64> {
+        self.byte_total
+            .map(|total| self.bytes_done() as f64 / total as f64)
+            .filter(|&fraction| self.allowed(fraction))
+    }
+
+    /// Number of bytes per second, calculated from the start, if `.with_bytes(...)` was used.
+    pub fn byte_rate(&self) -> f64 {
+        let rate = self.bytes_done() as f64 / self.active_duration().as_secs_f64();
+        if self.allowed(rate) {
+            rate
+        } else {
+            0.
+        }
+    }
+
+    /// How full a buffer (e.g. the channel this iterator feeds) is, from 0 to 1, as set with
+    /// `.set_buffer_depth(...)`. This isn't part of the progress, but it's handy to show them
+    /// together. `None` if it hasn't been set, or the capacity is 0.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..10).progress();
+    /// progressor.set_buffer_depth(8, 10);
+    /// let (state, _) = progressor.next().unwrap();
+    /// println!("queue {:.0}% full", state.buffer_fraction().unwrap() * 100.);
+    /// assert_eq!(state.buffer_fraction(), Some(0.8));
+    /// # }
+    /// ```
+    pub fn buffer_fraction(&self) -> Option<f64> {
+        self.buffer_depth
+            .filter(|&(_depth, capacity)| capacity > 0)
+            .map(|(depth, capacity)| depth as f64 / capacity as f64)
+    }
+
+    /// How much the `.fraction()` has changed since the `previous` record, e.g. to animate
+    /// between them. `None` if either fraction isn't known.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..4).progress();
+    /// let (previous, _) = progressor.next().unwrap();
+    /// let (state, _) = progressor.nth(1).unwrap();
+    /// assert_eq!(state.fraction_delta_since(&previous), Some(0.5));
+    /// # }
+    /// ```
+    pub fn fraction_delta_since(&self, previous: &ProgressRecord<T>) -> Option<f64> {
+        Some(self.fraction()? - previous.fraction()?)
+    }
+
+    /// How far ahead (more than 1) or behind (less than 1) of a schedule we are, i.e. the
+    /// `.fraction()` divided by the fraction which should be done by now, e.g. for a scheduler to
+    /// decide whether to add more workers. `None` if the fraction isn't known, or
+    /// `target_fraction_by_now` is 0.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..100).progress();
+    /// let (state, _) = progressor.nth(24).unwrap();
+    /// // 25% done, but we should be 50% done by now
+    /// assert_eq!(state.catch_up_factor(0.5), Some(0.5));
+    /// assert_eq!(state.catch_up_factor(0.), None);
+    /// # }
+    /// ```
+    pub fn catch_up_factor(&self, target_fraction_by_now: f64) -> Option<f64> {
+        if target_fraction_by_now == 0. {
+            return None;
+        }
+        self.fraction()
+            .map(|fraction| fraction / target_fraction_by_now)
+    }
+
+    /// What the ETA would be if we carried on at `rate` items per second, e.g. for "at twice the
+    /// speed, we'd be finished in ...". This is the same as `.remaining_at_rate(rate)`, and
+    /// unlike `.eta()`, none of the ETA options (e.g. `.with_stable_eta()`) are applied. `None` if
+    /// the total isn't known, or `rate` isn't positive.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// # use std::time::Duration;
+    /// let mut progressor = (0..100).progress();
+    /// let (state, _) = progressor.nth(9).unwrap();
+    /// // 90 items left
+    /// let rate = 15.;
+    /// assert_eq!(state.eta_with_rate(rate * 2.), Some(Duration::from_secs(3)));
+    /// assert_eq!(state.eta_with_rate(-1.), None);
+    /// # }
+    /// ```
+    pub fn eta_with_rate(&self, rate: f64) -> Option<Duration> {
+        self.remaining_at_rate(rate)
+    }
+
+    /// Just the bar, `width` characters wide, with `#` for the done part, and `-` for the rest.
+    /// The fraction is clamped to `0.0..=1.0`. `None` if the `.fraction()` isn't known.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..5).progress();
+    /// let (state, _) = progressor.nth(1).unwrap();
+    /// assert_eq!(state.bar(10), Some("####------".to_string()));
+    /// # }
+    /// ```
+    pub fn bar(&self, width: usize) -> Option<String> {
+        Some(render_bar(self.fraction()?, width))
+    }
+
+    /// A progress bar line (bar, percent, rate & ETA), as wide as the terminal. If the terminal
+    /// width can't be found, it's 80 characters wide. The terminal is queried if the `terminal`
+    /// feature is enabled, otherwise (or if that fails) the `COLUMNS` environment variable is
+    /// used. `None` if the `.fraction()` isn't known.
+    ///
+    /// ```
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..10).progress();
+    /// let (state, _) = progressor.next().unwrap();
+    /// println!("{}", state.bar_auto().unwrap());
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn bar_auto(&self) -> Option<String> {
+        self.bar_for_terminal_width(terminal_width())
+    }
+
+    /// Draw the progress bar line (like `.bar_auto()`, but `width` wide) on the bottom line of
+    /// the terminal, using ANSI escape codes to save the cursor position, move to the bottom,
+    /// clear the line, draw the bar, and restore the cursor. So other output (and scrollback) is
+    /// left alone. Nothing is written if the `.fraction()` isn't known.
+    ///
+    /// This always writes to `w`, see `.draw_bar_line_stdout(...)` to only draw on a terminal.
+    #[cfg(feature = "terminal")]
+    pub fn draw_bar_line<W: Write>(&self, w: W, width: usize) -> io::Result<()> {
+        let mut w = w;
+        match self.bar_for_terminal_width(Some(width)) {
+            None => Ok(()),
+            Some(bar) => write!(w, "\x1b7\x1b[999B\r\x1b[2K{}\x1b8", bar),
+        }
+    }
+
+    /// `.draw_bar_line(...)` on stdout, as wide as the terminal, but only if stdout is a
+    /// terminal.
+    #[cfg(feature = "terminal")]
+    pub fn draw_bar_line_stdout(&self) -> io::Result<()> {
+        use std::io::IsTerminal;
+        let stdout = io::stdout();
+        if !stdout.is_terminal() {
+            return Ok(());
+        }
+        let mut stdout = stdout.lock();
+        self.draw_bar_line(&mut stdout, terminal_width().unwrap_or(80))?;
+        stdout.flush()
+    }
+
+    #[cfg(feature = "std")]
+    fn bar_for_terminal_width(&self, terminal_width: Option<usize>) -> Option<String> {
+        let fraction = self.fraction()?;
+        let eta = match self.eta() {
+            Some(eta) => format!("{:.0}s", eta.as_secs_f64()),
+            None => "?".to_string(),
+        };
+        let text = format!(" {:5.1}% {:.1}/s ETA {}", fraction * 100., self.rate(), eta);
+        let width = terminal_width.unwrap_or(80).saturating_sub(text.len() + 2);
+        Some(format!("[{}]{}", render_bar(fraction, width), text))
+    }
+
+    /// True iff a `.with_time_budget(...)` was set, and we have been running for longer than
+    /// that.
+    pub fn over_budget(&self) -> bool {
+        self.time_budget
+            .is_some_and(|budget| self.duration_since_start() > budget)
+    }
+
+    /// How much of the `.with_time_budget(...)` is left (zero if over budget). `None` if no
+    /// budget was set.
+    pub fn budget_remaining(&self) -> Option<Duration> {
+        self.time_budget
+            .map(|budget| budget.saturating_sub(self.duration_since_start()))
+    }
+
+    /// Will we finish after the `.with_time_budget(...)` runs out, based on the `.eta()`? `None`
+    /// if no budget was set, or the ETA isn't known.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// # use std::time::Duration;
+    /// let mut progressor = (0..1_000).progress().with_time_budget(Duration::from_secs(3600));
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert!(!state.over_budget());
+    /// assert!(state.budget_remaining().unwrap() <= Duration::from_secs(3600));
+    /// # }
+    /// ```
+    pub fn will_exceed_budget(&self) -> Option<bool> {
+        let remaining = self.budget_remaining()?;
+        self.eta().map(|eta| eta > remaining)
+    }
+
+    /// How much of the `.with_time_budget(...)` has been used, i.e. the time so far divided by
+    /// the budget (more than 1 if over budget). `None` if no budget was set, or it's zero.
+    pub fn time_fraction(&self) -> Option<f64> {
+        let budget = self.time_budget?.as_secs_f64();
+        if budget == 0. {
+            return None;
+        }
+        Some(self.duration_since_start().as_secs_f64() / budget)
+    }
+
+    /// How far the `.fraction()` is ahead (positive) or behind (negative) the `.time_fraction()`
+    /// of the `.with_time_budget(...)`. e.g. 50% done with 40% of the budget used is `0.1`.
+    /// `None` if either isn't known.
+    pub fn schedule_skew(&self) -> Option<f64> {
+        Some(self.fraction()? - self.time_fraction()?)
+    }
+
+    /// Is the `.fraction()` done more than the `.time_fraction()` of the budget used? i.e. will
+    /// we finish within the `.with_time_budget(...)` if we keep going like this. e.g. to colour
+    /// a progress bar green or red. `None` if either isn't known.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// # use std::time::Duration;
+    /// let mut progressor = (0..100).progress().with_time_budget(Duration::from_secs(3600));
+    /// let (state, _) = progressor.nth(24).unwrap();
+    /// // 25% done, in much less than 25% of an hour
+    /// assert_eq!(state.ahead_of_schedule(), Some(true));
+    /// assert!(state.schedule_skew().unwrap() > 0.24);
+    ///
+    /// let (state, _) = (0..100).progress().next().unwrap();
+    /// assert_eq!(state.ahead_of_schedule(), None);
+    /// # }
+    /// ```
+    pub fn ahead_of_schedule(&self) -> Option<bool> {
+        self.schedule_skew().map(|skew| skew > 0.)
+    }
+
+    /// All the metrics of this record at once, calculated together, which is quicker than
+    /// calling each method.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..10).progress();
+    /// let (state, _) = progressor.next().unwrap();
+    /// let fields = state.fields();
+    /// assert_eq!(fields.num_done, 1);
+    /// assert_eq!(fields.total, Some(10));
+    /// assert_eq!(fields.fraction, Some(0.1));
+    /// # }
+    /// ```
+    pub fn fields(&self) -> ProgressFields {
+        let fraction = self.fraction();
+        ProgressFields {
+            num_done: self.num,
+            total: self.resolved_total(),
+            fraction,
+            percent: fraction.map(|f| f * 100.),
+            rate: self.rate(),
+            smoothed_rate: self.smoothed_rate(),
+            eta: self.eta_for_fraction(fraction),
+            elapsed: self.iterating_for,
+        }
+    }
+
+    /// Fill in the placeholders in `template`, to save writing the same `format!(...)` for
+    /// each progress line:
+    ///
+    /// * `{percent}`: the `.percent()`, to 1 decimal place
+    /// * `{num}`: the `.num_done()`
+    /// * `{total}`: the `.display_total()`
+    /// * `{rate}`: the `.rate()`, to 1 decimal place
+    /// * `{eta}`: the `.human_eta()`
+    /// * `{elapsed}`: the `.elapsed_human()`
+    /// * `{bytes}`, `{byte_total}` & `{byte_rate}` (to 1 decimal place): the `.bytes_done()`,
+    ///   `.byte_total()` & `.byte_rate()`, if the bytes are counted with `.with_bytes(...)`
+    ///
+    /// Values which aren't known are `?`. Anything else in `{…}` is left as it is.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..200).progress();
+    /// let (state, _) = progressor.nth(49).unwrap();
+    /// assert_eq!(state.message("{percent}% done, {num} of {total}"), "25.0% done, 50 of 200");
+    /// # }
+    /// ```
+    pub fn message(&self, template: &str) -> String {
+        let mut message = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            message.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = match rest.find('}') {
+                Some(end) => end,
+                None => break,
+            };
+            let value = match &rest[1..end] {
+                "percent" => self.percent().map(|percent| format!("{:.1}", percent)),
+                "num" => Some(self.num_done_u64().to_string()),
+                "total" => self.display_total().map(|total| total.to_string()),
+                "rate" => Some(format!("{:.1}", self.rate())),
+                #[cfg(feature = "std")]
+                "eta" => self.human_eta(),
+                #[cfg(not(feature = "std"))]
+                "eta" => self.eta().map(human_duration),
+                "elapsed" => Some(self.elapsed_human()),
+                "bytes" => self.bytes_done.map(|bytes| bytes.to_string()),
+                "byte_total" => self.byte_total.map(|total| total.to_string()),
+                "byte_rate" => self.bytes_done.map(|_| format!("{:.1}", self.byte_rate())),
+                _ => {
+                    // Not ours, so leave the `{` & carry on from the next character
+                    message.push('{');
+                    rest = &rest[1..];
+                    continue;
+                }
+            };
+            message.push_str(value.as_deref().unwrap_or("?"));
+            rest = &rest[end + 1..];
+        }
+        message.push_str(rest);
+        message
+    }
+
+    /// The main numbers as one line of compact JSON, e.g. for structured logging, without
+    /// needing serde. Unknown values are `null`.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let (state, _) = (0..4).progress().next().unwrap();
+    /// assert!(state.to_json_line().starts_with(r#"{"done":1,"total":4,"pct":25,"rate":"#));
+    /// # }
+    /// ```
+    pub fn to_json_line(&self) -> String {
+        let fields = self.fields();
+        format!(
+            "{{\"done\":{},\"total\":{},\"pct\":{},\"rate\":{},\"eta_secs\":{},\"elapsed_secs\":{}}}",
+            fields.num_done,
+            fields.total.map_or_else(|| "null".to_string(), |t| t.to_string()),
+            json_f64(fields.percent),
+            json_f64(Some(fields.rate)),
+            json_f64(fields.eta.map(|eta| eta.as_secs_f64())),
+            json_f64(Some(fields.elapsed.as_secs_f64())),
+        )
+    }
+
+    /// The line written by `.with_completion_log(...)`, e.g.
+    /// `finished_at=1700000000 items=100 elapsed_secs=2.000 rate=50.000`, where `finished_at` is
+    /// the Unix time in seconds.
+    #[cfg(feature = "std")]
+    fn completion_line(&self) -> String {
+        let finished_at = (self.started_wall + self.iterating_for)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        format!(
+            "finished_at={} items={} elapsed_secs={:.3} rate={:.3}",
+            finished_at,
+            self.num_done_u64(),
+            self.iterating_for.as_secs_f64(),
+            self.raw_rate()
+        )
+    }
+
+    /// Total weight of the items so far, if `.with_weights(...)` was used. 0 otherwise.
+    pub fn weight_done(&self) -> f64 {
+        self.weight_done
+    }
+
+    /// How far through the iterator as a fraction of the total weight, if `.with_weights(...)`
+    /// was used.
+    pub fn weighted_fraction(&self) -> Option<f64> {
+        self.total_weight.map(|total| self.weight_done / total)
+    }
+
+    /// The `.eta()` for people to read, shown as set by `.with_eta_display(...)`, by default how
+    /// long is left (e.g. `1h 2m 3s`). `None` if the ETA isn't known.
+    ///
+    /// ```
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..10).progress();
+    /// let (state, _) = progressor.next().unwrap();
+    /// println!("ETA {}", state.human_eta().unwrap_or_else(|| "?".into()));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn human_eta(&self) -> Option<String> {
+        let eta = self.eta()?;
+        let finish_at = || format_time_of_day(self.started_wall + self.iterating_for + eta);
+        Some(match self.eta_display {
+            EtaDisplay::Remaining => human_duration(eta),
+            EtaDisplay::FinishAt => finish_at(),
+            EtaDisplay::Both => format!("{} ({})", human_duration(eta), finish_at()),
+        })
+    }
+
+    /// If the total size is know (i.e. we know the `.fraction()`), calculate how long, in total,
+    /// this iterator would run for. i.e. how long it's run plus how much longer it has left
+    pub fn estimated_total_time(&self) -> Option<Duration> {
+        self.total_time_for_fraction(self.duration_since_start(), self.fraction()?)
+    }
+
+    /// How long it would take in total, if it's taken `so_far` to do `fraction`. `None` if that
+    /// can't be calculated, e.g. `fraction` is 0 because no items have been counted yet.
+    fn total_time_for_fraction(&self, so_far: Duration, fraction: f64) -> Option<Duration> {
+        if !(fraction > 0. && fraction.is_finite()) {
+            return None;
+        }
+        Duration::try_from_secs_f64(so_far.as_secs_f64() / fraction).ok()
+    }
+}
+
+/// The parts which need the times to be `std::time::Instant`s
+#[cfg(feature = "std")]
+impl ProgressRecord {
+    /// The main numbers of this record, as plain data.
+    pub fn snapshot(&self) -> ProgressSnapshot {
+        ProgressSnapshot {
+            num_done: self.num,
+            total: self.resolved_total(),
+            started_iterating: self.started_iterating,
+            elapsed: self.iterating_for,
+        }
+    }
+
+    /// When this iterator is expected to finish, i.e. when it started plus the
+    /// `.estimated_total_time()`. `None` if the `.fraction()` isn't known.
+    ///
+    /// ```
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..10).progress();
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert!(state.eta_as_instant().unwrap() >= state.started_iterating());
+    /// ```
+    pub fn eta_as_instant(&self) -> Option<Instant> {
+        Some(self.started_iterating + self.estimated_total_time()?)
+    }
+}
+
+/// The callback from `.on_finish(...)`
+type OnFinish<T> = Box<dyn FnOnce(&ProgressRecord<T>) + Send + Sync>;
+
+/// The time & count of each recent record, for `.with_windowed_rate(...)`
+type RateWindow<T> = VecDeque<(T, u64)>;
+
+pub struct OptionalProgressRecorderIter<
+    I,
+    #[cfg(feature = "std")] C: Clock = StdClock,
+    #[cfg(not(feature = "std"))] C: Clock,
+> {
+    /// The iterator that we are iteating on
+    iter: I,
+
+    /// Where the times come from
+    clock: C,
+
+    /// How many items have been seen
+    count: u64,
+
+    generate_every_count: usize,
+
+    /// When did we start iterating
+    started_iterating: C::Instant,
+
+    previous_record_tm: Option<C::Instant>,
+    /// The `count` at the previous record
+    previous_record_count: u64,
+    /// Don't add the time since the previous record to the averages, after `.reset_averages()`
+    skip_next_duration_sample: bool,
+
+    rolling_average: Option<(usize, VecDeque<f64>)>,
+    exp_average: Option<(f64, Option<Duration>)>,
+    weighted_rolling_average: Option<(usize, VecDeque<f64>)>,
+    /// From `.with_windowed_rate(...)`, the window, and the time & count of each recent record
+    windowed_rate: Option<(Duration, RateWindow<C::Instant>)>,
+    assumed_size: Option<usize>,
+    /// The inner iterator's `.size_hint()` isn't for the items which are counted, from
+    /// `.with_count_predicate(...)`. Then the `.assume_size(...)` isn't how many items are
+    /// yielded either.
+    ignore_size_hint: bool,
+    /// Fraction from `.set_position(...)`, if set
+    position_fraction: Option<f64>,
+    /// The bytes read so far, and the total, for `progress_lines(...)`
+    bytes: Option<(u64, u64)>,
+    /// From `.with_sample_every_bytes(...)`, how often, and the bytes read to generate the next
+    /// record at
+    sample_every_bytes: Option<(u64, u64)>,
+    /// Print a summary at the end, if it took longer than this
+    #[cfg(feature = "std")]
+    summary_if_longer_than: Option<Duration>,
+    /// Printed at the first item, from `.with_start_message(...)`
+    #[cfg(feature = "std")]
+    start_message: Option<String>,
+    /// Where our own messages are written, from `.with_output(...)`
+    #[cfg(feature = "std")]
+    output: Output,
+    /// Called with the final record, from `.on_finish(...)`
+    on_finish: Option<OnFinish<C::Instant>>,
+    /// Don't generate a record until this long after the previous one
+    min_update_interval: Option<Duration>,
+    /// From `.with_adaptive_stride(...)`, the maximum stride, and the count to generate the next
+    /// record at. The current stride is `generate_every_count`
+    adaptive_stride: Option<(usize, u64)>,
+    time_budget: Option<Duration>,
+    display_total: Option<usize>,
+    #[cfg(feature = "std")]
+    started_wall: SystemTime,
+    #[cfg(feature = "std")]
+    eta_display: EtaDisplay,
+    /// Factor for `.with_display_damping(...)`, and the last damped rate
+    display_damping: Option<(f64, Option<f64>)>,
+    count_multiplier: u64,
+    /// The optional maximum number of timestamps to keep, and the timestamps
+    timestamps: Option<(Option<usize>, Vec<Duration>)>,
+    /// From `.with_duration_history(...)`, how many to keep, the time each of the last items
+    /// took (in sec), and when the last item was
+    duration_history: Option<(usize, VecDeque<f64>, Option<C::Instant>)>,
+    cost_model: Option<CostModel>,
+    /// How many items were `Err`, from `.with_error_counting()`
+    num_errors: u64,
+    /// From `.with_safe_math()`
+    safe_math: bool,
+    /// From `.set_buffer_depth(...)`
+    buffer_depth: Option<(usize, usize)>,
+    /// For `.print_status_every_n_sec(...)`: when the last line was printed, and how wide it was.
+    /// In a `Mutex`, since it's printed with `&self`.
+    #[cfg(feature = "std")]
+    status_line: Mutex<(Option<C::Instant>, usize)>,
+    /// The last unit chosen, if `.with_auto_rate_unit()` is used
+    rate_unit: Option<Option<RateUnit>>,
+    /// Print a summary when interrupted, from `.with_interrupt_summary()`
+    #[cfg(feature = "std")]
+    interrupt_summary: bool,
+    /// Catching SIGINT until finished or dropped, from `.with_interrupt_summary()`
+    #[cfg(all(feature = "interrupt", unix))]
+    sigint_guard: Option<SigintGuard>,
+    /// Where to append a line when finished, from `.with_completion_log(...)`
+    #[cfg(feature = "std")]
+    completion_log: Option<PathBuf>,
+    /// How often, and at what level, to send `tracing` events, from `.trace_every_n_sec(...)`
+    #[cfg(feature = "tracing")]
+    trace_every: Option<(f32, tracing::Level)>,
+    /// How often, and at what level, to log a progress line, from `.log_every_n_sec(...)`
+    #[cfg(feature = "log")]
+    log_every: Option<(f32, log::Level)>,
+    /// The `.smoothed_rate()` of the last record, for `.next_item_eta()`
+    previous_smoothed_rate: Option<f64>,
+    /// Applied to the `.fraction()`, from `.with_fraction_map(...)`
+    fraction_map: Option<Box<dyn Fn(f64) -> f64 + Send + Sync>>,
+    /// Updated every item, if `.with_handle()` is used
+    #[cfg(feature = "std")]
+    handle: Option<ProgressHandle<C::Instant>>,
+    /// How many samples the averages have, if `.with_smoothing_bootstrap()` is used
+    #[cfg(feature = "std")]
+    smoothing_samples: Option<u64>,
+    /// The idle threshold, total time spent idle, and when the last item was
+    idle: Option<(Duration, Duration, Option<C::Instant>)>,
+    /// For `.do_every_n_sec(...)`: the count when it was last called, when it was last called
+    /// for an earlier item, and when it was last called
+    check_tms: (u64, Option<C::Instant>, Option<C::Instant>),
+
+    eta_settings: EtaSettings,
+    /// The `.eta()` of the last generated record
+    previous_eta: Option<Duration>,
+}
+
+/// Wraps an iterator and keeps track of state used for `ProgressRecord`'s
+pub struct ProgressRecorderIter<
+    I,
+    #[cfg(feature = "std")] C: Clock = StdClock,
+    #[cfg(not(feature = "std"))] C: Clock,
+>(OptionalProgressRecorderIter<I, C>);
+
+impl<I, C: Clock> AsRef<OptionalProgressRecorderIter<I, C>> for ProgressRecorderIter<I, C> {
+    fn as_ref(&self) -> &OptionalProgressRecorderIter<I, C> {
+        &self.0
+    }
+}
+
+impl<I, C: Clock> AsMut<OptionalProgressRecorderIter<I, C>> for ProgressRecorderIter<I, C> {
+    fn as_mut(&mut self) -> &mut OptionalProgressRecorderIter<I, C> {
+        &mut self.0
+    }
+}
+
+impl<I: Iterator, C: Clock> Deref for ProgressRecorderIter<I, C> {
+    type Target = OptionalProgressRecorderIter<I, C>;
+    fn deref(&self) -> &OptionalProgressRecorderIter<I, C> {
+        &self.0
+    }
+}
+
+impl<I: Iterator, C: Clock> DerefMut for ProgressRecorderIter<I, C> {
+    fn deref_mut(&mut self) -> &mut OptionalProgressRecorderIter<I, C> {
+        &mut self.0
+    }
+}
+
+#[cfg(feature = "std")]
+impl<I: Iterator> ProgressRecorderIter<I> {
+    /// Create a new `ProgressRecorderIter` from another iterator.
+    pub fn new(iter: I) -> ProgressRecorderIter<I> {
+        ProgressRecorderIter(OptionalProgressRecorderIter::new(iter, 1))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<I: Iterator, C: Clock<Instant = Instant>> ProgressRecorderIter<I, C> {
+    /// Only the progress, as a `ProgressSnapshot` for each item, e.g. for a side process which
+    /// is only counting. The items are still taken from the underlying iterator, and are
+    /// dropped. See `OptionalProgressRecorderIter::snapshots` to only get some of them.
+    ///
+    /// ```
+    /// # use iter_progress::ProgressableIter;
+    /// let last = (0..10).progress().snapshots().last().unwrap();
+    /// assert_eq!(last.num_done, 10);
+    /// assert_eq!(last.total, Some(10));
+    /// ```
+    pub fn snapshots(self) -> impl Iterator<Item = ProgressSnapshot> {
+        self.map(|(state, _item)| state.snapshot())
+    }
+}
+
+impl<I: Iterator, C: Clock> ProgressRecorderIter<I, C> {
+    /// Create a new `ProgressRecorderIter` from another iterator, which gets the time from
+    /// `clock` rather than `std::time::Instant`, e.g. a hardware timer without `std`.
+    ///
+    /// ```
+    /// # extern crate core;
+    /// # use iter_progress::{Clock, ProgressRecorderIter};
+    /// # use core::cell::Cell;
+    /// # use core::time::Duration;
+    /// /// Milliseconds from a hardware timer
+    /// struct Ticks(Cell<u64>);
+    ///
+    /// impl Clock for Ticks {
+    ///     type Instant = u64;
+    ///     fn now(&self) -> u64 {
+    ///         self.0.get()
+    ///     }
+    ///     fn duration_between(&self, earlier: u64, later: u64) -> Duration {
+    ///         Duration::from_millis(later.saturating_sub(earlier))
+    ///     }
+    /// }
+    ///
+    /// let ticks = Ticks(Cell::new(1_000));
+    /// let mut progressor = ProgressRecorderIter::with_clock(0..10, &ticks);
+    /// let (state, num) = progressor.next().unwrap();
+    /// assert_eq!(num, 0);
+    /// assert_eq!(state.timestamp(), 1_000);
+    ///
+    /// // 2 sec later
+    /// ticks.0.set(3_000);
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.fraction(), Some(0.2));
+    /// assert_eq!(state.rate(), 1.);
+    /// assert_eq!(state.eta(), Some(Duration::from_secs(8)));
+    /// ```
+    pub fn with_clock(iter: I, clock: C) -> ProgressRecorderIter<I, C> {
+        ProgressRecorderIter(OptionalProgressRecorderIter::with_clock(iter, 1, clock))
+    }
+
+    pub fn assume_size(self, size: impl Into<Option<usize>>) -> Self {
+        let mut new = self;
+        new.0.assumed_size = size.into();
+        new
+    }
+
+    /// See `OptionalProgressRecorderIter::with_stable_eta`
+    pub fn with_stable_eta(self) -> Self {
+        ProgressRecorderIter(self.0.with_stable_eta())
+    }
+
+    /// See `OptionalProgressRecorderIter::with_monotonic_eta_countdown`
+    pub fn with_monotonic_eta_countdown(self) -> Self {
+        ProgressRecorderIter(self.0.with_monotonic_eta_countdown())
+    }
+
+    /// See `OptionalProgressRecorderIter::with_rolling_average`
+    pub fn with_rolling_average(self, size: impl Into<Option<usize>>) -> Self {
+        ProgressRecorderIter(self.0.with_rolling_average(size))
+    }
+
+    /// See `OptionalProgressRecorderIter::with_exp_average`
+    pub fn with_exp_average(self, rate: impl Into<Option<f64>>) -> Self {
+        ProgressRecorderIter(self.0.with_exp_average(rate))
+    }
+
+    /// See `OptionalProgressRecorderIter::with_weighted_rolling_average`
+    pub fn with_weighted_rolling_average(self, size: impl Into<Option<usize>>) -> Self {
+        ProgressRecorderIter(self.0.with_weighted_rolling_average(size))
+    }
+
+    /// See `OptionalProgressRecorderIter::with_windowed_rate`
+    pub fn with_windowed_rate(self, window: impl Into<Option<Duration>>) -> Self {
+        ProgressRecorderIter(self.0.with_windowed_rate(window))
+    }
+
+    /// See `OptionalProgressRecorderIter::with_interrupt_summary`
+    #[cfg(feature = "std")]
+    #[cfg(all(feature = "interrupt", unix))]
+    pub fn with_interrupt_summary(self) -> Self {
+        ProgressRecorderIter(self.0.with_interrupt_summary())
+    }
+
+    /// See `OptionalProgressRecorderIter::trace_every_n_sec`
+    #[cfg(feature = "tracing")]
+    pub fn trace_every_n_sec(self, n: f32, level: tracing::Level) -> Self {
+        ProgressRecorderIter(self.0.trace_every_n_sec(n, level))
+    }
+
+    /// See `OptionalProgressRecorderIter::log_every_n_sec`
+    #[cfg(feature = "log")]
+    pub fn log_every_n_sec(self, n: f32, level: log::Level) -> Self {
+        ProgressRecorderIter(self.0.log_every_n_sec(n, level))
+    }
+
+    /// See `OptionalProgressRecorderIter::with_safe_math`
+    pub fn with_safe_math(self) -> Self {
+        ProgressRecorderIter(self.0.with_safe_math())
+    }
+
+    /// See `OptionalProgressRecorderIter::with_auto_rate_unit`
+    pub fn with_auto_rate_unit(self) -> Self {
+        ProgressRecorderIter(self.0.with_auto_rate_unit())
+    }
+
+    /// See `OptionalProgressRecorderIter::with_completion_log`
+    #[cfg(feature = "std")]
+    pub fn with_completion_log(self, path: impl AsRef<Path>) -> Self {
+        ProgressRecorderIter(self.0.with_completion_log(path))
+    }
+
+    /// See `OptionalProgressRecorderIter::with_fraction_map`
+    pub fn with_fraction_map<F: Fn(f64) -> f64 + Send + Sync + 'static>(self, f: F) -> Self {
+        ProgressRecorderIter(self.0.with_fraction_map(f))
+    }
+
+    /// See `OptionalProgressRecorderIter::with_handle`
+    #[cfg(feature = "std")]
+    pub fn with_handle(self) -> Self {
+        ProgressRecorderIter(self.0.with_handle())
+    }
+
+    /// See `OptionalProgressRecorderIter::with_smoothing_bootstrap`
+    #[cfg(feature = "std")]
+    pub fn with_smoothing_bootstrap(self) -> Self {
+        ProgressRecorderIter(self.0.with_smoothing_bootstrap())
+    }
+
+    /// See `OptionalProgressRecorderIter::with_idle_threshold`
+    pub fn with_idle_threshold(self, threshold: impl Into<Option<Duration>>) -> Self {
+        ProgressRecorderIter(self.0.with_idle_threshold(threshold))
+    }
+
+    /// See `OptionalProgressRecorderIter::with_cost_model`
+    pub fn with_cost_model<F: Fn(usize) -> f64 + Send + Sync + 'static>(self, cost: F) -> Self {
+        ProgressRecorderIter(self.0.with_cost_model(cost))
+    }
+
+    /// See `OptionalProgressRecorderIter::with_timestamps`
+    pub fn with_timestamps(self) -> Self {
+        ProgressRecorderIter(self.0.with_timestamps())
+    }
+
+    /// See `OptionalProgressRecorderIter::with_timestamps_cap`
+    pub fn with_timestamps_cap(self, n: usize) -> Self {
+        ProgressRecorderIter(self.0.with_timestamps_cap(n))
+    }
+
+    /// See `OptionalProgressRecorderIter::with_duration_history`
+    pub fn with_duration_history(self, k: usize) -> Self {
+        ProgressRecorderIter(self.0.with_duration_history(k))
+    }
+
+    /// See `OptionalProgressRecorderIter::with_count_multiplier`
+    pub fn with_count_multiplier(self, k: usize) -> Self {
+        ProgressRecorderIter(self.0.with_count_multiplier(k))
+    }
+
+    /// See `OptionalProgressRecorderIter::with_display_damping`
+    pub fn with_display_damping(self, factor: impl Into<Option<f64>>) -> Self {
+        ProgressRecorderIter(self.0.with_display_damping(factor))
+    }
+
+    /// See `OptionalProgressRecorderIter::with_eta_display`
+    #[cfg(feature = "std")]
+    pub fn with_eta_display(self, mode: EtaDisplay) -> Self {
+        ProgressRecorderIter(self.0.with_eta_display(mode))
+    }
+
+    /// See `OptionalProgressRecorderIter::with_display_total`
+    pub fn with_display_total(self, total: impl Into<Option<usize>>) -> Self {
+        ProgressRecorderIter(self.0.with_display_total(total))
+    }
+
+    /// See `OptionalProgressRecorderIter::with_time_budget`
+    pub fn with_time_budget(self, budget: impl Into<Option<Duration>>) -> Self {
+        ProgressRecorderIter(self.0.with_time_budget(budget))
+    }
+
+    /// See `OptionalProgressRecorderIter::with_summary_if_longer_than`
+    #[cfg(feature = "std")]
+    pub fn with_summary_if_longer_than(self, threshold: impl Into<Option<Duration>>) -> Self {
+        ProgressRecorderIter(self.0.with_summary_if_longer_than(threshold))
+    }
+
+    /// See `OptionalProgressRecorderIter::with_start_message`
+    #[cfg(feature = "std")]
+    pub fn with_start_message(self, message: impl Into<String>) -> Self {
+        ProgressRecorderIter(self.0.with_start_message(message))
+    }
+
+    /// See `OptionalProgressRecorderIter::with_output`
+    #[cfg(feature = "std")]
+    pub fn with_output(self, output: Output) -> Self {
+        ProgressRecorderIter(self.0.with_output(output))
+    }
+
+    /// See `OptionalProgressRecorderIter::on_finish`
+    pub fn on_finish<F: FnOnce(&ProgressRecord<C::Instant>) + Send + Sync + 'static>(
+        self,
+        f: F,
+    ) -> Self {
+        ProgressRecorderIter(self.0.on_finish(f))
+    }
+
+    /// Give each item a weight (e.g. its size), calculated by `f`, where the total weight of all
+    /// items is `total_weight`. Each `ProgressRecord` then has a `.weight_done()` &
+    /// `.weighted_fraction()`, which are more accurate than counting items when items differ.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let sizes = vec![10, 30, 60];
+    /// let mut progressor = sizes.into_iter().progress().with_weights(100., |size| *size as f64);
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.weighted_fraction(), Some(0.1));
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.weighted_fraction(), Some(0.4));
+    /// # }
+    /// ```
+    pub fn with_weights<F>(self, total_weight: f64, f: F) -> WeightedProgressIter<I, F, C>
+    where
+        F: FnMut(&I::Item) -> f64,
+    {
+        WeightedProgressIter {
+            iter: self,
+            weight_fn: f,
+            weight_done: 0.,
+            total_weight,
+            regression: None,
+        }
+    }
+
+    /// Also count bytes, as well as items, where `f` returns the number of bytes of each item, and
+    /// there are `total_bytes` in total (if known). Each `ProgressRecord` then has
+    /// `.bytes_done()`, `.byte_total()`, `.byte_fraction()` & `.byte_rate()`, while
+    /// `.num_done()`, `.fraction()` & `.rate()` still count items.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let lines = vec!["a", "bcd", "ef", "ghij"];
+    /// let mut progressor = lines.into_iter().progress().with_bytes(10, |line| line.len() as u64);
+    /// let (state, _) = progressor.nth(1).unwrap();
+    /// println!("{}/4 lines ({}/10 bytes)", state.num_done(), state.bytes_done());
+    /// assert_eq!(state.fraction(), Some(0.5));
+    /// assert_eq!(state.byte_fraction(), Some(0.4));
+    /// # }
+    /// ```
+    pub fn with_bytes<F>(
+        self,
+        total_bytes: impl Into<Option<u64>>,
+        f: F,
+    ) -> ByteProgressIter<I, F, C>
+    where
+        F: FnMut(&I::Item) -> u64,
+    {
+        ByteProgressIter {
+            iter: self,
+            bytes_fn: f,
+            bytes_done: 0,
+            byte_total: total_bytes.into(),
+        }
+    }
+
+    /// Only count the items where `f` returns `true` (e.g. only valid records). All items are
+    /// still yielded, with a `ProgressRecord`, but `.num_done()` is only the number of matching
+    /// items so far. The `.size_hint()` can't be used to know how many items will match, so to
+    /// get a `.fraction()`, `.assume_size(...)` must be set to the number of matching items.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..10).progress().assume_size(5).with_count_predicate(|i| i % 2 == 0);
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.num_done(), 1);
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.num_done(), 1);
+    /// assert_eq!(state.fraction(), Some(0.2));
+    /// # }
+    /// ```
+    pub fn with_count_predicate<F>(self, f: F) -> CountPredicateIter<I, F, C>
+    where
+        F: FnMut(&I::Item) -> bool,
+    {
+        let mut iter = self;
+        // The size hint is for all items, not just matching ones
+        iter.0.ignore_size_hint = true;
+        CountPredicateIter { iter, predicate: f }
+    }
+
+    /// Return the `ProgressRecord` for each item in an `Arc`, so it can be cheaply given to
+    /// several consumers (e.g. a channel, a logger and a UI). This costs an allocation per item,
+    /// while a `ProgressRecord` is a plain struct which is cheap to copy, so it's only worth it
+    /// when a record is shared many times.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// use std::sync::Arc;
+    /// let mut progressor = (0..10).progress().shared();
+    /// let (state, _) = progressor.next().unwrap();
+    /// let for_logger = Arc::clone(&state);
+    /// assert_eq!(for_logger.num_done(), 1);
+    /// # }
+    /// ```
+    pub fn shared(self) -> SharedProgressIter<I, C> {
+        SharedProgressIter { iter: self }
+    }
+
+    /// From now on, only generate a record every `generate_every_count` items, like
+    /// `.optional_progress(...)`. Everything so far (the count, start time, averages etc.) and
+    /// the settings are kept. The records are for the items whose number is a multiple of
+    /// `generate_every_count`, counting from the start, not from this call.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..100).progress();
+    /// progressor.nth(2);
+    /// let mut progressor = progressor.into_optional(5);
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert!(state.is_none());
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.unwrap().num_done(), 5);
+    /// # }
+    /// ```
+    pub fn into_optional(self, generate_every_count: usize) -> OptionalProgressRecorderIter<I, C> {
+        let mut optional = self.0;
+        optional.generate_every_count = generate_every_count;
+        optional
+    }
+
+    /// Like `Iterator::scan`, but `f` is also given the `ProgressRecord` for each item. `f` can
+    /// update the state `St`, and returns the value to yield, or `None` to stop iterating.
+    /// `.num_done()` counts the items that have been given to `f`.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let running_sum: Vec<_> = (1..=4)
+    ///     .progress()
+    ///     .scan_progress(0, |sum, state, x| {
+    ///         *sum += x;
+    ///         Some((*sum, state.num_done()))
+    ///     })
+    ///     .collect();
+    /// assert_eq!(running_sum, vec![(1, 1), (3, 2), (6, 3), (10, 4)]);
+    /// # }
+    /// ```
+    pub fn scan_progress<St, B, F>(self, init: St, f: F) -> ScanProgress<I, St, F, C>
+    where
+        F: FnMut(&mut St, &ProgressRecord<C::Instant>, I::Item) -> Option<B>,
+    {
+        ScanProgress {
+            iter: self,
+            state: init,
+            f,
+            finished: false,
+        }
+    }
+}
+
+/// Iterator where each item has a weight. Created with `.with_weights(...)`
+pub struct WeightedProgressIter<
+    I,
+    F,
+    #[cfg(feature = "std")] C: Clock = StdClock,
+    #[cfg(not(feature = "std"))] C: Clock,
+> {
+    iter: ProgressRecorderIter<I, C>,
+    weight_fn: F,
+    weight_done: f64,
+    total_weight: f64,
+    /// Window size, and the recent `(secs since start, weight done)` samples
+    regression: Option<(usize, VecDeque<(f64, f64)>)>,
+}
+
+impl<I: Iterator, F, C: Clock> WeightedProgressIter<I, F, C> {
+    /// Calculate the `.eta()` by fitting a straight line (least squares) to the weight done
+    /// over time for the last `window` items, and extrapolating to when the total weight will be
+    /// reached. This copes with items of different weights, and with the speed drifting over a
+    /// long run. The ETA is `None` until `window` items have been seen. `None` to disable.
+    pub fn with_eta_from_weighted_regression(self, window: impl Into<Option<usize>>) -> Self {
+        let mut new = self;
+        new.regression = window
+            .into()
+            .map(|window| (window.max(2), VecDeque::with_capacity(window.max(2))));
+        new
+    }
+
+    /// Gets the original iterator back, consuming this.
+    pub fn into_inner(self) -> I {
+        self.iter.0.into_inner()
+    }
+}
+
+impl<I, F, C: Clock> Deref for WeightedProgressIter<I, F, C> {
+    type Target = ProgressRecorderIter<I, C>;
+    fn deref(&self) -> &ProgressRecorderIter<I, C> {
+        &self.iter
+    }
+}
+
+impl<I, F, C: Clock> DerefMut for WeightedProgressIter<I, F, C> {
+    fn deref_mut(&mut self) -> &mut ProgressRecorderIter<I, C> {
+        &mut self.iter
+    }
+}
+
+/// Least squares fit of `(x, y)` points, returning the `(intercept, slope)`
+fn linear_regression(points: &VecDeque<(f64, f64)>) -> Option<(f64, f64)> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let (mut covariance, mut variance) = (0., 0.);
+    for (x, y) in points {
+        covariance += (x - mean_x) * (y - mean_y);
+        variance += (x - mean_x) * (x - mean_x);
+    }
+    if variance == 0. {
+        return None;
+    }
+    let slope = covariance / variance;
+    Some((mean_y - slope * mean_x, slope))
+}
+
+impl<I, F, C: Clock> Iterator for WeightedProgressIter<I, F, C>
+where
+    I: Iterator,
+    F: FnMut(&I::Item) -> f64,
+{
+    type Item = (ProgressRecord<C::Instant>, I::Item);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (mut state, item) = self.iter.next()?;
+        self.weight_done += (self.weight_fn)(&item);
+        state.weight_done = self.weight_done;
+        state.total_weight = Some(self.total_weight);
+
+        if let Some((window, samples)) = &mut self.regression {
+            let secs = state.duration_since_start().as_secs_f64();
+            if samples.len() == *window {
+                samples.pop_front();
+            }
+            samples.push_back((secs, self.weight_done));
+            if samples.len() == *window {
+                state.model_eta = linear_regression(samples)
+                    .filter(|(_, slope)| *slope > 0.)
+                    .map(|(intercept, slope)| {
+                        let finish_secs = (self.total_weight - intercept) / slope;
+                        Duration::from_secs_f64((finish_secs - secs).max(0.))
+                    });
+            }
+        }
+
+        Some((state, item))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// Iterator which counts bytes as well as items. Created with `.with_bytes(...)`
+pub struct ByteProgressIter<
+    I,
+    F,
+    #[cfg(feature = "std")] C: Clock = StdClock,
+    #[cfg(not(feature = "std"))] C: Clock,
+> {
+    iter: ProgressRecorderIter<I, C>,
+    bytes_fn: F,
+    bytes_done: u64,
+    byte_total: Option<u64>,
+}
+
+impl<I: Iterator, F, C: Clock> ByteProgressIter<I, F, C> {
+    /// Gets the original iterator back, consuming this.
+    pub fn into_inner(self) -> I {
+        self.iter.0.into_inner()
+    }
+}
+
+impl<I, F, C: Clock> Deref for ByteProgressIter<I, F, C> {
+    type Target = ProgressRecorderIter<I, C>;
+    fn deref(&self) -> &ProgressRecorderIter<I, C> {
+        &self.iter
+    }
+}
+
+impl<I, F, C: Clock> DerefMut for ByteProgressIter<I, F, C> {
+    fn deref_mut(&mut self) -> &mut ProgressRecorderIter<I, C> {
+        &mut self.iter
+    }
+}
+
+impl<I, F, C: Clock> Iterator for ByteProgressIter<I, F, C>
+where
+    I: Iterator,
+    F: FnMut(&I::Item) -> u64,
+{
+    type Item = (ProgressRecord<C::Instant>, I::Item);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (mut state, item) = self.iter.next()?;
+        self.bytes_done += (self.bytes_fn)(&item);
+        state.bytes_done = Some(self.bytes_done);
+        state.byte_total = self.byte_total;
+        Some((state, item))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// Iterator which only counts the items which match a predicate. Created with
+/// `.with_count_predicate(...)`
+pub struct CountPredicateIter<
+    I,
+    F,
+    #[cfg(feature = "std")] C: Clock = StdClock,
+    #[cfg(not(feature = "std"))] C: Clock,
+> {
+    iter: ProgressRecorderIter<I, C>,
+    predicate: F,
+}
+
+impl<I: Iterator, F, C: Clock> CountPredicateIter<I, F, C> {
+    /// Gets the original iterator back, consuming this.
+    pub fn into_inner(self) -> I {
+        self.iter.0.into_inner()
+    }
+}
+
+impl<I, F, C: Clock> Deref for CountPredicateIter<I, F, C> {
+    type Target = ProgressRecorderIter<I, C>;
+    fn deref(&self) -> &ProgressRecorderIter<I, C> {
+        &self.iter
+    }
+}
+
+impl<I, F, C: Clock> DerefMut for CountPredicateIter<I, F, C> {
+    fn deref_mut(&mut self) -> &mut ProgressRecorderIter<I, C> {
+        &mut self.iter
+    }
+}
+
+impl<I, F, C: Clock> Iterator for CountPredicateIter<I, F, C>
+where
+    I: Iterator,
+    F: FnMut(&I::Item) -> bool,
+{
+    type Item = (ProgressRecord<C::Instant>, I::Item);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let recorder = &mut self.iter.0;
+        let item = match recorder.iter.next() {
+            Some(item) => item,
+            None => {
+                recorder.finish();
+                return None;
+            }
+        };
+        let counted = (self.predicate)(&item);
+        // we know there is always a record generated
+        Some((recorder.generate_record_counting(counted).unwrap(), item))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<I, T, E, C: Clock> ProgressRecorderIter<I, C>
+where
+    I: Iterator<Item = Result<T, E>>,
+{
+    /// Count how many of the items are `Err`, for `ProgressRecord::num_errors()` and
+    /// `ProgressRecord::error_rate()`. Errors are still counted in `.num_done()`.
+    pub fn with_error_counting(self) -> ErrorCountingIter<I, C> {
+        ErrorCountingIter { iter: self }
+    }
+}
+
+/// Iterator which counts how many of the `Result`s are `Err`. Created with
+/// `.with_error_counting()`.
+pub struct ErrorCountingIter<
+    I,
+    #[cfg(feature = "std")] C: Clock = StdClock,
+    #[cfg(not(feature = "std"))] C: Clock,
+> {
+    iter: ProgressRecorderIter<I, C>,
+}
+
+impl<I: Iterator, C: Clock> ErrorCountingIter<I, C> {
+    /// Gets the original iterator back, consuming this.
+    pub fn into_inner(self) -> I {
+        self.iter.0.into_inner()
+    }
+}
+
+impl<I, C: Clock> Deref for ErrorCountingIter<I, C> {
+    type Target = ProgressRecorderIter<I, C>;
+    fn deref(&self) -> &ProgressRecorderIter<I, C> {
+        &self.iter
+    }
+}
+
+impl<I, C: Clock> DerefMut for ErrorCountingIter<I, C> {
+    fn deref_mut(&mut self) -> &mut ProgressRecorderIter<I, C> {
+        &mut self.iter
+    }
+}
+
+impl<I, T, E, C: Clock> Iterator for ErrorCountingIter<I, C>
+where
+    I: Iterator<Item = Result<T, E>>,
+{
+    type Item = (ProgressRecord<C::Instant>, Result<T, E>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let recorder = &mut self.iter.0;
+        let item = match recorder.iter.next() {
+            Some(item) => item,
+            None => {
+                recorder.finish();
+                return None;
+            }
+        };
+        if item.is_err() {
+            recorder.num_errors += 1;
+        }
+        // we know there is always a record generated
+        Some((recorder.generate_record().unwrap(), item))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// Iterator which carries state along with the progress. Created with `.scan_progress(...)`.
+pub struct ScanProgress<
+    I,
+    St,
+    F,
+    #[cfg(feature = "std")] C: Clock = StdClock,
+    #[cfg(not(feature = "std"))] C: Clock,
+> {
+    iter: ProgressRecorderIter<I, C>,
+    state: St,
+    f: F,
+    finished: bool,
+}
+
+impl<I, St, B, F, C: Clock> Iterator for ScanProgress<I, St, F, C>
+where
+    I: Iterator,
+    F: FnMut(&mut St, &ProgressRecord<C::Instant>, I::Item) -> Option<B>,
+{
+    type Item = B;
+
+    fn next(&mut self) -> Option<B> {
+        if self.finished {
+            return None;
+        }
+        let (state, item) = self.iter.next()?;
+        let res = (self.f)(&mut self.state, &state, item);
+        self.finished = res.is_none();
+        res
+    }
+}
+
+/// Iterator which returns the `ProgressRecord` in an `Arc`. Created with `.shared()` or
+/// `.progress_shared()`.
+pub struct SharedProgressIter<
+    I,
+    #[cfg(feature = "std")] C: Clock = StdClock,
+    #[cfg(not(feature = "std"))] C: Clock,
+> {
+    iter: ProgressRecorderIter<I, C>,
+}
+
+impl<I: Iterator, C: Clock> SharedProgressIter<I, C> {
+    /// Gets the original iterator back, consuming this.
+    pub fn into_inner(self) -> I {
+        self.iter.0.into_inner()
+    }
+}
+
+impl<I, C: Clock> Deref for SharedProgressIter<I, C> {
+    type Target = ProgressRecorderIter<I, C>;
+    fn deref(&self) -> &ProgressRecorderIter<I, C> {
+        &self.iter
+    }
+}
+
+impl<I, C: Clock> DerefMut for SharedProgressIter<I, C> {
+    fn deref_mut(&mut self) -> &mut ProgressRecorderIter<I, C> {
+        &mut self.iter
+    }
+}
+
+impl<I: Iterator, C: Clock> Iterator for SharedProgressIter<I, C> {
+    type Item = (Arc<ProgressRecord<C::Instant>>, I::Item);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter
+            .next()
+            .map(|(state, item)| (Arc::new(state), item))
+    }
+
+    #[inline]
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.iter
+            .nth(n)
+            .map(|(state, item)| (Arc::new(state), item))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// Iterator over overlapping windows of `size` items, with the progress in windows. Created with
+/// `.progress_windows(...)`.
+pub struct ProgressWindows<
+    I: Iterator,
+    #[cfg(feature = "std")] C: Clock = StdClock,
+    #[cfg(not(feature = "std"))] C: Clock,
+> {
+    iter: ProgressRecorderIter<I, C>,
+    size: usize,
+    window: VecDeque<I::Item>,
+}
+
+impl<I: Iterator, C: Clock> ProgressWindows<I, C> {
+    /// Assume there will be `size` windows, like `ProgressRecorderIter::assume_size`, for when
+    /// the iterator doesn't know its size.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..).progress_windows(3).assume_size(10);
+    /// let (state, window) = progressor.next().unwrap();
+    /// assert_eq!(window, vec![0, 1, 2]);
+    /// assert_eq!(state.fraction(), Some(0.1));
+    /// # }
+    /// ```
+    pub fn assume_size(self, size: usize) -> Self {
+        ProgressWindows {
+            iter: self.iter.assume_size(size),
+            ..self
+        }
+    }
+}
+
+impl<I: Iterator, C: Clock> Deref for ProgressWindows<I, C> {
+    type Target = ProgressRecorderIter<I, C>;
+    fn deref(&self) -> &ProgressRecorderIter<I, C> {
+        &self.iter
+    }
+}
+
+impl<I: Iterator, C: Clock> DerefMut for ProgressWindows<I, C> {
+    fn deref_mut(&mut self) -> &mut ProgressRecorderIter<I, C> {
+        &mut self.iter
+    }
+}
+
+impl<I, C: Clock> Iterator for ProgressWindows<I, C>
+where
+    I: Iterator,
+    I::Item: Clone,
+{
+    type Item = (ProgressRecord<C::Instant>, Vec<I::Item>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let recorder = &mut self.iter.0;
+        loop {
+            match recorder.iter.next() {
+                Some(item) => {
+                    if self.window.len() == self.size {
+                        self.window.pop_front();
+                    }
+                    self.window.push_back(item);
+                }
+                None => {
+                    recorder.finish();
+                    return None;
+                }
+            }
+            if self.window.len() == self.size {
+                break;
+            }
+        }
+        // Every item left makes one more window, so the size hint of the inner iterator gives
+        // the number of windows left.
+        let state = recorder.generate_record().unwrap();
+        Some((state, self.window.iter().cloned().collect()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.iter.size_hint();
+        // How many more items are needed for the first window
+        let needed = (self.size - self.window.len()).saturating_sub(1);
+        (
+            lower.saturating_sub(needed),
+            upper.map(|upper| upper.saturating_sub(needed)),
+        )
+    }
+}
+
+/// An iterator that records it's progress as it goes along
+#[cfg(feature = "std")]
+pub trait ProgressableIter<I> {
+    fn progress(self) -> ProgressRecorderIter<I>;
+
+    /// Like `.progress()`, but the total is the `.len()` at the start, so the `.fraction()` is
+    /// always known.
+    fn progress_exact(self) -> ProgressRecorderIter<I>
+    where
+        I: ExactSizeIterator;
+
+    /// Like `.progress().assume_size(size)`, for when you know the total.
+    fn progress_with_size(self, size: usize) -> ProgressRecorderIter<I>;
+
+    /// Like `.progress()`, with all the settings in `config`. Since the `min_update_interval`
+    /// can skip records, this is an `OptionalProgressRecorderIter`.
+    fn progress_with_config(self, config: ProgressConfig) -> OptionalProgressRecorderIter<I>;
+
+    /// Like `.progress()`, but the record is in an `Arc`. See `ProgressRecorderIter::shared()`.
+    fn progress_shared(self) -> SharedProgressIter<I>
+    where
+        I: Iterator;
+
+    /// For a `.cycle()`d iterator, stop after `passes` passes of `base_len` items, with that as
+    /// the total.
+    fn progress_cycles(self, base_len: usize, passes: usize) -> ProgressRecorderIter<Take<I>>
+    where
+        I: Iterator;
+
+    /// Overlapping windows of `size` items (like `slice::windows`), where the progress counts
+    /// windows.
+    fn progress_windows(self, size: usize) -> ProgressWindows<I>
+    where
+        I: Iterator,
+        I::Item: Clone;
+}
+
+#[cfg(feature = "std")]
+impl<I> ProgressableIter<I> for I
+where
+    I: Iterator,
+{
+    /// Convert an iterator into a `ProgressRecorderIter`.
+    fn progress(self) -> ProgressRecorderIter<I> {
+        ProgressRecorderIter::new(self)
+    }
+
+    /// Convert an `ExactSizeIterator` into a `ProgressRecorderIter`, using its `.len()` as the
+    /// total.
+    ///
+    /// ```
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = vec!['a', 'b', 'c', 'd'].into_iter().progress_exact();
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.fraction(), Some(0.25));
+    /// ```
+    fn progress_exact(self) -> ProgressRecorderIter<I>
+    where
+        I: ExactSizeIterator,
+    {
+        let len = self.len();
+        ProgressRecorderIter::new(self).assume_size(len)
+    }
+
+    /// Convert an iterator into a `ProgressRecorderIter`, with `size` items in total.
+    ///
+    /// ```
+    /// # use iter_progress::ProgressableIter;
+    /// let lines = "a\nb\nc\nd".lines();
+    /// let mut progressor = lines.progress_with_size(4);
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.fraction(), Some(0.25));
+    /// ```
+    fn progress_with_size(self, size: usize) -> ProgressRecorderIter<I> {
+        ProgressRecorderIter::new(self).assume_size(size)
+    }
+
+    /// Convert an iterator into an `OptionalProgressRecorderIter`, which generates a record for
+    /// every item (unless `config.min_update_interval` is set), with the settings from
+    /// `config`.
+    ///
+    /// ```
+    /// # use iter_progress::{ProgressableIter, ProgressConfig};
+    /// let config = ProgressConfig {
+    ///     assumed_size: Some(4),
+    ///     ..ProgressConfig::default()
+    /// };
+    /// let mut progressor = "a b c d".split(' ').progress_with_config(config);
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.unwrap().fraction(), Some(0.25));
+    /// ```
+    fn progress_with_config(self, config: ProgressConfig) -> OptionalProgressRecorderIter<I> {
+        OptionalProgressRecorderIter::new(self, 1)
+            .with_rolling_average(config.rolling_average)
+            .with_exp_average(config.exp_average)
+            .assume_size(config.assumed_size)
+            .with_min_update_interval(config.min_update_interval)
+    }
+
+    /// Convert an iterator into a `SharedProgressIter`, which returns the `ProgressRecord` in an
+    /// `Arc`.
+    fn progress_shared(self) -> SharedProgressIter<I> {
+        ProgressRecorderIter::new(self).shared()
+    }
+
+    /// Iterate over a `.cycle()`d iterator (which never ends, so has no useful `.size_hint()`)
+    /// for `passes` passes of its `base_len` items, so the total is `base_len * passes`, and the
+    /// `.fraction()` & ETA are over all the passes. `.num_done()` counts items from all passes.
+    ///
+    /// ```
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = vec!['a', 'b', 'c'].into_iter().cycle().progress_cycles(3, 2);
+    /// let (state, c) = progressor.nth(4).unwrap();
+    /// assert_eq!(c, 'b');
+    /// assert_eq!(state.num_done(), 5);
+    /// assert_eq!(state.total(), Some(6));
+    /// ```
+    fn progress_cycles(self, base_len: usize, passes: usize) -> ProgressRecorderIter<Take<I>> {
+        ProgressRecorderIter::new(self.take(base_len.saturating_mul(passes)))
+    }
+
+    /// Iterate over overlapping windows of `size` items, as `Vec`s, like `slice::windows`. The
+    /// progress is in windows, i.e. `.num_done()` is how many windows there have been, and the
+    /// total is the number of items minus `size - 1`. There are no windows if there are fewer
+    /// than `size` items. For an iterator of unknown size, `ProgressWindows::assume_size(...)`
+    /// sets the number of windows.
+    ///
+    /// ```
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..5).progress_windows(3);
+    /// let (state, window) = progressor.next().unwrap();
+    /// assert_eq!(window, vec![0, 1, 2]);
+    /// assert_eq!(state.num_done(), 1);
+    /// assert_eq!(state.fraction(), Some(1. / 3.));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    fn progress_windows(self, size: usize) -> ProgressWindows<I>
+    where
+        I::Item: Clone,
+    {
+        assert!(size != 0, "window size must be non-zero");
+        ProgressWindows {
+            iter: ProgressRecorderIter::new(self),
+            size,
+            window: VecDeque::with_capacity(size),
+        }
+    }
+}
+
+impl<I, C: Clock> Iterator for ProgressRecorderIter<I, C>
+where
+    I: Iterator,
+{
+    type Item = (ProgressRecord<C::Instant>, <I as Iterator>::Item);
+
+    #[inline]
+    fn next(&mut self) -> Option<(ProgressRecord<C::Instant>, <I as Iterator>::Item)> {
+        match self.0.iter.next() {
+            // we know there is always a record generated
+            Some(a) => Some((self.0.generate_record().unwrap(), a)),
+            None => {
+                self.0.finish();
+                None
+            }
+        }
+    }
+
+    /// Skips `n` items of the inner iterator in one go (which is much faster for some
+    /// iterators), generating only one `ProgressRecord`, for the returned item. The skipped
+    /// items are still counted in `.num_done()`. `.skip(...)` uses this.
+    #[inline]
+    fn nth(&mut self, n: usize) -> Option<(ProgressRecord<C::Instant>, <I as Iterator>::Item)> {
+        match self.0.nth_counted(n) {
+            // we know there is always a record generated
+            Some(a) => Some((self.0.generate_record().unwrap(), a)),
+            None => {
+                self.0.finish();
+                None
+            }
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.remaining_size_hint()
+    }
+
+    #[inline]
+    fn count(self) -> usize {
+        self.0.iter.count()
+    }
+}
+
+/// Each item of the inner iterator is one item of this, so it's exactly as long.
+impl<I: ExactSizeIterator, C: Clock> ExactSizeIterator for ProgressRecorderIter<I, C> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.0.iter.len()
+    }
+}
+
+/// Items from the back are counted the same as items from the front, so e.g. with `.rev()`,
+/// `.fraction()` works as usual. If items are taken from both ends, `.num_done()` and
+/// `.fraction()` are about how many items have been taken in total, not a position.
+///
+/// ```
+/// # #[cfg(feature = "std")] {
+/// # use iter_progress::ProgressableIter;
+/// let mut progressor = vec!['a', 'b', 'c', 'd'].into_iter().progress().rev();
+/// let (state, c) = progressor.next().unwrap();
+/// assert_eq!(c, 'd');
+/// assert_eq!(state.fraction(), Some(0.25));
+/// # }
+/// ```
+impl<I: DoubleEndedIterator, C: Clock> DoubleEndedIterator for ProgressRecorderIter<I, C> {
+    #[inline]
+    fn next_back(&mut self) -> Option<(ProgressRecord<C::Instant>, <I as Iterator>::Item)> {
+        match self.0.iter.next_back() {
+            // we know there is always a record generated
+            Some(a) => Some((self.0.generate_record().unwrap(), a)),
+            None => {
+                self.0.finish();
+                None
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<I: Iterator> OptionalProgressRecorderIter<I> {
+    pub fn new(iter: I, generate_every_count: usize) -> OptionalProgressRecorderIter<I> {
+        OptionalProgressRecorderIter::with_clock(iter, generate_every_count, StdClock)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<I: Iterator, C: Clock<Instant = Instant>> OptionalProgressRecorderIter<I, C> {
+    /// Only the progress, as a `ProgressSnapshot` for each record which is generated (e.g. every
+    /// `generate_every_count` items), for a sparse time series of the progress. All the items
+    /// are still taken from the underlying iterator, and are dropped.
+    ///
+    /// ```
+    /// # use iter_progress::OptionalProgressableIter;
+    /// let counts: Vec<_> = (0..10)
+    ///     .optional_progress(5)
+    ///     .snapshots()
+    ///     .map(|snapshot| snapshot.num_done)
+    ///     .collect();
+    /// assert_eq!(counts, vec![5, 10]);
+    /// ```
+    pub fn snapshots(self) -> impl Iterator<Item = ProgressSnapshot> {
+        self.filter_map(|(state, _item)| state.map(|state| state.snapshot()))
+    }
+}
+
+impl<I: Iterator, C: Clock> OptionalProgressRecorderIter<I, C> {
+    /// Like `OptionalProgressRecorderIter::new`, but gets the time from `clock`. See
+    /// `ProgressRecorderIter::with_clock`.
+    pub fn with_clock(
+        iter: I,
+        generate_every_count: usize,
+        clock: C,
+    ) -> OptionalProgressRecorderIter<I, C> {
+        let started_iterating = clock.now();
+        OptionalProgressRecorderIter {
+            iter,
+            clock,
+            count: 0,
+            generate_every_count,
+            started_iterating,
+            previous_record_tm: None,
+            previous_record_count: 0,
+            skip_next_duration_sample: false,
+            rolling_average: None,
+            exp_average: None,
+            weighted_rolling_average: None,
+            windowed_rate: None,
+            assumed_size: None,
+            ignore_size_hint: false,
+            position_fraction: None,
+            bytes: None,
+            sample_every_bytes: None,
+            #[cfg(feature = "std")]
+            summary_if_longer_than: None,
+            #[cfg(feature = "std")]
+            start_message: None,
+            #[cfg(feature = "std")]
+            output: Output::Stdout,
+            on_finish: None,
+            min_update_interval: None,
+            adaptive_stride: None,
+            time_budget: None,
+            display_total: None,
+            #[cfg(feature = "std")]
+            started_wall: SystemTime::now(),
+            #[cfg(feature = "std")]
+            eta_display: EtaDisplay::default(),
+            display_damping: None,
+            count_multiplier: 1,
+            timestamps: None,
+            duration_history: None,
+            cost_model: None,
+            idle: None,
+            num_errors: 0,
+            safe_math: false,
+            buffer_depth: None,
+            #[cfg(feature = "std")]
+            status_line: Mutex::new((None, 0)),
+            rate_unit: None,
+            #[cfg(feature = "std")]
+            interrupt_summary: false,
+            #[cfg(all(feature = "interrupt", unix))]
+            sigint_guard: None,
+            #[cfg(feature = "std")]
+            completion_log: None,
+            #[cfg(feature = "tracing")]
+            trace_every: None,
+            #[cfg(feature = "log")]
+            log_every: None,
+            previous_smoothed_rate: None,
+            fraction_map: None,
+            #[cfg(feature = "std")]
+            handle: None,
+            #[cfg(feature = "std")]
+            smoothing_samples: None,
+            check_tms: (0, None, None),
+            eta_settings: EtaSettings::default(),
+            previous_eta: None,
+        }
+    }
+
+    /// Set the desired size of the rolling average window calculation (if any). `None` to
+    /// disable.
+    /// Larger values slow down each iteration (since the rolling average is calculated each
+    /// iteration).
+    pub fn with_rolling_average(self, size: impl Into<Option<usize>>) -> Self {
+        let mut res = self;
+        res.rolling_average = size
+            .into()
+            .filter(|&size| size > 0)
+            .map(|size| (size, VecDeque::with_capacity(size)));
+        res
+    }
+
+    /// Set the desired exponential rate
+    /// 0.001 is a good value.
+    pub fn with_exp_average(self, rate: impl Into<Option<f64>>) -> Self {
+        let mut res = self;
+        res.exp_average = rate.into().map(|rate| (rate, None));
+        res
+    }
+
+    /// Rather than a record every `generate_every_count` items, generate one for the first item,
+    /// and then double the stride after each record (i.e. after 2 more items, then 4, 8, …), up
+    /// to `max`. So there are lots of updates at the start, and fewer later on. See `.stride()`
+    /// for the current stride.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::OptionalProgressableIter;
+    /// let progressor = (0..100).optional_progress(1).with_adaptive_stride(16);
+    /// let generated: Vec<_> = progressor
+    ///     .filter_map(|(state, _)| state)
+    ///     .map(|state| state.num_done())
+    ///     .collect();
+    /// assert_eq!(generated, vec![1, 3, 7, 15, 31, 47, 63, 79, 95]);
+    /// # }
+    /// ```
+    pub fn with_adaptive_stride(self, max: usize) -> Self {
+        let mut new = self;
+        new.generate_every_count = 1;
+        new.adaptive_stride = Some((max.max(1), new.count + 1));
+        new
+    }
+
+    /// How many items until the next record, after the previous one, i.e. the
+    /// `generate_every_count`, or the current stride from `.with_adaptive_stride(...)`.
+    pub fn stride(&self) -> usize {
+        self.generate_every_count
+    }
+
+    /// Only generate a record if at least `interval` has passed since the previous record (or,
+    /// for the first one, since the start). This is as well as the "every N items" rule, so
+    /// both have to allow it, and with `.optional_progress(1)`, it's purely by time. Useful if
+    /// items take wildly different amounts of time. `None` to disable.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::OptionalProgressableIter;
+    /// # use std::time::Duration;
+    /// let progressor = (0..1_000)
+    ///     .optional_progress(1)
+    ///     .with_min_update_interval(Duration::from_secs(1));
+    /// for (state, _) in progressor {
+    ///     if let Some(state) = state {
+    ///         println!("{:.1}% done", state.percent().unwrap());
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn with_min_update_interval(self, interval: impl Into<Option<Duration>>) -> Self {
+        let mut new = self;
+        new.min_update_interval = interval.into();
+        new
+    }
+
+    /// Also calculate a rolling average over the last `size` items, where recent items count
+    /// more, see `ProgressRecord::weighted_rolling_rate()`. `None` to disable. The weights
+    /// decay linearly: the most recent item has weight `size`, the one before `size - 1`, and so
+    /// on down to 1 for the oldest. So it reacts to a change of speed quicker than the flat
+    /// `.with_rolling_average(...)`, but it still forgets old items completely, unlike
+    /// `.with_exp_average(...)`.
+    pub fn with_weighted_rolling_average(self, size: impl Into<Option<usize>>) -> Self {
+        let mut res = self;
+        res.weighted_rolling_average = size
+            .into()
+            .filter(|&size| size > 0)
+            .map(|size| (size, VecDeque::with_capacity(size)));
+        res
+    }
+
+    /// Also calculate the rate over the last `window` of time, see
+    /// `ProgressRecord::windowed_rate()`. Unlike `.with_rolling_average(...)`, which is over a
+    /// number of items, this always covers the same amount of time, however long each item
+    /// takes. `None` to disable.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// # use std::time::Duration;
+    /// let mut progressor = (0..100).progress().with_windowed_rate(Duration::from_secs(10));
+    /// progressor.next();
+    /// let (state, _) = progressor.next().unwrap();
+    /// println!("{:?} per sec in the last 10 sec", state.windowed_rate());
+    /// # }
+    /// ```
+    pub fn with_windowed_rate(self, window: impl Into<Option<Duration>>) -> Self {
+        let mut res = self;
+        res.windowed_rate = window.into().map(|window| (window, VecDeque::new()));
+        res
+    }
+
+    /// Add an 'assumed size' to this iterator. If the iterator doesn't return an exact value for
+    /// `.size_hint()`, you can use this to override
+    /// the `.size_hint()` from the iterator will override this if it returns an exact size (i.e.
+    /// `.size_hint().1 == Some(...size_hint().0).
+    /// Set to `None` to undo this.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..).progress().assume_size(10);
+    /// let (state, num) = progressor.next().unwrap();
+    /// assert_eq!(state.fraction(), Some(0.1));
+    /// # }
+    /// ```
+    pub fn assume_size(self, size: impl Into<Option<usize>>) -> Self {
+        let mut new = self;
+        new.assumed_size = size.into();
+        new
+    }
+
+    /// Don't return an `.eta()` until at least `n` items have been seen. The first few items are
+    /// rarely representative.
+    pub fn with_eta_min_samples(self, n: usize) -> Self {
+        let mut new = self;
+        new.eta_settings.min_samples = n as u64;
+        new
+    }
+
+    /// Treat any `.eta()` longer than `cap` as unknown (`None`), rather than returning absurd
+    /// values. `None` to disable.
+    pub fn with_eta_cap(self, cap: impl Into<Option<Duration>>) -> Self {
+        let mut new = self;
+        new.eta_settings.cap = cap.into();
+        new
+    }
+
+    /// If there was more than `stall` between this item and the previous one, keep returning the
+    /// previous `.eta()`, rather than jumping up because of one slow item. `None` to disable.
+    pub fn with_eta_hold_on_stall(self, stall: impl Into<Option<Duration>>) -> Self {
+        let mut new = self;
+        new.eta_settings.hold_on_stall = stall.into();
+        new
+    }
+
+    /// Round the `.eta()` to the nearest multiple of `granularity`. `None` to disable.
+    pub fn with_eta_granularity(self, granularity: impl Into<Option<Duration>>) -> Self {
+        let mut new = self;
+        new.eta_settings.granularity = granularity.into();
+        new
+    }
+
+    /// Make the `.eta()` count down steadily, like a countdown timer, rather than going up & down
+    /// with every item. Each ETA is the previous ETA minus the time since the previous record,
+    /// unless the newly calculated ETA is more than 10% more or less than that, i.e. when things
+    /// have really slowed down or sped up.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..1_000).progress().with_monotonic_eta_countdown();
+    /// let (state, _) = progressor.next().unwrap();
+    /// println!("{:?} left", state.eta());
+    /// # }
+    /// ```
+    pub fn with_monotonic_eta_countdown(self) -> Self {
+        let mut new = self;
+        new.eta_settings.monotonic_countdown = true;
+        new
+    }
+
+    /// Turn on sensible defaults to make the `.eta()` less jumpy: no ETA for the first 10 items,
+    /// ETAs over 30 days are unknown, the ETA is held when there is more than 5 sec between
+    /// items, and it is rounded to the nearest second.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..1_000).progress().with_stable_eta();
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.eta(), None);
+    /// # }
+    /// ```
+    pub fn with_stable_eta(self) -> Self {
+        self.with_eta_min_samples(10)
+            .with_eta_cap(Duration::from_secs(30 * 24 * 60 * 60))
+            .with_eta_hold_on_stall(Duration::from_secs(5))
+            .with_eta_granularity(Duration::from_secs(1))
+    }
+
+    /// Make sure the records never return infinite or NaN numbers (or panic) when there's
+    /// nothing sensible to return, e.g. no time has passed, the total is 0, or the position set
+    /// with `.set_position(...)` is NaN. With this:
+    ///
+    /// * `.rate()` is 0 if it would be infinite or NaN
+    /// * `.rolling_average_rate()`, `.exp_average_rate()` and `.smoothed_rate()` are `None` if
+    ///   they would be infinite or NaN
+    /// * `.fraction()` (and so `.percent()`) is `None` if it would be infinite or NaN
+    /// * `.eta()` and `.estimated_total_time()` are `None` if the fraction is `None` (they're
+    ///   always `None` if the fraction is 0 or not finite, or the time is too long for a
+    ///   `Duration`)
+    pub fn with_safe_math(self) -> Self {
+        let mut new = self;
+        new.safe_math = true;
+        new
+    }
+
+    /// Remember the unit chosen by `ProgressRecord::rate_best_unit()`, and only change it when the
+    /// rate is well past the boundary, so it doesn't flip back & forth between units.
+    pub fn with_auto_rate_unit(self) -> Self {
+        let mut new = self;
+        new.rate_unit = Some(None);
+        new
+    }
+
+    /// When Ctrl-C is pressed, print how far along we got (to the `.with_output(...)`), and then
+    /// stop the process as normal. This catches SIGINT, and only prints when the next item is
+    /// asked for, since very little can be safely done inside a signal handler. So if an item
+    /// takes a long time, so does stopping. Only on unix, with the `interrupt` feature.
+    ///
+    /// # Signal handling
+    ///
+    /// This replaces the SIGINT handler for the **whole process**, not just this iterator, so
+    /// while it's in use, Ctrl-C anywhere in the program won't do anything until this iterator
+    /// is asked for its next item. Then, after the summary, the SIGINT is passed on to the
+    /// handler from before, so if the program had its own, that is still called, and otherwise
+    /// the process stops as normal. When the iterator is finished or dropped, the handler from
+    /// before is put back (once no other iterator with `.with_interrupt_summary()` is still in
+    /// use). Don't change the SIGINT handler elsewhere while it's in use, or that handler will
+    /// be replaced when this one is put back.
+    #[cfg(feature = "std")]
+    #[cfg(all(feature = "interrupt", unix))]
+    pub fn with_interrupt_summary(self) -> Self {
+        let mut new = self;
+        new.interrupt_summary = true;
+        if new.sigint_guard.is_none() {
+            new.sigint_guard = Some(install_sigint_handler());
+        }
+        new
+    }
+
+    /// Send a `tracing` event at `level` every `n` sec (as decided by
+    /// `ProgressRecord::should_do_every_n_sec(...)`) as the records are generated, with the
+    /// fields `percent`, `rate`, `eta_secs` & `num`. Unknown values are left out. Only with the
+    /// `tracing` feature.
+    ///
+    /// ```
+    /// # use iter_progress::ProgressableIter;
+    /// for (_state, _i) in (0..1_000).progress().trace_every_n_sec(5., tracing::Level::INFO) {
+    ///     // ...
+    /// }
+    /// ```
+    #[cfg(feature = "tracing")]
+    pub fn trace_every_n_sec(self, n: f32, level: tracing::Level) -> Self {
+        let mut new = self;
+        new.trace_every = Some((n, level));
+        new
+    }
+
+    /// Log a progress line like `25.0% done, 10.5 per sec, ETA 3m 12s` at `level` every `n` sec
+    /// (as decided by `ProgressRecord::should_do_every_n_sec(...)`) as the records are
+    /// generated. Unknown values are `?`. Only with the `log` feature.
+    ///
+    /// ```
+    /// # use iter_progress::ProgressableIter;
+    /// for (_state, _i) in (0..1_000).progress().log_every_n_sec(5., log::Level::Info) {
+    ///     // ...
+    /// }
+    /// ```
+    #[cfg(feature = "log")]
+    pub fn log_every_n_sec(self, n: f32, level: log::Level) -> Self {
+        let mut new = self;
+        new.log_every = Some((n, level));
+        new
+    }
+
+    /// When the iterator is finished, append a line to the file at `path` (creating it if
+    /// needed), e.g. for a log of batch jobs. The line is like
+    /// `finished_at=1700000000 items=100 elapsed_secs=2.000 rate=50.000`, where `finished_at` is
+    /// the Unix time in seconds. If the file can't be written to, an error is printed to the
+    /// `.with_output(...)`, and iteration carries on as normal.
+    #[cfg(feature = "std")]
+    pub fn with_completion_log(self, path: impl AsRef<Path>) -> Self {
+        let mut new = self;
+        new.completion_log = Some(path.as_ref().to_path_buf());
+        new
+    }
+
+    /// Change the `.fraction()` (and so the `.percent()`, `.eta()` etc.) with `f`, e.g. when a
+    /// job has several phases which should take up different parts of the progress bar. The
+    /// result is clamped to between 0 and 1.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// // The first half of the items are quick, so only show them as the first 20%
+    /// let mut progressor = (0..10)
+    ///     .progress()
+    ///     .with_fraction_map(|f| if f < 0.5 { f * 0.4 } else { 0.2 + (f - 0.5) * 1.6 });
+    /// let (state, _) = progressor.nth(4).unwrap();
+    /// assert_eq!(state.fraction(), Some(0.2));
+    /// # }
+    /// ```
+    pub fn with_fraction_map<F: Fn(f64) -> f64 + Send + Sync + 'static>(self, f: F) -> Self {
+        let mut new = self;
+        new.fraction_map = Some(Box::new(f));
+        new
+    }
+
+    /// Keep a `ProgressHandle` updated for every item, which other threads can poll for the
+    /// progress. Get it with `.handle()`.
+    #[cfg(feature = "std")]
+    pub fn with_handle(self) -> Self {
+        let mut new = self;
+        let handle = ProgressHandle::new(new.started_iterating);
+        let state = new.current_record_at(new.started_iterating, None);
+        handle.update(state.num_done_u64(), state.resolved_total());
+        new.handle = Some(handle);
+        new
+    }
+
+    /// A `ProgressHandle` for this iterator, if `.with_handle()` was used.
+    #[cfg(feature = "std")]
+    pub fn handle(&self) -> Option<ProgressHandle<C::Instant>> {
+        self.handle.clone()
+    }
+
+    /// Have `ProgressRecord::smoothed_rate()` use the since-start `.rate()` until the rolling or
+    /// exponential averages have enough data, and then blend them in, so there's always a rate
+    /// to show at the start. See `ProgressRecord::smoothed_rate()` for how they're blended.
+    #[cfg(feature = "std")]
+    pub fn with_smoothing_bootstrap(self) -> Self {
+        let mut new = self;
+        new.smoothing_samples = Some(0);
+        new
+    }
+
+    /// Treat any gap between items which is longer than `threshold` as idle time (e.g. waiting on
+    /// a rate limited API), which isn't counted towards the `.rate()` or `.eta()`. See
+    /// `ProgressRecord::idle_time()`. This checks the time for every item, even if no record is
+    /// generated. `None` to disable.
+    pub fn with_idle_threshold(self, threshold: impl Into<Option<Duration>>) -> Self {
+        let mut new = self;
+        new.idle = threshold
+            .into()
+            .map(|threshold| (threshold, Duration::ZERO, None));
+        new
+    }
+
+    /// Items don't all take the same time, but you know roughly how much each costs: `cost(i)`
+    /// is the expected relative cost of item `i` (starting at 0). The `.eta()` is then the cost
+    /// of the remaining items, at the cost per second so far. The total must be known. `cost` is
+    /// called once for each item done, and once for each item in the total, which is kept up to
+    /// date as the total changes (e.g. with `.add_discovered(...)`).
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// // Later items take longer
+    /// let mut progressor = (0..100).progress().with_cost_model(|i| (i + 1) as f64);
+    /// # }
+    /// ```
+    pub fn with_cost_model<F: Fn(usize) -> f64 + Send + Sync + 'static>(self, cost: F) -> Self {
+        let mut new = self;
+        new.cost_model = Some(CostModel {
+            cost: Box::new(cost),
+            num_costed: 0,
+            cost_done: 0.,
+            num_in_total: 0,
+            total_cost: 0.,
+        });
+        new
+    }
+
+    /// Record when each item was returned (as the time since the start), to get a timeline of
+    /// the whole run with `.timestamps()`. This is done for every item, even if no record is
+    /// generated. Memory use grows with the number of items, see `.with_timestamps_cap(...)`.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..10).progress().with_timestamps();
+    /// for _ in progressor.by_ref() {}
+    /// assert_eq!(progressor.timestamps().len(), 10);
+    /// # }
+    /// ```
+    pub fn with_timestamps(self) -> Self {
+        let mut new = self;
+        new.timestamps = Some((None, Vec::new()));
+        new
+    }
+
+    /// Like `.with_timestamps()`, but only keep the timestamps of the last `n` items.
+    pub fn with_timestamps_cap(self, n: usize) -> Self {
+        let mut new = self;
+        new.timestamps = Some((Some(n), Vec::with_capacity(2 * n)));
+        new
+    }
+
+    /// When each item was returned, as the duration since the start, oldest first, if
+    /// `.with_timestamps()` (or `.with_timestamps_cap(...)`) was set. Empty otherwise.
+    pub fn timestamps(&self) -> &[Duration] {
+        match &self.timestamps {
+            None => &[],
+            Some((None, timestamps)) => timestamps,
+            Some((Some(cap), timestamps)) => &timestamps[timestamps.len().saturating_sub(*cap)..],
+        }
+    }
+
+    /// An optimistic & pessimistic estimate of how long is left, e.g. "3–8 min (p10–p90)". The
+    /// time between each item is taken from the `.timestamps()`, and the `lo_pct` & `hi_pct`
+    /// percentiles (from 0 to 100) of those, times the number of items left, are returned.
+    /// `None` if `.with_timestamps()` (or `.with_timestamps_cap(...)`) isn't set, there are fewer
+    /// than 2 timestamps, or the total isn't known.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..100).progress().with_timestamps_cap(50);
+    /// for _ in 0..10 {
+    ///     progressor.next();
+    /// }
+    /// if let Some((lo, hi)) = progressor.eta_band(10., 90.) {
+    ///     println!("finish in {:?}–{:?} (p10–p90)", lo, hi);
+    /// }
+    /// # }
+    /// ```
+    pub fn eta_band(&self, lo_pct: f64, hi_pct: f64) -> Option<(Duration, Duration)> {
+        let mut durations: Vec<f64> = self
+            .timestamps()
+            .windows(2)
+            .map(|w| (w[1] - w[0]).as_secs_f64())
+            .collect();
+        if durations.is_empty() {
+            return None;
+        }
+        let remaining = self.current_record().items_remaining()? as f64;
+        durations.sort_by(|a, b| a.total_cmp(b));
+        let band = |pct: f64| Duration::from_secs_f64(percentile(&durations, pct) * remaining);
+        Some((band(lo_pct), band(hi_pct)))
+    }
+
+    /// Keep how long each of the last `k` items took, for `.rate_percentile(...)`. This is done
+    /// for every item, even if no record is generated.
+    pub fn with_duration_history(self, k: usize) -> Self {
+        let mut new = self;
+        new.duration_history = Some((k, VecDeque::with_capacity(k), None));
+        new
+    }
+
+    /// The `p` percentile (from 0 to 1, e.g. 0.5 for the median) of the rates of the last items
+    /// from `.with_duration_history(...)`, which unlike the rolling average, isn't thrown off by
+    /// a few very slow (or fast) items. `None` if `.with_duration_history(...)` isn't set, or
+    /// there are no items yet.
+    ///
+    /// This sorts a copy of the `k` durations each time it's called, so it's O(k log k).
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..100).progress().with_duration_history(50);
+    /// for _ in 0..10 {
+    ///     progressor.next();
+    /// }
+    /// println!("median {:?} items/sec", progressor.rate_percentile(0.5));
+    /// # }
+    /// ```
+    pub fn rate_percentile(&self, p: f64) -> Option<f64> {
+        let durations = match &self.duration_history {
+            Some((_k, durations, _last_item_tm)) if !durations.is_empty() => durations,
+            _ => return None,
+        };
+        let mut rates: Vec<f64> = durations.iter().map(|duration| 1. / duration).collect();
+        rates.sort_by(|a, b| a.total_cmp(b));
+        Some(percentile(&rates, p * 100.))
+    }
+
+    /// Each item from the iterator represents `k` units (e.g. a batch of 1,000 rows). The
+    /// `.num_done()`, `.rate()`, `.fraction()` etc. are then in units, so `.assume_size(...)`
+    /// should be the total number of units. The rolling & exponential averages are still per
+    /// item.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..5).progress().with_count_multiplier(1_000);
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.num_done(), 1_000);
+    /// assert_eq!(state.fraction(), Some(0.2));
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `k` is 0.
+    pub fn with_count_multiplier(self, k: usize) -> Self {
+        assert!(k > 0, "count multiplier must be at least 1");
+        let mut new = self;
+        new.count_multiplier = k as u64;
+        new
+    }
+
+    /// Smooth the `ProgressRecord::rate()` with an exponential average, so the number people see
+    /// doesn't jump around. Each new rate is weighted by `factor` (between 0 and 1, lower is
+    /// smoother), e.g. 0.2. This only changes `.rate()`, not the timings, averages or `.eta()`.
+    /// `None` to disable.
+    pub fn with_display_damping(self, factor: impl Into<Option<f64>>) -> Self {
+        let mut new = self;
+        new.display_damping = factor.into().map(|factor| (factor, None));
+        new
+    }
+
+    /// Set how `ProgressRecord::human_eta()` shows the ETA: how long is left (the default), the
+    /// time it should finish at, or both.
+    ///
+    /// ```
+    /// # use iter_progress::{EtaDisplay, ProgressableIter};
+    /// let mut progressor = (0..10).progress().with_eta_display(EtaDisplay::Both);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn with_eta_display(self, mode: EtaDisplay) -> Self {
+        let mut new = self;
+        new.eta_display = mode;
+        new
+    }
+
+    /// Show a different total to people (e.g. a rounded "of ~10,000") than the actual total.
+    /// This only changes `ProgressRecord::display_total()`, the `.fraction()` etc. still use the
+    /// actual total. `None` to disable.
+    pub fn with_display_total(self, total: impl Into<Option<usize>>) -> Self {
+        let mut new = self;
+        new.display_total = total.into();
+        new
+    }
+
+    /// Set how long this should take at most. This isn't enforced, but records can then tell you
+    /// if you're `.over_budget()`, or if you `.will_exceed_budget()`, e.g. to stop early. `None`
+    /// to disable.
+    pub fn with_time_budget(self, budget: impl Into<Option<Duration>>) -> Self {
+        let mut new = self;
+        new.time_budget = budget.into();
+        new
+    }
+
+    /// When the iterator is finished, print a summary line (how many items, how long it took,
+    /// and the rate), but only if it ran for longer than `threshold`, so quick runs stay quiet.
+    /// Use `Duration::ZERO` to always print it, or `None` to disable.
+    #[cfg(feature = "std")]
+    pub fn with_summary_if_longer_than(self, threshold: impl Into<Option<Duration>>) -> Self {
+        let mut new = self;
+        new.summary_if_longer_than = threshold.into();
+        new
+    }
+
+    /// Print `message` when the first item is reached, to go with the
+    /// `.with_summary_if_longer_than(...)` at the end. Any `{total}` in it is replaced with the
+    /// total number of items, or `?` if that isn't known.
+    ///
+    /// ```
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..5_000).progress().with_start_message("Processing {total} items...");
+    /// while let Some((_state, i)) = progressor.next() {
+    ///     // "Processing 5000 items..." was printed before the first item
+    ///     progressor.print_every_n_sec(1., format!("{}\n", i));
+    /// }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn with_start_message(self, message: impl Into<String>) -> Self {
+        let mut new = self;
+        new.start_message = Some(message.into());
+        new
+    }
+
+    /// Where to write the start message, the summaries, and any error writing the
+    /// `.with_completion_log(...)` (stdout by default). `Output::Sink` turns them off. Use `.output()` to write your own progress messages there too.
+    ///
+    /// ```
+    /// # use iter_progress::{Output, ProgressableIter};
+    /// use std::io::Write;
+    /// let mut progressor = (0..10)
+    ///     .progress()
+    ///     .with_output(Output::Stderr)
+    ///     .with_start_message("Starting");
+    /// while let Some((state, _)) = progressor.next() {
+    ///     if state.should_do_every_n_sec(1.) {
+    ///         writeln!(progressor.output(), "{} done", state.num_done()).unwrap();
+    ///     }
+    /// }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn with_output(self, output: Output) -> Self {
+        let mut new = self;
+        new.output = output;
+        new
+    }
+
+    /// The `Output` from `.with_output(...)`, to write to.
+    #[cfg(feature = "std")]
+    pub fn output(&mut self) -> &mut Output {
+        &mut self.output
+    }
+
+    /// The `.with_start_message(...)` to print, if there is one. Only returned once.
+    #[cfg(feature = "std")]
+    fn start_line(&mut self) -> Option<String> {
+        let message = self.start_message.take()?;
+        // The time doesn't matter for the total
+        let total = self
+            .current_record_at(self.started_iterating, None)
+            .total()
+            .map_or_else(|| "?".to_string(), |total| total.to_string());
+        Some(message.replace("{total}", &total))
+    }
+
+    /// Call `f` once, with the final record (i.e. the total count & time), when the inner
+    /// iterator has finished, i.e. the first time `.next()` returns `None`.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let progressor = (0..10).progress().on_finish(|state| {
+    ///     println!("Done {} items in {:?}", state.num_done(), state.duration_since_start());
+    /// });
+    /// for (_state, _i) in progressor {}
+    /// # }
+    /// ```
+    pub fn on_finish<F: FnOnce(&ProgressRecord<C::Instant>) + Send + Sync + 'static>(
+        self,
+        f: F,
+    ) -> Self {
+        let mut new = self;
+        new.on_finish = Some(Box::new(f));
+        new
+    }
+
+    /// Called when the inner iterator has finished.
+    fn finish(&mut self) {
+        #[cfg(all(feature = "interrupt", unix))]
+        {
+            // Ctrl-C should stop the process as normal again
+            self.sigint_guard = None;
+        }
+        let now = self.clock.now();
+        #[cfg(feature = "std")]
+        if let Some(summary) = self.summary(now) {
+            // Not worth failing the iteration for
+            let _ = writeln!(self.output, "{}", summary);
+        }
+        if let Some(on_finish) = self.on_finish.take() {
+            on_finish(&self.current_record_at(now, self.previous_record_tm));
+        }
+        #[cfg(feature = "std")]
+        if let Some(path) = self.completion_log.take() {
+            let line = self
+                .current_record_at(now, self.previous_record_tm)
+                .completion_line();
+            let written = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .and_then(|mut file| writeln!(file, "{}", line));
+            // The iteration itself worked, so don't fail because of the log
+            if let Err(err) = written {
+                let _ = writeln!(
+                    self.output,
+                    "Unable to write completion log to {}: {}",
+                    path.display(),
+                    err
+                );
+            }
+        }
+    }
+
+    /// The line to print if there has been a Ctrl-C since the last item, and
+    /// `.with_interrupt_summary()` is used.
+    #[cfg(feature = "std")]
+    fn interrupted_summary(&mut self) -> Option<String> {
+        if !self.interrupt_summary || !INTERRUPTED.swap(false, Ordering::SeqCst) {
+            return None;
+        }
+        let state = self.current_record_at(self.clock.now(), self.previous_record_tm);
+        let mut line = format!(
+            "Interrupted after {} items in {:.1} sec ({:.1} per sec)",
+            state.num_done_u64(),
+            state.duration_since_start().as_secs_f64(),
+            state.rate()
+        );
+        if let Some(percent) = state.percent() {
+            line.push_str(&format!(", {:.1}% done", percent));
+        }
+        Some(line)
+    }
+
+    /// The summary to print at the end, if there should be one. Only returned once.
+    #[cfg(feature = "std")]
+    fn summary(&mut self, now: C::Instant) -> Option<String> {
+        let threshold = self.summary_if_longer_than.take()?;
+        let state = self.current_record_at(now, self.previous_record_tm);
+        if state.duration_since_start() <= threshold {
+            return None;
+        }
+        Some(format!(
+            "Finished {} items in {:.1} sec ({:.1} per sec)",
+            state.num_done_u64(),
+            state.duration_since_start().as_secs_f64(),
+            state.rate()
+        ))
+    }
+
+    /// Report progress as a continuous position, rather than by counting items. e.g. for a
+    /// simulation which takes steps of different sizes. The `.fraction()` of all following
+    /// records is `pos / total` (clamped to `0.0..=1.0`), regardless of how many items there
+    /// have been.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..).progress();
+    /// progressor.set_position(2.5, 10.);
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.fraction(), Some(0.25));
+    /// # }
+    /// ```
+    pub fn set_position(&mut self, pos: f64, total: f64) {
+        self.position_fraction = Some((pos / total).clamp(0., 1.));
+    }
+
+    /// Set how many items are in a buffer (e.g. the bounded channel this iterator feeds), out of
+    /// its `capacity`, for `ProgressRecord::buffer_fraction()` on the following records.
+    pub fn set_buffer_depth(&mut self, depth: usize, capacity: usize) {
+        self.buffer_depth = Some((depth, capacity));
+    }
+
+    /// Add `n` to the assumed total (see `.assume_size(...)`), e.g. when walking a tree, and
+    /// more nodes are discovered as you go. The `.fraction()` is then the number done out of the
+    /// number discovered so far, which is only an estimate, and can go down when more are
+    /// discovered. The assumed size isn't used if the iterator has an exact `.size_hint()`.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..).progress();
+    /// // The root
+    /// progressor.add_discovered(1);
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.fraction(), Some(1.));
+    /// // The root had 3 children
+    /// progressor.add_discovered(3);
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.fraction(), Some(0.5));
+    /// # }
+    /// ```
+    pub fn add_discovered(&mut self, n: usize) {
+        self.assumed_size = Some(self.assumed_size.unwrap_or(0) + n);
+    }
+
+    /// Clear the rolling & exponential averages (keeping their settings), but not the count or
+    /// start time. Useful after a phase change, so the averages from the old phase don't affect
+    /// the new one. The time between the previous record and the next one isn't added to the
+    /// averages, but otherwise (e.g. for `.rate_recent()` & `.is_first()`) it's still the
+    /// previous record.
+    pub fn reset_averages(&mut self) {
+        if let Some((_size, values)) = &mut self.rolling_average {
+            values.clear();
+        }
+        if let Some((_rate, last)) = &mut self.exp_average {
+            *last = None;
+        }
+        if let Some((_size, values)) = &mut self.weighted_rolling_average {
+            values.clear();
+        }
+        if let Some((_window, samples)) = &mut self.windowed_rate {
+            samples.clear();
+        }
+        self.skip_next_duration_sample = true;
+    }
+
+    /// Start tracking the progress again from now, as if no items had been seen, e.g. after
+    /// seeking the underlying reader back to the start to retry. The count, start time, the
+    /// averages (keeping their settings), idle & error counts, the timestamps and duration
+    /// history are all cleared, as is the cost done for `.with_cost_model(...)`. The settings
+    /// (e.g. `.assume_size(...)`) are kept. A `.handle()` is restarted too.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..100).progress().with_rolling_average(5);
+    /// progressor.nth(9);
+    /// progressor.reset();
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert_eq!(state.num_done(), 1);
+    /// # }
+    /// ```
+    pub fn reset(&mut self) {
+        self.reset_averages();
+        self.previous_record_tm = None;
+        self.previous_record_count = 0;
+        self.count = 0;
+        self.started_iterating = self.clock.now();
+        #[cfg(feature = "std")]
+        {
+            self.started_wall = SystemTime::now();
+        }
+        if let Some(cost_model) = &mut self.cost_model {
+            cost_model.num_costed = 0;
+            cost_model.cost_done = 0.;
+        }
+        if let Some((_factor, last)) = &mut self.display_damping {
+            *last = None;
+        }
+        if let Some((_cap, timestamps)) = &mut self.timestamps {
+            timestamps.clear();
+        }
+        if let Some((_k, durations, last_item_tm)) = &mut self.duration_history {
+            durations.clear();
+            *last_item_tm = None;
+        }
+        #[cfg(feature = "std")]
+        if let Some(samples) = &mut self.smoothing_samples {
+            *samples = 0;
+        }
+        if let Some((_threshold, idle_time, last_item_tm)) = &mut self.idle {
+            *idle_time = Duration::ZERO;
+            *last_item_tm = None;
+        }
+        if let Some((_max, next_at)) = &mut self.adaptive_stride {
+            self.generate_every_count = 1;
+            *next_at = 1;
+        }
+        self.num_errors = 0;
+        self.check_tms = (0, None, None);
+        self.previous_smoothed_rate = None;
+        self.previous_eta = None;
+        #[cfg(feature = "std")]
+        if let Some(handle) = &self.handle {
+            let state = self.current_record_at(self.started_iterating, None);
+            handle.restart(self.started_iterating);
+            handle.update(state.num_done_u64(), state.resolved_total());
+        }
+    }
+
+    /// Calculate the current `ProgressRecord` for where we are now.
+    fn generate_record(&mut self) -> Option<ProgressRecord<C::Instant>> {
+        self.generate_record_counting(true)
+    }
+
+    /// Like `.generate_record(...)`, but this item is only counted if `counted`, e.g. for
+    /// `.with_count_predicate(...)`. Everything else (e.g. the start message, or the handle) is
+    /// done for every item.
+    fn generate_record_counting(&mut self, counted: bool) -> Option<ProgressRecord<C::Instant>> {
+        if counted {
+            self.count += 1;
+        }
+        #[cfg(feature = "std")]
+        if let Some(summary) = self.interrupted_summary() {
+            let _ = writeln!(self.output, "{}", summary);
+            // It has to be out before the process stops
+            let _ = self.output.flush();
+            #[cfg(all(feature = "interrupt", unix))]
+            pass_on_sigint();
+        }
+        #[cfg(feature = "std")]
+        if let Some(line) = self.start_line() {
+            let _ = writeln!(self.output, "{}", line);
+        }
+        // Only get the time when it's needed, and then use the same time for the record
+        let clock = &self.clock;
+        let mut now = None;
+        if let Some((threshold, idle_time, last_item_tm)) = &mut self.idle {
+            let now = *now.get_or_insert_with(|| clock.now());
+            let gap = clock.duration_between(last_item_tm.unwrap_or(self.started_iterating), now);
+            if gap > *threshold {
+                *idle_time += gap;
+            }
+            *last_item_tm = Some(now);
+        }
+        #[cfg(feature = "std")]
+        if let Some(handle) = &self.handle {
+            // The time doesn't matter for these
+            let state = self.current_record_at(self.started_iterating, None);
+            handle.update(state.num_done_u64(), state.resolved_total());
+        }
+        if let Some((cap, timestamps)) = &mut self.timestamps {
+            let now = *now.get_or_insert_with(|| clock.now());
+            timestamps.push(clock.duration_between(self.started_iterating, now));
+            if let Some(cap) = *cap {
+                // Only trim occasionally, to keep this cheap
+                if timestamps.len() >= 2 * cap {
+                    timestamps.drain(..timestamps.len() - cap);
+                }
+            }
+        }
+        if let Some((k, durations, last_item_tm)) = &mut self.duration_history {
+            let now = *now.get_or_insert_with(|| clock.now());
+            if durations.len() == *k {
+                durations.pop_front();
+            }
+            if *k > 0 {
+                let duration =
+                    clock.duration_between(last_item_tm.unwrap_or(self.started_iterating), now);
+                durations.push_back(duration.as_secs_f64());
+            }
+            *last_item_tm = Some(now);
+        }
+        if let Some((_max, next_at)) = self.adaptive_stride {
+            if self.count < next_at {
+                return None;
+            }
+        } else if !self.count.is_multiple_of(self.generate_every_count as u64) {
+            return None;
+        }
+        if let Some((every, next_at)) = &mut self.sample_every_bytes {
+            let bytes_done = self.bytes.map_or(0, |(bytes_done, _)| bytes_done);
+            if bytes_done < *next_at {
+                return None;
+            }
+            *next_at = (bytes_done / *every + 1) * *every;
+        }
+        if let Some(interval) = self.min_update_interval {
+            let now = *now.get_or_insert_with(|| clock.now());
+            let since = self.previous_record_tm.unwrap_or(self.started_iterating);
+            if clock.duration_between(since, now) < interval {
+                return None;
+            }
+        }
+
+        let now = now.unwrap_or_else(|| clock.now());
+        let record = self.record(now);
+        #[cfg(feature = "tracing")]
+        if let Some((n, level)) = self.trace_every {
+            if record.should_do_every_n_sec(n) {
+                trace_progress(level, &record);
+            }
+        }
+        #[cfg(feature = "log")]
+        if let Some((n, level)) = self.log_every {
+            if record.should_do_every_n_sec(n) {
+                log::log!(
+                    level,
+                    "{}",
+                    record.message("{percent}% done, {rate} per sec, ETA {eta}")
+                );
+            }
+        }
+        if let Some((max, next_at)) = &mut self.adaptive_stride {
+            self.generate_every_count = (self.generate_every_count * 2).min(*max);
+            *next_at = self.count + self.generate_every_count as u64;
+        }
+        Some(record)
+    }
+
+    /// The `n`th item of the inner iterator, like `.nth(n)`, counting the `n` items skipped
+    /// over. If it runs out first, only the items it had are counted, so e.g. the summary is
+    /// right. The returned item isn't counted.
+    fn nth_counted(&mut self, n: usize) -> Option<I::Item> {
+        let (lower, upper) = self.iter.size_hint();
+        if n < lower || upper == Some(lower) {
+            // We know how many items were skipped, even if it runs out
+            let item = self.iter.nth(n);
+            self.count += if item.is_some() { n } else { lower.min(n) } as u64;
+            item
+        } else {
+            for _ in 0..n {
+                self.iter.next()?;
+                self.count += 1;
+            }
+            self.iter.next()
+        }
+    }
+
+    /// The inner iterator's `.size_hint()`, unless that isn't exact and there is an
+    /// `.assume_size(...)`, then it's how many items of that are left, so e.g.
+    /// `Vec::with_capacity` can use it. That's kept within the inner iterator's bounds, apart
+    /// from the `usize::MAX` lower bound of an infinite iterator. With
+    /// `.with_count_predicate(...)`, not every item is counted, so it's always the inner
+    /// iterator's `.size_hint()`.
+    fn remaining_size_hint(&self) -> (usize, Option<usize>) {
+        match (self.iter.size_hint(), self.assumed_size) {
+            ((lower, upper), Some(assumed_size))
+                if upper != Some(lower) && !self.ignore_size_hint =>
+            {
+                // The assumed size is in units, with `.with_count_multiplier(...)`
+                let remaining = (assumed_size as u64)
+                    .saturating_sub(self.count * self.count_multiplier)
+                    / self.count_multiplier;
+                let remaining = remaining.min(usize::MAX as u64) as usize;
+                let remaining = match upper {
+                    Some(upper) => remaining.clamp(lower, upper),
+                    None if lower == usize::MAX => remaining,
+                    None => remaining.max(lower),
+                };
+                (remaining, Some(remaining))
+            }
+            (size_hint, _) => size_hint,
+        }
+    }
+
+    /// Calculate the `ProgressRecord` for the current count, regardless of whether one should
+    /// be generated now.
+    fn record(&mut self, now: C::Instant) -> ProgressRecord<C::Instant> {
+        // There can be several items since the previous record (e.g. `.optional_progress(n)`),
+        // and the averages are of the time per item
+        let stride = self.count.saturating_sub(self.previous_record_count).max(1) as f64;
+        // Since `.reset_averages()`, there's no previous duration to use
+        let previous_sample_tm = if core::mem::take(&mut self.skip_next_duration_sample) {
+            None
+        } else {
+            self.previous_record_tm
+        };
+        let clock = &self.clock;
+        let since_previous_sample =
+            previous_sample_tm.map(|previous_tm| clock.duration_between(previous_tm, now));
+
+        let exp_average_rate = if let Some((rate, last)) = self.exp_average {
+            if let Some(since_previous) = since_previous_sample {
+                let this_duration = since_previous.div_f64(stride);
+                let current_ema = match last {
+                    None => this_duration,
+                    Some(last) => this_duration.mul_f64(rate) + last.mul_f64(1. - rate),
+                };
+                self.exp_average = Some((rate, Some(current_ema)));
+                Some(current_ema)
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        let rolling_average_duration = match &mut self.rolling_average {
+            None => None,
+            Some((size, values)) => {
+                if let Some(since_previous) = since_previous_sample {
+                    let this_duration = since_previous.as_secs_f64() / stride;
+                    if values.len() == *size {
+                        values.pop_front();
+                    }
+                    values.push_back(this_duration);
+                    // We might not have filled up the buffer yet
+                    Some(Duration::from_secs_f64(
+                        values.iter().sum::<f64>() / (values.len() as f64),
+                    ))
+                } else {
+                    None
+                }
+            }
+        };
+
+        let weighted_rolling_average_duration = match &mut self.weighted_rolling_average {
+            None => None,
+            Some((size, values)) => {
+                if let Some(since_previous) = since_previous_sample {
+                    if values.len() == *size {
+                        values.pop_front();
+                    }
+                    values.push_back(since_previous.as_secs_f64() / stride);
+                    // Oldest first, so weights 1, 2, … len
+                    let weighted_sum: f64 = (1..)
+                        .zip(values.iter())
+                        .map(|(weight, duration)| weight as f64 * duration)
+                        .sum();
+                    let total_weight = (values.len() * (values.len() + 1) / 2) as f64;
+                    Some(Duration::from_secs_f64(weighted_sum / total_weight))
+                } else {
+                    None
+                }
+            }
+        };
+
+        let windowed_rate = match &mut self.windowed_rate {
+            None => None,
+            Some((window, samples)) => {
+                let num = self.count * self.count_multiplier;
+                samples.push_back((now, num));
+                // Forget the ones which are too old now, so this doesn't grow forever
+                while samples
+                    .front()
+                    .is_some_and(|&(tm, _)| clock.duration_between(tm, now) > *window)
+                {
+                    samples.pop_front();
+                }
+                match samples.front() {
+                    Some(&(oldest_tm, oldest_num)) => {
+                        let gap = clock.duration_between(oldest_tm, now);
+                        if gap.is_zero() {
+                            None
+                        } else {
+                            Some((num - oldest_num) as f64 / gap.as_secs_f64())
+                        }
+                    }
+                    None => None,
+                }
+            }
+        };
+
+        // `powf()` needs `std`
+        #[cfg(not(feature = "std"))]
+        let smoothing_weight = None;
+        #[cfg(feature = "std")]
+        let smoothing_weight = match &mut self.smoothing_samples {
+            None => None,
+            Some(samples) => {
+                if since_previous_sample.is_some() {
+                    *samples += 1;
+                }
+                let weight = if let Some((rate, _)) = self.exp_average {
+                    1. - (1. - rate).powf(*samples as f64)
+                } else if let Some((size, _)) = &self.rolling_average {
+                    (*samples as f64 / *size as f64).min(1.)
+                } else {
+                    1.
+                };
+                Some(weight)
+            }
+        };
+
+        let mut res = ProgressRecord {
+            rolling_average_duration,
+            exp_average_duration: exp_average_rate,
+            weighted_rolling_average_duration,
+            windowed_rate,
+            smoothing_weight,
+            ..self.current_record_at(now, self.previous_record_tm)
+        };
+
+        if let Some(cost_model) = &mut self.cost_model {
+            res.model_eta = cost_model.eta(res.num, res.resolved_total(), res.iterating_for);
+        }
+
+        if let Some((factor, last)) = &mut self.display_damping {
+            let raw_rate = res.raw_rate();
+            let damped = match *last {
+                Some(last) if raw_rate.is_finite() => raw_rate * *factor + last * (1. - *factor),
+                _ => raw_rate,
+            };
+            *last = Some(damped);
+            res.damped_rate = Some(damped);
+        }
+
+        if self.eta_settings != EtaSettings::default() {
+            self.previous_eta = res.eta();
+        }
+
+        if let Some(rate_unit) = &mut self.rate_unit {
+            let unit = RateUnit::choose(res.rate(), *rate_unit);
+            *rate_unit = Some(unit);
+            res.rate_unit = Some(unit);
+        }
+
+        self.previous_smoothed_rate = res.smoothed_rate();
+        self.previous_record_tm = Some(now);
+        self.previous_record_count = self.count;
+
+        res
+    }
+
+    /// The `ProgressRecord` for where we are now, even if no record was generated for the last
+    /// item (e.g. with `.optional_progress(...)`). The rolling & exponential averages are not
+    /// included.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::OptionalProgressableIter;
+    /// let mut progressor = (0..10).optional_progress(5);
+    /// let (state, _) = progressor.next().unwrap();
+    /// assert!(state.is_none());
+    /// assert_eq!(progressor.current_record().fraction(), Some(0.1));
+    /// # }
+    /// ```
+    pub fn current_record(&self) -> ProgressRecord<C::Instant> {
+        self.current_record_at(self.clock.now(), self.previous_record_tm)
+    }
+
+    /// How long we expect to wait for the next item, i.e. `1 / smoothed_rate()` of the last
+    /// record. `None` if there's no smoothed rate (e.g. neither `.with_rolling_average(...)` nor
+    /// `.with_exp_average(...)` was used, or it's too soon to know).
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..10).progress().with_rolling_average(5);
+    /// progressor.next();
+    /// assert_eq!(progressor.next_item_eta(), None);
+    /// # }
+    /// ```
+    pub fn next_item_eta(&self) -> Option<Duration> {
+        self.previous_smoothed_rate
+            .filter(|&rate| rate > 0. && rate.is_finite())
+            .map(|rate| Duration::from_secs_f64(1. / rate))
+    }
+
+    fn current_record_at(
+        &self,
+        now: C::Instant,
+        previous_tm: Option<C::Instant>,
+    ) -> ProgressRecord<C::Instant> {
+        let mut res = ProgressRecord {
+            size_hint: if self.ignore_size_hint {
+                (0, None)
+            } else {
+                self.iter.size_hint()
+            },
+            assumed_size: self.assumed_size.map(|size| size as u64),
+            assumed_fraction: self.position_fraction,
+            bytes_done: self.bytes.map(|(bytes_done, _)| bytes_done),
+            byte_total: self.bytes.map(|(_, total)| total),
+            eta_settings: self.eta_settings,
+            previous_eta: self.previous_eta,
+            time_budget: self.time_budget,
+            display_total: self.display_total,
+            #[cfg(feature = "std")]
+            started_wall: self.started_wall,
+            #[cfg(feature = "std")]
+            eta_display: self.eta_display,
+            count_multiplier: self.count_multiplier,
+            num_since_previous: self.count.saturating_sub(self.previous_record_count)
+                * self.count_multiplier,
+            num_errors: self.num_errors,
+            safe_math: self.safe_math,
+            buffer_depth: self.buffer_depth,
+            idle_time: self
+                .idle
+                .map_or(Duration::ZERO, |(_, idle_time, _)| idle_time),
+            ..ProgressRecord::new(
+                self.count * self.count_multiplier,
+                &self.clock,
+                self.started_iterating,
+                previous_tm,
+                now,
+            )
+        };
+        if let Some(fraction_map) = &self.fraction_map {
+            res.assumed_fraction = res
+                .fraction()
+                .map(|fraction| fraction_map(fraction).clamp(0., 1.));
+        }
+        res
+    }
+
+    /// Call `f` with the `.current_record()`, but only every `n` sec. Like
+    /// `ProgressRecord::do_every_n_sec`, but works when no record was generated for this item.
+    /// It can be called several times for each item with different `n`, and each fires
+    /// according to its own interval.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::OptionalProgressableIter;
+    /// let mut progressor = (0..10).optional_progress(5);
+    /// while let Some((_state, _num)) = progressor.next() {
+    ///     progressor.do_every_n_sec(1., |state| println!("{} done", state.num_done()));
+    ///     progressor.do_every_n_sec(60., |state| println!("{:?}% done", state.percent()));
+    /// }
+    /// # }
+    /// ```
+    pub fn do_every_n_sec<F: Fn(&ProgressRecord<C::Instant>)>(&mut self, n: impl Into<f32>, f: F) {
+        self.every_n_sec_record().do_every_n_sec(n, f);
+    }
+
+    /// Write `msg` to the `.with_output(...)` (stdout by default), but only every `n` sec. The
+    /// newline isn't included.
+    ///
+    /// ```
+    /// # use iter_progress::OptionalProgressableIter;
+    /// let mut progressor = (0..10).optional_progress(5);
+    /// while let Some((_state, num)) = progressor.next() {
+    ///     progressor.print_every_n_sec(1., format!("at {}\n", num));
+    /// }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn print_every_n_sec<T: std::fmt::Display>(&mut self, n: f32, msg: T) {
+        if self.every_n_sec_record().should_do_every_n_sec(n) {
+            let _ = write!(self.output, "{}", msg);
+        }
+    }
+
+    /// The `.current_record()` for the `..._every_n_sec` methods, where the window starts from
+    /// when they were last called for an earlier item.
+    fn every_n_sec_record(&mut self) -> ProgressRecord<C::Instant> {
+        let now = self.clock.now();
+        let (count, previous_item_tm, last_tm) = &mut self.check_tms;
+        if *count != self.count {
+            // First call for this item, so the window for all calls for this item starts from
+            // when it was last called for an earlier item
+            *count = self.count;
+            *previous_item_tm = *last_tm;
+        }
+        *last_tm = Some(now);
+        let previous_tm = *previous_item_tm;
+        self.current_record_at(now, previous_tm)
+    }
+
+    /// Write `status` to `w` as a status line, which overwrites the previous one (with `\r`),
+    /// but only every `n` sec. If the previous line was longer, it is padded with spaces, so none
+    /// of the old line is left on the screen. If `n` isn't a valid number of seconds (e.g.
+    /// negative or NaN), it's never written.
+    ///
+    /// ```
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..10).progress();
+    /// while let Some((state, _)) = progressor.next() {
+    ///     let status = format!("{} done", state.num_done());
+    ///     progressor
+    ///         .print_status_every_n_sec(&mut std::io::stderr(), 1., &status)
+    ///         .unwrap();
+    /// }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn print_status_every_n_sec<W: Write>(
+        &self,
+        w: &mut W,
+        n: f32,
+        status: &str,
+    ) -> io::Result<()> {
+        let state = self.current_record();
+        let mut status_line = self.status_line.lock().unwrap_or_else(|e| e.into_inner());
+        let (last_fired, previous_width) = &mut *status_line;
+        // Like a `Throttle`, but with this iterator's `Clock`
+        let interval = Duration::try_from_secs_f32(n).unwrap_or(Duration::MAX);
+        let since = last_fired.unwrap_or(state.started_iterating());
+        if self.clock.duration_between(since, state.timestamp()) < interval {
+            return Ok(());
+        }
+        *last_fired = Some(state.timestamp());
+        let width = status.chars().count();
+        write!(
+            w,
+            "\r{}{}",
+            status,
+            " ".repeat(previous_width.saturating_sub(width))
+        )?;
+        *previous_width = width;
+        w.flush()
+    }
+
+    /// Call `f` with the `.current_record()`, but only every `n` items. Like
+    /// `ProgressRecord::do_every_n_items`, but works when no record was generated for this item.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// # use iter_progress::OptionalProgressableIter;
+    /// let mut progressor = (0..10).optional_progress(5);
+    /// while let Some((_state, _num)) = progressor.next() {
+    ///     progressor.do_every_n_items(2, |state| {
+    ///         println!("{:?}% done", state.percent());
+    ///     });
+    /// }
+    /// # }
+    /// ```
+    pub fn do_every_n_items<F: Fn(&ProgressRecord<C::Instant>)>(&self, n: usize, f: F) {
+        self.current_record().do_every_n_items(n, f);
+    }
+
+    /// Write `msg` to the `.with_output(...)` (stdout by default), but only every `n` items.
+    /// The newline isn't included.
+    ///
+    /// ```
+    /// # use iter_progress::ProgressableIter;
+    /// let mut progressor = (0..10_000).progress();
+    /// while let Some((_state, num)) = progressor.next() {
+    ///     progressor.print_every_n_items(1_000, format!("at {}\n", num));
+    /// }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn print_every_n_items<T: std::fmt::Display>(&mut self, n: usize, msg: T) {
+        if self.current_record().should_do_every_n_items(n) {
+            let _ = write!(self.output, "{}", msg);
+        }
+    }
+
+    /// Returns referend to the inner iterator
+    pub fn inner(&self) -> &I {
+        &self.iter
+    }
+
+    /// Gets the original iterator back, consuming this.
+    pub fn into_inner(self) -> I {
+        self.iter
+    }
+}
+
+#[cfg(feature = "std")]
+pub trait OptionalProgressableIter<I: Iterator> {
+    fn optional_progress(self, generate_every_count: usize) -> OptionalProgressRecorderIter<I>;
+}
+
+#[cfg(feature = "std")]
+impl<I> OptionalProgressableIter<I> for I
+where
+    I: Iterator,
+{
+    /// Convert an iterator into an `OptionalProgressRecorderIter`.
+    fn optional_progress(self, generate_every_count: usize) -> OptionalProgressRecorderIter<I> {
+        OptionalProgressRecorderIter::new(self, generate_every_count)
+    }
+}
+
+impl<I: Iterator, C: Clock> Iterator for OptionalProgressRecorderIter<I, C> {
+    type Item = (Option<ProgressRecord<C::Instant>>, <I as Iterator>::Item);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.iter.next() {
+            Some(a) => Some((self.generate_record(), a)),
+            None => {
+                self.finish();
+                None
+            }
+        }
+    }
+
+    /// Skips `n` items of the inner iterator in one go. The skipped items are counted in
+    /// `.num_done()`, but a record is only generated if the returned item is one of the
+    /// `generate_every_count` items.
+    #[inline]
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        match self.nth_counted(n) {
+            Some(a) => Some((self.generate_record(), a)),
+            None => {
+                self.finish();
+                None
+            }
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.remaining_size_hint()
+    }
+
+    #[inline]
+    fn count(self) -> usize {
+        self.iter.count()
+    }
+}
+
+/// Each item of the inner iterator is one item of this, so it's exactly as long.
+impl<I: ExactSizeIterator, C: Clock> ExactSizeIterator for OptionalProgressRecorderIter<I, C> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+/// Iterator over the lines of a `BufRead`, which also yields how many bytes each line took up
+/// (including the newline). A line which isn't valid UTF-8 is an `InvalidData` error, but its
+/// bytes are still counted.
+#[cfg(feature = "std")]
+struct ByteCountedLines<R> {
+    reader: R,
+}
+
+#[cfg(feature = "std")]
+impl<R: BufRead> Iterator for ByteCountedLines<R> {
+    type Item = (u64, io::Result<String>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = Vec::new();
+        match self.reader.read_until(b'\n', &mut line) {
+            Ok(0) => None,
+            Ok(bytes) => {
+                if line.ends_with(b"\n") {
+                    line.pop();
+                    if line.ends_with(b"\r") {
+                        line.pop();
+                    }
+                }
+                let line = String::from_utf8(line)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+                Some((bytes as u64, line))
+            }
+            Err(e) => Some((0, Err(e))),
+        }
+    }
+}
+
+/// Iterates over the lines of a `BufRead`, with a `ProgressRecord` where the `.fraction()` is
+/// based on how many bytes have been read. Created with `progress_lines(...)`
+#[cfg(feature = "std")]
+pub struct ProgressLines<R> {
+    iter: OptionalProgressRecorderIter<ByteCountedLines<R>>,
+}
+
+/// Read the lines of `reader` (like `BufRead::lines()`), and keep track of how many bytes have
+/// been read. The `.fraction()` of each `ProgressRecord` is the bytes read so far out of
+/// `total_bytes` (e.g. the file size), while `.num_done()` is the number of lines, and
+/// `.bytes_done()` is the bytes read.
+///
+/// ```
+/// # use iter_progress::progress_lines;
+/// let text = "hello\nworld\n";
+/// let mut lines = progress_lines(text.as_bytes(), text.len() as u64);
+/// let (state, line) = lines.next().unwrap();
+/// assert_eq!(line.unwrap(), "hello");
+/// assert_eq!(state.num_done(), 1);
+/// assert_eq!(state.fraction(), Some(0.5));
+/// ```
+#[cfg(feature = "std")]
+pub fn progress_lines<R: BufRead>(reader: R, total_bytes: u64) -> ProgressLines<R> {
+    let mut iter = OptionalProgressRecorderIter::new(ByteCountedLines { reader }, 1);
+    iter.bytes = Some((0, total_bytes));
+    ProgressLines { iter }
+}
+
+#[cfg(feature = "std")]
+impl<R: BufRead> ProgressLines<R> {
+    /// How many bytes have been read so far
+    pub fn bytes_done(&self) -> u64 {
+        self.iter.bytes.map_or(0, |(bytes_done, _)| bytes_done)
+    }
+
+    /// Rather than a `ProgressRecord` for every line, only generate one each time another `n`
+    /// bytes have been read. The record is generated on the line which crosses the threshold.
+    ///
+    /// ```
+    /// # use iter_progress::progress_lines;
+    /// let text = "a\nbb\nccc\ndddd\n";
+    /// let lines = progress_lines(text.as_bytes(), text.len() as u64).with_sample_every_bytes(5);
+    /// let sampled: Vec<_> = lines.map(|(state, _line)| state.is_some()).collect();
+    /// assert_eq!(sampled, vec![false, true, false, true]);
+    /// ```
+    pub fn with_sample_every_bytes(self, n: u64) -> OptionalProgressLines<R> {
+        assert!(n > 0, "Can't sample every 0 bytes");
+        let mut new = self;
+        new.iter.sample_every_bytes = Some((n, n));
+        OptionalProgressLines { lines: new }
+    }
+
+    /// See `OptionalProgressRecorderIter::on_finish`
+    pub fn on_finish<F: FnOnce(&ProgressRecord) + Send + Sync + 'static>(self, f: F) -> Self {
+        ProgressLines {
+            iter: self.iter.on_finish(f),
+        }
+    }
+
+    /// See `OptionalProgressRecorderIter::with_start_message`
+    #[cfg(feature = "std")]
+    pub fn with_start_message(self, message: impl Into<String>) -> Self {
+        ProgressLines {
+            iter: self.iter.with_start_message(message),
+        }
+    }
+
+    /// See `OptionalProgressRecorderIter::with_summary_if_longer_than`
+    #[cfg(feature = "std")]
+    pub fn with_summary_if_longer_than(self, threshold: impl Into<Option<Duration>>) -> Self {
+        ProgressLines {
+            iter: self.iter.with_summary_if_longer_than(threshold),
+        }
+    }
+
+    /// See `OptionalProgressRecorderIter::with_output`
+    #[cfg(feature = "std")]
+    pub fn with_output(self, output: Output) -> Self {
+        ProgressLines {
+            iter: self.iter.with_output(output),
+        }
+    }
+
+    /// See `OptionalProgressRecorderIter::with_completion_log`
+    #[cfg(feature = "std")]
+    pub fn with_completion_log(self, path: impl AsRef<Path>) -> Self {
+        ProgressLines {
+            iter: self.iter.with_completion_log(path),
+        }
+    }
+
+    /// Read the next line, counting its bytes, and generate a `ProgressRecord` the same way as
+    /// any other item, unless `.with_sample_every_bytes(...)` says not to yet.
+    fn next_line(&mut self) -> Option<(Option<ProgressRecord>, io::Result<String>)> {
+        let (bytes, line) = match self.iter.iter.next() {
+            Some(next) => next,
+            None => {
+                self.iter.finish();
+                return None;
+            }
+        };
+        if let Some((bytes_done, total_bytes)) = &mut self.iter.bytes {
+            *bytes_done += bytes;
+            if *total_bytes > 0 {
+                let (bytes_done, total_bytes) = (*bytes_done, *total_bytes);
+                self.iter
+                    .set_position(bytes_done as f64, total_bytes as f64);
+            }
+        }
+        Some((self.iter.generate_record(), line))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: BufRead> Iterator for ProgressLines<R> {
+    type Item = (ProgressRecord, io::Result<String>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // we know there is always a record generated
+        self.next_line().map(|(state, line)| (state.unwrap(), line))
+    }
+}
+
+/// Like `ProgressLines`, but only generates a `ProgressRecord` every N bytes. Created with
+/// `.with_sample_every_bytes(...)`.
+#[cfg(feature = "std")]
+pub struct OptionalProgressLines<R> {
+    lines: ProgressLines<R>,
+}
+
+#[cfg(feature = "std")]
+impl<R: BufRead> OptionalProgressLines<R> {
+    /// How many bytes have been read so far
+    pub fn bytes_done(&self) -> u64 {
+        self.lines.bytes_done()
+    }
+
+    /// See `OptionalProgressRecorderIter::on_finish`
+    pub fn on_finish<F: FnOnce(&ProgressRecord) + Send + Sync + 'static>(self, f: F) -> Self {
+        OptionalProgressLines {
+            lines: self.lines.on_finish(f),
+        }
+    }
+
+    /// See `OptionalProgressRecorderIter::with_start_message`
+    #[cfg(feature = "std")]
+    pub fn with_start_message(self, message: impl Into<String>) -> Self {
+        OptionalProgressLines {
+            lines: self.lines.with_start_message(message),
+        }
+    }
+
+    /// See `OptionalProgressRecorderIter::with_summary_if_longer_than`
+    #[cfg(feature = "std")]
+    pub fn with_summary_if_longer_than(self, threshold: impl Into<Option<Duration>>) -> Self {
+        OptionalProgressLines {
+            lines: self.lines.with_summary_if_longer_than(threshold),
+        }
+    }
+
+    /// See `OptionalProgressRecorderIter::with_output`
+    #[cfg(feature = "std")]
+    pub fn with_output(self, output: Output) -> Self {
+        OptionalProgressLines {
+            lines: self.lines.with_output(output),
+        }
+    }
+
+    /// See `OptionalProgressRecorderIter::with_completion_log`
+    #[cfg(feature = "std")]
+    pub fn with_completion_log(self, path: impl AsRef<Path>) -> Self {
+        OptionalProgressLines {
+            lines: self.lines.with_completion_log(path),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: BufRead> Iterator for OptionalProgressLines<R> {
+    type Item = (Option<ProgressRecord>, io::Result<String>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lines.next_line()
+    }
+}
+
+/// Decides whether something (e.g. logging, or updating a UI) should be done for a
+/// `ProgressRecord`, at most once every `interval`. Unlike `ProgressRecord::do_every_n_sec`, it
+/// keeps track of when it last fired, so several can be used for the same records.
+///
+/// ```
+/// # use iter_progress::{ProgressableIter, Throttle};
+/// # use std::time::Duration;
+/// let mut log = Throttle::every(Duration::from_secs(60));
+/// let mut ui = Throttle::every(Duration::from_millis(100));
+/// for (state, _) in (0..100).progress() {
+///     if log.should_fire(&state) {
+///         println!("{} done", state.num_done());
+///     }
+///     if ui.should_fire(&state) {
+///         // redraw
+///     }
+/// }
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    interval: Duration,
+    last_fired: Option<Instant>,
+}
+
+#[cfg(feature = "std")]
+impl Throttle {
+    /// A `Throttle` which fires at most once every `interval`.
+    pub fn every(interval: Duration) -> Throttle {
+        Throttle {
+            interval,
+            last_fired: None,
         }
     }
 
-    /// If we want to do every `n` items, should we do it now?
-    pub fn should_do_every_n_items(&self, n: usize) -> bool {
-        (self.num_done() - 1) % n == 0
+    /// Returns true if at least `interval` has passed (at the time of `record`) since this last
+    /// returned true, or, the first time, since the iteration started.
+    pub fn should_fire(&mut self, record: &ProgressRecord) -> bool {
+        let now = record.timestamp();
+        let since = self.last_fired.unwrap_or(record.started_iterating);
+        if now.saturating_duration_since(since) >= self.interval {
+            self.last_fired = Some(now);
+            true
+        } else {
+            false
+        }
     }
+}
 
-    /// Print out `msg`, but only if there has been `n` items.
-    /// Often you want to print out a debug message every 1,000 items or so. This function does
-    /// that.
-    pub fn print_every_n_items<T: std::fmt::Display>(&self, n: usize, msg: T) {
-        if self.should_do_every_n_items(n) {
-            print!("{}", msg);
-        }
+/// A progress counter which can be shared between threads, for when the work isn't done by one
+/// iterator. Cloning it gives another handle to the same counter.
+///
+/// ```
+/// # use iter_progress::AtomicProgress;
+/// let progress = AtomicProgress::new().assume_size(200);
+/// let threads: Vec<_> = (0..2).map(|_| {
+///     let progress = progress.clone();
+///     std::thread::spawn(move || for _ in 0..100 { progress.inc(); })
+/// }).collect();
+/// for t in threads { t.join().unwrap(); }
+/// assert_eq!(progress.record().num_done(), 200);
+/// assert_eq!(progress.record().fraction(), Some(1.));
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct AtomicProgress {
+    count: Arc<AtomicU64>,
+    started_iterating: Instant,
+    assumed_size: Option<usize>,
+}
+
+#[cfg(feature = "std")]
+impl Default for AtomicProgress {
+    fn default() -> Self {
+        AtomicProgress::new()
     }
+}
 
-    /// Do thing but only every `n` items.
-    /// Could be a print statement.
-    ///
-    /// takes 2 arguments, `n` and the function (`f`) which takes a `&ProgressState`. `f` will only
-    /// be called every `n` items that pass through the iterator.
-    ///
-    /// ```
-    /// # use iter_progress::ProgressableIter;
-    /// for (state, _) in (0..150).progress() {
-    ///    state.do_every_n_items(5, |state| {
-    ///        println!("Current progress: {}%", state.percent().unwrap());
-    ///    });
-    /// }
-    /// ```
-    pub fn do_every_n_items<F: Fn(&Self)>(&self, n: usize, f: F) {
-        if self.should_do_every_n_items(n) {
-            f(self);
+#[cfg(feature = "std")]
+impl AtomicProgress {
+    /// Start a new counter at 0.
+    pub fn new() -> AtomicProgress {
+        AtomicProgress {
+            count: Arc::new(AtomicU64::new(0)),
+            started_iterating: Instant::now(),
+            assumed_size: None,
         }
     }
 
-    /// Rolling average time to process each item this iterator is processing if it is recording
-    /// that. None if it's not being recorded, or it's too soon to know (e.g. for the first item).
-    pub fn rolling_average_duration(&self) -> &Option<Duration> {
-        &self.rolling_average_duration
+    /// Set the total number of items, so that the `.fraction()` etc. can be calculated.
+    pub fn assume_size(self, size: impl Into<Option<usize>>) -> Self {
+        let mut new = self;
+        new.assumed_size = size.into();
+        new
     }
 
-    /// Rolling average number of items per second this iterator is processing if it is recording
-    /// that. None if it's not being recorded, or it's too soon to know (e.g. for the first item).
-    pub fn rolling_average_rate(&self) -> Option<f64> {
-        self.rolling_average_duration.map(|d| 1. / d.as_secs_f64())
+    /// Record that one more item has been done.
+    pub fn inc(&self) {
+        self.add(1);
     }
 
-    /// Exponential average time to process each item this iterator is processing if it is recording
-    /// that. None if it's not being recorded, or it's too soon to know (e.g. for the first item).
-    pub fn exp_average_duration(&self) -> &Option<Duration> {
-        &self.exp_average_duration
+    /// Record that `n` more items have been done.
+    pub fn add(&self, n: u64) {
+        self.count.fetch_add(n, Ordering::Relaxed);
     }
 
-    /// Exponential average number of items per second this iterator is processing if it is recording
-    /// that. None if it's not being recorded, or it's too soon to know (e.g. for the first item).
-    pub fn exp_average_rate(&self) -> Option<f64> {
-        self.exp_average_duration.map(|d| 1. / d.as_secs_f64())
+    /// The `ProgressRecord` for the current count, from all threads.
+    pub fn record(&self) -> ProgressRecord {
+        self.record_at(Instant::now(), None)
     }
 
-    /// If the total size is know (i.e. we know the `.fraction()`), calculate the estimated time
-    /// to arrival, i.e. how long before this is finished.
-    pub fn eta(&self) -> Option<Duration> {
-        self.fraction()
-            .map(|f| self.duration_since_start().div_f64(f) - self.duration_since_start())
+    fn record_at(&self, now: Instant, previous_record_tm: Option<Instant>) -> ProgressRecord {
+        ProgressRecord {
+            assumed_size: self.assumed_size.map(|size| size as u64),
+            ..ProgressRecord::new(
+                self.count.load(Ordering::Relaxed),
+                &StdClock,
+                self.started_iterating,
+                previous_record_tm,
+                now,
+            )
+        }
     }
 
-    /// If the total size is know (i.e. we know the `.fraction()`), calculate how long, in total,
-    /// this iterator would run for. i.e. how long it's run plus how much longer it has left
-    pub fn estimated_total_time(&self) -> Option<Duration> {
-        self.fraction()
-            .map(|f| self.duration_since_start().div_f64(f))
+    /// Start a background thread which, every `interval`, calls `f` with the combined
+    /// `ProgressRecord` of all threads (e.g. to print it). `f` is called one final time when
+    /// the returned `ProgressMonitor` is finished or dropped, so it always sees the last count.
+    pub fn with_parallel_snapshot_interval<F>(&self, interval: Duration, f: F) -> ProgressMonitor
+    where
+        F: FnMut(&ProgressRecord) + Send + 'static,
+    {
+        let mut f = f;
+        let progress = self.clone();
+        let (stop, stopped) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || {
+            let mut previous_record_tm = None;
+            let mut previous_num = 0;
+            loop {
+                let finished = match stopped.recv_timeout(interval) {
+                    Err(mpsc::RecvTimeoutError::Timeout) => false,
+                    Ok(()) | Err(mpsc::RecvTimeoutError::Disconnected) => true,
+                };
+                let now = Instant::now();
+                let mut record = progress.record_at(now, previous_record_tm);
+                record.num_since_previous = record.num.saturating_sub(previous_num);
+                f(&record);
+                previous_record_tm = Some(now);
+                previous_num = record.num;
+                if finished {
+                    break;
+                }
+            }
+        });
+
+        ProgressMonitor {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
     }
 }
 
-pub struct OptionalProgressRecorderIter<I> {
-    /// The iterator that we are iteating on
-    iter: I,
-
-    /// How many items have been seen
-    count: usize,
-
-    generate_every_count: usize,
+/// A background thread reporting the progress of an `AtomicProgress`. Created with
+/// `.with_parallel_snapshot_interval(...)`. The thread is stopped when this is finished or
+/// dropped.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct ProgressMonitor {
+    stop: Option<mpsc::Sender<()>>,
+    handle: Option<thread::JoinHandle<()>>,
+}
 
-    /// When did we start iterating
-    started_iterating: Instant,
+#[cfg(feature = "std")]
+impl ProgressMonitor {
+    /// Stop the background thread, after one final call of the callback, and wait for it to
+    /// finish.
+    pub fn finish(self) {
+        // Dropping does it
+    }
+}
 
-    previous_record_tm: Option<Instant>,
+#[cfg(feature = "std")]
+impl Drop for ProgressMonitor {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            // If the thread has already gone (e.g. callback panicked), there's nothing to stop
+            let _ = stop.send(());
+        }
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
 
-    rolling_average: Option<(usize, Vec<f64>)>,
-    exp_average: Option<(f64, Option<Duration>)>,
-    assumed_size: Option<usize>,
+/// A cheap, cloneable view of an iterator's progress, which another thread (e.g. a GUI's render
+/// loop) can poll with `.snapshot()` while the iterator is being used. Created with
+/// `.with_handle()`, and updated for every item. Each snapshot is consistent, i.e. its
+/// `num_done` & `total` are always from the same item.
+///
+/// ```
+/// # use iter_progress::ProgressableIter;
+/// let mut progressor = (0..10).progress().with_handle();
+/// let handle = progressor.handle().unwrap();
+/// progressor.next();
+/// progressor.next();
+/// assert_eq!(handle.snapshot().num_done, 2);
+/// assert_eq!(handle.snapshot().total, Some(10));
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct ProgressHandle<T = Instant> {
+    /// In one lock, so the count & total are always updated together
+    state: Arc<Mutex<HandleState<T>>>,
+}
 
-    _fake_now: Option<Instant>,
+#[cfg(feature = "std")]
+#[derive(Debug)]
+struct HandleState<T> {
+    num_done: u64,
+    total: Option<u64>,
+    started_iterating: T,
 }
 
-/// Wraps an iterator and keeps track of state used for `ProgressRecord`'s
-pub struct ProgressRecorderIter<I>(OptionalProgressRecorderIter<I>);
+#[cfg(feature = "std")]
+impl<T> ProgressHandle<T> {
+    fn new(started_iterating: T) -> ProgressHandle<T> {
+        ProgressHandle {
+            state: Arc::new(Mutex::new(HandleState {
+                num_done: 0,
+                total: None,
+                started_iterating,
+            })),
+        }
+    }
 
-impl<I> AsRef<OptionalProgressRecorderIter<I>> for ProgressRecorderIter<I> {
-    fn as_ref(&self) -> &OptionalProgressRecorderIter<I> {
-        &self.0
+    /// For `.reset()`
+    fn restart(&self, started_iterating: T) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.started_iterating = started_iterating;
     }
-}
 
-impl<I> AsMut<OptionalProgressRecorderIter<I>> for ProgressRecorderIter<I> {
-    fn as_mut(&mut self) -> &mut OptionalProgressRecorderIter<I> {
-        &mut self.0
+    fn update(&self, num_done: u64, total: Option<u64>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.num_done = num_done;
+        state.total = total;
     }
 }
 
-impl<I: Iterator> Deref for ProgressRecorderIter<I> {
-    type Target = OptionalProgressRecorderIter<I>;
-    fn deref(&self) -> &OptionalProgressRecorderIter<I> {
-        &self.0
+#[cfg(feature = "std")]
+impl ProgressHandle {
+    /// The progress as of the last item.
+    pub fn snapshot(&self) -> ProgressSnapshot {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        ProgressSnapshot {
+            num_done: state.num_done,
+            total: state.total,
+            started_iterating: state.started_iterating,
+            elapsed: state.started_iterating.elapsed(),
+        }
     }
 }
 
-impl<I: Iterator> DerefMut for ProgressRecorderIter<I> {
-    fn deref_mut(&mut self) -> &mut OptionalProgressRecorderIter<I> {
-        &mut self.0
-    }
+/// One overall progress for a pipeline of several phases, each with its own iterator. Each phase
+/// is given a `weight`, its share of the overall progress (so they should add up to 1), and
+/// inside a phase, that iterator's `.fraction()` is mapped into the phase's slice of the total.
+///
+/// ```
+/// # use iter_progress::PhaseProgress;
+/// let mut phases = PhaseProgress::new();
+/// {
+///     let download = phases.phase(0.8);
+///     for (state, _) in download.progress(0..10) {
+///         // 8%, 16%, … 80%
+///         println!("{:.0}% done overall", state.percent().unwrap());
+///     }
+/// }
+/// assert_eq!(phases.fraction(), 0.8);
+/// let unpack = phases.phase(0.2);
+/// let mut progressor = unpack.progress(0..2);
+/// let (state, _) = progressor.next().unwrap();
+/// assert!((state.fraction().unwrap() - 0.9).abs() < 1e-9);
+/// assert!((phases.fraction() - 0.9).abs() < 1e-9);
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default)]
+pub struct PhaseProgress {
+    state: Arc<Mutex<PhaseState>>,
 }
 
-impl<I: Iterator> ProgressRecorderIter<I> {
-    /// Create a new `ProgressRecorderIter` from another iterator.
-    pub fn new(iter: I) -> ProgressRecorderIter<I> {
-        ProgressRecorderIter(OptionalProgressRecorderIter::new(iter, 1))
-    }
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+struct PhaseState {
+    /// Sum of the weights of the finished phases
+    completed: f64,
+    /// Weight of the current phase
+    current_weight: f64,
+    /// How far through the current phase, from its iterator
+    current_fraction: f64,
+}
 
-    pub fn assume_size(self, size: impl Into<Option<usize>>) -> Self {
-        let mut new = self;
-        new.0.assumed_size = size.into();
-        new
+#[cfg(feature = "std")]
+impl PhaseProgress {
+    /// No phases done yet.
+    pub fn new() -> PhaseProgress {
+        PhaseProgress::default()
     }
-}
 
-/// An iterator that records it's progress as it goes along
-pub trait ProgressableIter<I> {
-    fn progress(self) -> ProgressRecorderIter<I>;
-}
+    /// Start the next phase, which makes up `weight` of the overall progress. The phase is
+    /// finished (and it's whole `weight` counted as done) when the returned `PhaseGuard` is
+    /// dropped.
+    pub fn phase(&mut self, weight: f64) -> PhaseGuard {
+        let mut state = self.state.lock().unwrap();
+        state.current_weight = weight;
+        state.current_fraction = 0.;
+        PhaseGuard {
+            state: Arc::clone(&self.state),
+            weight,
+        }
+    }
 
-impl<I> ProgressableIter<I> for I
-where
-    I: Iterator,
-{
-    /// Convert an iterator into a `ProgressRecorderIter`.
-    fn progress(self) -> ProgressRecorderIter<I> {
-        ProgressRecorderIter::new(self)
+    /// The overall fraction done, i.e. the weights of the finished phases, plus the current
+    /// phase's fraction of its weight.
+    pub fn fraction(&self) -> f64 {
+        let state = self.state.lock().unwrap();
+        (state.completed + state.current_fraction * state.current_weight).clamp(0., 1.)
     }
 }
 
-impl<I> Iterator for ProgressRecorderIter<I>
-where
-    I: Iterator,
-{
-    type Item = (ProgressRecord, <I as Iterator>::Item);
+/// The current phase of a `PhaseProgress`. Created with `.phase(weight)`, and the phase is
+/// finished when this is dropped.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct PhaseGuard {
+    state: Arc<Mutex<PhaseState>>,
+    weight: f64,
+}
 
-    #[inline]
-    fn next(&mut self) -> Option<(ProgressRecord, <I as Iterator>::Item)> {
-        self.0.iter.next().map(|a| {
-            let fake_now = std::mem::take(&mut self.0._fake_now);
-            // we know there is always a record generated
-            (self.0.generate_record(fake_now).unwrap(), a)
+#[cfg(feature = "std")]
+impl PhaseGuard {
+    /// Convert this phase's iterator into a `ProgressRecorderIter`, where the `.fraction()` (and
+    /// `.percent()`, ETA, etc.) is the overall progress of all phases, rather than just this one.
+    /// The iterator needs a known size (or `.assume_size(...)`) for its fraction to be known.
+    pub fn progress<I: Iterator>(&self, iter: I) -> ProgressRecorderIter<I> {
+        let state = Arc::clone(&self.state);
+        let weight = self.weight;
+        ProgressRecorderIter::new(iter).with_fraction_map(move |fraction| {
+            let mut state = state.lock().unwrap();
+            state.current_fraction = fraction;
+            state.completed + fraction * weight
         })
     }
+}
 
-    #[inline]
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.0.iter.size_hint()
+#[cfg(feature = "std")]
+impl Drop for PhaseGuard {
+    fn drop(&mut self) {
+        // Don't panic in drop, even if another thread panicked while holding the lock
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.completed += self.weight;
+        state.current_weight = 0.;
+        state.current_fraction = 0.;
     }
+}
 
-    #[inline]
-    fn count(self) -> usize {
-        self.0.iter.count()
-    }
+/// Wraps a `Read`, and counts how many bytes have been read through it, so you can get a
+/// `ProgressRecord` where `.num_done()` is the number of bytes.
+///
+/// ```
+/// # use iter_progress::ProgressReader;
+/// use std::io::Read;
+/// let data = vec![0u8; 100];
+/// let mut reader = ProgressReader::new(&data[..]).assume_size(100);
+/// let mut buf = [0; 40];
+/// reader.read(&mut buf).unwrap();
+/// assert_eq!(reader.record().fraction(), Some(0.4));
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct ProgressReader<R> {
+    inner: R,
+    bytes_done: u64,
+    started_iterating: Instant,
+    previous_record_tm: Cell<Option<Instant>>,
+    assumed_size: Option<u64>,
 }
 
-impl<I: Iterator> OptionalProgressRecorderIter<I> {
-    pub fn new(iter: I, generate_every_count: usize) -> OptionalProgressRecorderIter<I> {
-        OptionalProgressRecorderIter {
-            iter,
-            count: 0,
-            generate_every_count,
+#[cfg(feature = "std")]
+impl<R: Read> ProgressReader<R> {
+    /// Start counting the bytes read from `inner`
+    pub fn new(inner: R) -> ProgressReader<R> {
+        ProgressReader {
+            inner,
+            bytes_done: 0,
             started_iterating: Instant::now(),
-            previous_record_tm: None,
-            rolling_average: None,
-            exp_average: None,
+            previous_record_tm: Cell::new(None),
             assumed_size: None,
-            _fake_now: None,
         }
     }
 
-    /// Set the desired size of the rolling average window calculation (if any). `None` to
-    /// disable.
-    /// Larger values slow down each iteration (since the rolling average is calculated each
-    /// iteration).
-    pub fn with_rolling_average(self, size: impl Into<Option<usize>>) -> Self {
-        let mut res = self;
-        res.rolling_average = size.into().map(|size| (size, vec![0.; size]));
-        res
-    }
-
-    /// Set the desired exponential rate
-    /// 0.001 is a good value.
-    pub fn with_exp_average(self, rate: impl Into<Option<f64>>) -> Self {
-        let mut res = self;
-        res.exp_average = rate.into().map(|rate| (rate, None));
-        res
-    }
-
-    /// Add an 'assumed size' to this iterator. If the iterator doesn't return an exact value for
-    /// `.size_hint()`, you can use this to override
-    /// the `.size_hint()` from the iterator will override this if it returns an exact size (i.e.
-    /// `.size_hint().1 == Some(...size_hint().0).
-    /// Set to `None` to undo this.
-    ///
-    /// ```
-    /// # use iter_progress::ProgressableIter;
-    /// let mut progressor = (0..).progress().assume_size(10);
-    /// let (state, num) = progressor.next().unwrap();
-    /// assert_eq!(state.fraction(), Some(0.1));
-    /// ```
-    pub fn assume_size(self, size: impl Into<Option<usize>>) -> Self {
+    /// Set the total number of bytes (e.g. the file size), so that the `.fraction()` etc. can be
+    /// calculated. `None` to unset.
+    pub fn assume_size(self, size: impl Into<Option<u64>>) -> Self {
         let mut new = self;
         new.assumed_size = size.into();
         new
     }
 
-    /// Calculate the current `ProgressRecord` for where we are now.
-    fn generate_record(&mut self, fake_now: Option<Instant>) -> Option<ProgressRecord> {
-        self.count += 1;
-        if self.count % self.generate_every_count != 0 {
-            return None;
-        }
-
-        let now = fake_now.unwrap_or_else(Instant::now);
-
-        let exp_average_rate = if let Some((rate, last)) = self.exp_average {
-            if let Some(previous_tm) = self.previous_record_tm {
-                let this_duration = now - previous_tm;
-                let current_ema = match last {
-                    None => this_duration,
-                    Some(last) => this_duration.mul_f64(rate) + last.mul_f64(1. - rate),
-                };
-                self.exp_average = Some((rate, Some(current_ema)));
-                Some(current_ema)
-            } else {
-                None
-            }
-        } else {
-            None
-        };
+    /// How many bytes have been read so far
+    pub fn bytes_done(&self) -> u64 {
+        self.bytes_done
+    }
 
-        let rolling_average_duration = match &mut self.rolling_average {
-            None => None,
-            Some((size, values)) => {
-                if let Some(previous_tm) = self.previous_record_tm {
-                    let this_duration = (now - previous_tm).as_secs_f64();
-                    values[self.count % *size] = this_duration;
-                    if self.count < *size {
-                        // We haven't filled up the buffer yet
-                        Some(Duration::from_secs_f64(
-                            values[0..=self.count].iter().sum::<f64>() / (self.count as f64),
-                        ))
-                    } else {
-                        Some(Duration::from_secs_f64(
-                            values.iter().sum::<f64>() / (*size as f64),
-                        ))
-                    }
-                } else {
-                    None
-                }
-            }
-        };
+    /// The `ProgressRecord` for the bytes read so far. The `.previous_record_tm()` is when this
+    /// was last called.
+    pub fn record(&self) -> ProgressRecord {
+        self.record_at(Instant::now())
+    }
 
-        let res = ProgressRecord {
-            num: self.count,
-            iterating_for: now - self.started_iterating,
-            size_hint: self.iter.size_hint(),
+    fn record_at(&self, now: Instant) -> ProgressRecord {
+        ProgressRecord {
             assumed_size: self.assumed_size,
-            assumed_fraction: None,
-            started_iterating: self.started_iterating,
-            previous_record_tm: self.previous_record_tm,
-            rolling_average_duration,
-            exp_average_duration: exp_average_rate,
-        };
-
-        self.previous_record_tm = Some(now);
-
-        Some(res)
+            ..ProgressRecord::new(
+                self.bytes_done,
+                &StdClock,
+                self.started_iterating,
+                self.previous_record_tm.replace(Some(now)),
+                now,
+            )
+        }
     }
 
-    /// Returns referend to the inner iterator
-    pub fn inner(&self) -> &I {
-        &self.iter
+    /// Returns reference to the inner reader
+    pub fn inner(&self) -> &R {
+        &self.inner
     }
 
-    /// Gets the original iterator back, consuming this.
-    pub fn into_inner(self) -> I {
-        self.iter
+    /// Gets the original reader back, consuming this.
+    pub fn into_inner(self) -> R {
+        self.inner
     }
+}
 
-    #[cfg(test)]
-    fn set_fake_now(&mut self, fake_now: impl Into<Option<Instant>>) {
-        self._fake_now = fake_now.into();
+#[cfg(feature = "std")]
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes = self.inner.read(buf)?;
+        self.bytes_done += bytes as u64;
+        Ok(bytes)
     }
 }
 
-pub trait OptionalProgressableIter<I: Iterator> {
-    fn optional_progress(self, generate_every_count: usize) -> OptionalProgressRecorderIter<I>;
+/// A `Read` that records how many bytes have been read through it
+#[cfg(feature = "std")]
+pub trait ProgressableRead<R> {
+    fn progress_bytes(self) -> ProgressReader<R>;
+
+    /// The lines of a `BufRead`, where the progress is the bytes read. See `progress_lines`.
+    fn progress_lines(self, total_bytes: u64) -> ProgressLines<R>
+    where
+        R: BufRead;
 }
 
-impl<I> OptionalProgressableIter<I> for I
+#[cfg(feature = "std")]
+impl<R> ProgressableRead<R> for R
 where
-    I: Iterator,
+    R: Read,
 {
-    /// Convert an iterator into an `OptionalProgressRecorderIter`.
-    fn optional_progress(self, generate_every_count: usize) -> OptionalProgressRecorderIter<I> {
-        OptionalProgressRecorderIter::new(self, generate_every_count)
-    }
-}
-
-impl<I: Iterator> Iterator for OptionalProgressRecorderIter<I> {
-    type Item = (Option<ProgressRecord>, <I as Iterator>::Item);
-
-    #[inline]
-    fn next(&mut self) -> Option<Self::Item> {
-        let fake_now = std::mem::take(&mut self._fake_now);
-        self.iter
-            .next()
-            .map(|a| (self.generate_record(fake_now), a))
+    /// Wrap a reader in a `ProgressReader`, which counts the bytes read.
+    ///
+    /// ```
+    /// # use iter_progress::ProgressableRead;
+    /// use std::io::Read;
+    /// let data = vec![0u8; 1000];
+    /// let mut reader = (&data[..]).progress_bytes().assume_size(1000);
+    /// let mut buf = [0; 250];
+    /// reader.read_exact(&mut buf).unwrap();
+    /// assert_eq!(reader.record().num_done(), 250);
+    /// assert_eq!(reader.record().percent(), Some(25.));
+    /// ```
+    fn progress_bytes(self) -> ProgressReader<R> {
+        ProgressReader::new(self)
     }
 
-    #[inline]
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.iter.size_hint()
+    /// Read the lines of a `BufRead`, where the `.fraction()` is the bytes read out of
+    /// `total_bytes` (e.g. the file size), the same as `progress_lines(...)`.
+    ///
+    /// ```
+    /// # use iter_progress::ProgressableRead;
+    /// let text = "hello\nworld\n";
+    /// let mut lines = text.as_bytes().progress_lines(text.len() as u64);
+    /// let (state, line) = lines.next().unwrap();
+    /// assert_eq!(line.unwrap(), "hello");
+    /// assert_eq!(state.num_done(), 1);
+    /// assert_eq!(state.bytes_done(), 6);
+    /// ```
+    fn progress_lines(self, total_bytes: u64) -> ProgressLines<R>
+    where
+        R: BufRead,
+    {
+        progress_lines(self, total_bytes)
     }
+}
 
-    #[inline]
-    fn count(self) -> usize {
-        self.iter.count()
+/// Like `std::io::copy`, but calls `on_progress` after every chunk is copied, with a
+/// `ProgressRecord` where `.num_done()` is the number of bytes copied so far. If `total` (in
+/// bytes) is given, the `.fraction()` etc. are known. Returns the number of bytes copied.
+///
+/// ```
+/// # use iter_progress::copy_with_progress;
+/// let data = vec![0u8; 100_000];
+/// let mut output = Vec::new();
+/// copy_with_progress(&data[..], &mut output, Some(100_000), |state| {
+///     state.do_every_n_sec(1., |state| {
+///         println!("{:.1}% copied", state.percent().unwrap());
+///     });
+/// }).unwrap();
+/// assert_eq!(output.len(), 100_000);
+/// ```
+#[cfg(feature = "std")]
+pub fn copy_with_progress<R, W, F>(
+    reader: R,
+    writer: W,
+    total: Option<u64>,
+    on_progress: F,
+) -> io::Result<u64>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(&ProgressRecord),
+{
+    let mut writer = writer;
+    let mut on_progress = on_progress;
+    let mut reader = ProgressReader::new(reader).assume_size(total);
+    let mut buf = [0; 8 * 1024];
+    loop {
+        let bytes = match reader.read(&mut buf) {
+            Ok(0) => return Ok(reader.bytes_done()),
+            Ok(bytes) => bytes,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        writer.write_all(&buf[..bytes])?;
+        on_progress(&reader.record());
     }
 }
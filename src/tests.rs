@@ -1,11 +1,51 @@
+#![allow(clippy::bool_assert_comparison, clippy::clone_on_copy)]
+
 use super::*;
 
+/// A `Clock` which is the real time until it's set with `.set_fake_now(...)`, and then stays at
+/// that time. The clones share the time, so it can be set while an iterator has it.
+#[derive(Debug, Clone, Default)]
+struct FakeClock(Arc<Mutex<Option<Instant>>>);
+
+impl Clock for FakeClock {
+    type Instant = Instant;
+
+    fn now(&self) -> Instant {
+        self.0.lock().unwrap().unwrap_or_else(Instant::now)
+    }
+
+    fn duration_between(&self, earlier: Instant, later: Instant) -> Duration {
+        later.saturating_duration_since(earlier)
+    }
+}
+
+impl<I: Iterator> OptionalProgressRecorderIter<I, FakeClock> {
+    fn set_fake_now(&mut self, fake_now: impl Into<Option<Instant>>) {
+        *self.clock.0.lock().unwrap() = fake_now.into();
+    }
+}
+
+/// Like `.progress()` & `.optional_progress(...)`, but with a `FakeClock`
+trait FakeProgressableIter: Iterator + Sized {
+    fn fake_progress(self) -> ProgressRecorderIter<Self, FakeClock> {
+        ProgressRecorderIter::with_clock(self, FakeClock::default())
+    }
+
+    fn fake_optional_progress(
+        self,
+        generate_every_count: usize,
+    ) -> OptionalProgressRecorderIter<Self, FakeClock> {
+        OptionalProgressRecorderIter::with_clock(self, generate_every_count, FakeClock::default())
+    }
+}
+
+impl<I: Iterator> FakeProgressableIter for I {}
+
 #[test]
 fn test_simple() {
-    use super::ProgressableIter;
     use std::time::Duration;
 
-    let mut progressor = (0..).progress();
+    let mut progressor = (0..).fake_progress();
 
     // 0
     let (state, _) = progressor.next().unwrap();
@@ -132,3 +172,3196 @@ fn optional() {
     assert!(progressed_iterator[3].0.is_none());
     assert!(progressed_iterator[4].0.is_none());
 }
+
+#[test]
+fn num_done_u64() {
+    let mut progressor = (0..).progress();
+    // Pretend we've already seen more items than fit in a 32-bit usize
+    progressor.count = u32::MAX as u64 + 10;
+
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.num_done_u64(), u32::MAX as u64 + 11);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.num_done_u64(), u32::MAX as u64 + 12);
+}
+
+#[test]
+fn stable_eta() {
+    let mut progressor = (0..100).fake_progress().with_stable_eta();
+    let (state, _) = progressor.next().unwrap();
+    let mut fake_now = state.started_iterating();
+    assert_eq!(state.eta(), None);
+
+    // A noisy run, with items taking between 100ms & 1,900ms
+    let mut etas = Vec::new();
+    for i in 1..20 {
+        fake_now += Duration::from_millis(100 + (i * 700) % 1900);
+        progressor.set_fake_now(fake_now);
+        let (state, _) = progressor.next().unwrap();
+        etas.push(state.eta());
+    }
+    // Not enough samples yet
+    assert!(etas[..8].iter().all(|eta| eta.is_none()));
+    // Then always whole seconds
+    assert!(etas[8..].iter().all(|eta| eta.unwrap().subsec_nanos() == 0));
+
+    // One very slow item doesn't change the ETA
+    let last_eta = *etas.last().unwrap();
+    fake_now += Duration::from_secs(60);
+    progressor.set_fake_now(fake_now);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.eta(), last_eta);
+
+    // Absurdly long ETAs are unknown
+    let mut progressor = (0..10_000_000).fake_progress().with_stable_eta();
+    let (state, _) = progressor.next().unwrap();
+    let mut fake_now = state.started_iterating();
+    for _ in 1..10 {
+        fake_now += Duration::from_secs(1);
+        progressor.set_fake_now(fake_now);
+        progressor.next().unwrap();
+    }
+    fake_now += Duration::from_secs(1);
+    progressor.set_fake_now(fake_now);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.num_done(), 11);
+    assert_eq!(state.eta(), None);
+}
+
+#[test]
+fn progress_lines1() {
+    // Last line has no newline
+    let text = "abc\nde\r\n\nfghij";
+    let mut lines = progress_lines(text.as_bytes(), text.len() as u64);
+
+    let (state, line) = lines.next().unwrap();
+    assert_eq!(line.unwrap(), "abc");
+    assert_eq!(state.num_done(), 1);
+    assert_eq!(state.fraction(), Some(4. / 14.));
+
+    let (state, line) = lines.next().unwrap();
+    assert_eq!(line.unwrap(), "de");
+    assert_eq!(state.fraction(), Some(8. / 14.));
+
+    let (state, line) = lines.next().unwrap();
+    assert_eq!(line.unwrap(), "");
+    assert_eq!(state.fraction(), Some(9. / 14.));
+
+    let (state, line) = lines.next().unwrap();
+    assert_eq!(line.unwrap(), "fghij");
+    assert_eq!(state.num_done(), 4);
+    assert_eq!(state.fraction(), Some(1.));
+    assert_eq!(lines.bytes_done(), 14);
+
+    assert!(lines.next().is_none());
+}
+
+#[test]
+fn progress_lines_invalid_utf8() {
+    let text: &[u8] = b"abc\n\xff\xfe\nde\n";
+    let mut lines = progress_lines(text, text.len() as u64);
+    assert_eq!(lines.next().unwrap().1.unwrap(), "abc");
+
+    // An error, but the bytes are still read
+    let (state, line) = lines.next().unwrap();
+    assert_eq!(line.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    assert_eq!(state.num_done(), 2);
+    assert_eq!(state.fraction(), Some(7. / 10.));
+    assert_eq!(lines.bytes_done(), 7);
+
+    let (state, line) = lines.next().unwrap();
+    assert_eq!(line.unwrap(), "de");
+    assert_eq!(state.fraction(), Some(1.));
+    assert!(lines.next().is_none());
+}
+
+#[test]
+fn progress_lines_on_finish() {
+    let text = "abc\nde\nf";
+    let (finished, finishes) = mpsc::channel();
+    let mut lines = progress_lines(text.as_bytes(), text.len() as u64).on_finish(move |state| {
+        finished
+            .send((state.num_done(), state.bytes_done(), state.fraction()))
+            .unwrap();
+    });
+    for _ in 0..3 {
+        lines.next().unwrap().1.unwrap();
+        assert!(finishes.try_recv().is_err());
+    }
+    assert!(lines.next().is_none());
+    assert_eq!(finishes.try_recv(), Ok((3, 8, Some(1.))));
+    // Only once
+    assert!(lines.next().is_none());
+    assert!(finishes.try_recv().is_err());
+
+    // Also when only some lines have a record
+    let (finished, finishes) = mpsc::channel();
+    let lines = progress_lines(text.as_bytes(), text.len() as u64)
+        .with_sample_every_bytes(100)
+        .on_finish(move |state| finished.send(state.num_done()).unwrap());
+    assert!(lines
+        .map(|(state, _line)| state)
+        .all(|state| state.is_none()));
+    assert_eq!(finishes.try_recv(), Ok(3));
+}
+
+#[test]
+fn progress_lines_trait() {
+    let text = "first line\nsecond\n\nlast";
+    let lines: Vec<_> = io::BufReader::new(text.as_bytes())
+        .progress_lines(text.len() as u64)
+        .map(|(state, line)| {
+            (
+                line.unwrap(),
+                state.num_done(),
+                state.bytes_done(),
+                state.byte_total(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        lines,
+        vec![
+            ("first line".to_string(), 1, 11, Some(23)),
+            ("second".to_string(), 2, 18, Some(23)),
+            ("".to_string(), 3, 19, Some(23)),
+            ("last".to_string(), 4, 23, Some(23)),
+        ]
+    );
+}
+
+#[test]
+fn progress_lines_builders() {
+    let text = "abc\nde\nf";
+    let buffer = SharedBuffer::default();
+    let mut lines = progress_lines(text.as_bytes(), text.len() as u64)
+        .with_output(Output::Writer(Box::new(buffer.clone())))
+        .with_start_message("Reading")
+        .with_summary_if_longer_than(Duration::ZERO);
+    assert_eq!(buffer.contents(), "");
+    lines.next().unwrap().1.unwrap();
+    assert_eq!(buffer.contents(), "Reading\n");
+    assert_eq!(lines.count(), 2);
+    assert!(buffer
+        .contents()
+        .starts_with("Reading\nFinished 3 items in "));
+
+    // The same with only some lines having a record, which still have the bytes
+    let buffer = SharedBuffer::default();
+    let lines = progress_lines(text.as_bytes(), text.len() as u64)
+        .with_sample_every_bytes(5)
+        .with_output(Output::Writer(Box::new(buffer.clone())))
+        .with_start_message("Reading")
+        .with_summary_if_longer_than(Duration::ZERO);
+    let records: Vec<_> = lines
+        .filter_map(|(state, _line)| state)
+        .map(|state| (state.num_done(), state.bytes_done(), state.byte_total()))
+        .collect();
+    assert_eq!(records, vec![(2, 7, Some(8))]);
+    assert!(buffer
+        .contents()
+        .starts_with("Reading\nFinished 3 items in "));
+}
+
+#[test]
+fn sample_every_bytes() {
+    // Lines of 4, 2, 8, 1, 3, 11 & 1 bytes (including the newline)
+    let text = "abc\nd\nefghijk\n\nlm\nnopqrstuvw\nx";
+    let mut lines = progress_lines(text.as_bytes(), text.len() as u64).with_sample_every_bytes(5);
+
+    let mut sampled_at = Vec::new();
+    while let Some((state, _line)) = lines.next() {
+        if let Some(state) = state {
+            sampled_at.push((state.num_done(), lines.bytes_done()));
+        }
+    }
+    // Records are generated on the line which crosses each multiple of 5 bytes
+    assert_eq!(sampled_at, vec![(2, 6), (3, 14), (4, 15), (6, 29), (7, 30)]);
+}
+
+#[test]
+fn set_position() {
+    let mut progressor = (0..).progress();
+    let mut pos = 0.;
+    let mut fractions = Vec::new();
+    for step in &[0.5, 2.0, 0.25, 1.25, 1.0] {
+        pos += step;
+        progressor.set_position(pos, 5.);
+        let (state, _) = progressor.next().unwrap();
+        fractions.push(state.fraction().unwrap());
+    }
+    assert_eq!(fractions, vec![0.1, 0.5, 0.55, 0.8, 1.0]);
+
+    // Overshooting is clamped
+    progressor.set_position(6., 5.);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.fraction(), Some(1.));
+    assert_eq!(state.num_done(), 6);
+}
+
+#[test]
+fn parallel_snapshot_interval() {
+    use std::sync::{Arc, Mutex};
+
+    let progress = AtomicProgress::new().assume_size(1_000);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let monitor = {
+        let seen = seen.clone();
+        progress.with_parallel_snapshot_interval(Duration::from_millis(1), move |state| {
+            seen.lock().unwrap().push(state.num_done());
+        })
+    };
+
+    let worker = {
+        let progress = progress.clone();
+        std::thread::spawn(move || {
+            for _ in 0..1_000 {
+                progress.inc();
+            }
+        })
+    };
+    worker.join().unwrap();
+    monitor.finish();
+
+    let seen = seen.lock().unwrap();
+    // Counts only go up, and the final snapshot sees everything
+    assert!(seen.windows(2).all(|w| w[0] <= w[1]));
+    assert_eq!(*seen.last().unwrap(), 1_000);
+}
+
+#[test]
+fn scan_progress() {
+    let mut sums = (1..=10).progress().scan_progress(0, |sum, state, x| {
+        *sum += x;
+        if *sum > 20 {
+            None
+        } else {
+            Some((*sum, state.fraction()))
+        }
+    });
+    assert_eq!(sums.next(), Some((1, Some(0.1))));
+    assert_eq!(sums.next(), Some((3, Some(0.2))));
+    assert_eq!(sums.next(), Some((6, Some(0.3))));
+    assert_eq!(sums.next(), Some((10, Some(0.4))));
+    assert_eq!(sums.next(), Some((15, Some(0.5))));
+    // 21 is too big
+    assert_eq!(sums.next(), None);
+    assert_eq!(sums.next(), None);
+}
+
+#[test]
+fn start_message() {
+    let mut progressor = (0..5_000)
+        .progress()
+        .with_start_message("Processing {total} items...");
+    assert_eq!(
+        progressor.start_line(),
+        Some("Processing 5000 items...".to_string())
+    );
+    // Only once
+    assert_eq!(progressor.start_line(), None);
+
+    let mut progressor = (0..)
+        .progress()
+        .with_start_message("Processing {total} items...");
+    assert_eq!(
+        progressor.start_line(),
+        Some("Processing ? items...".to_string())
+    );
+
+    // Printed at the first item, and not again
+    let mut progressor = (0..3).progress().with_start_message("Starting");
+    progressor.next().unwrap();
+    assert_eq!(progressor.start_message, None);
+    assert_eq!(progressor.start_line(), None);
+
+    let mut progressor = (0..3).progress();
+    assert_eq!(progressor.start_line(), None);
+}
+
+#[test]
+fn min_update_interval() {
+    let mut progressor = (0..20)
+        .fake_optional_progress(1)
+        .with_min_update_interval(Duration::from_secs(5));
+    let start = progressor.started_iterating;
+    let mut generated = vec![];
+    for secs in 1..=12 {
+        progressor.set_fake_now(start + Duration::from_secs(secs));
+        let (state, _) = progressor.next().unwrap();
+        if let Some(state) = state {
+            generated.push((secs, state.num_done()));
+        }
+    }
+    assert_eq!(generated, vec![(5, 5), (10, 10)]);
+
+    // Both rules have to allow it
+    let mut progressor = (0..20)
+        .fake_optional_progress(4)
+        .with_min_update_interval(Duration::from_secs(5));
+    let start = progressor.started_iterating;
+    let mut generated = vec![];
+    for secs in 1..=16 {
+        progressor.set_fake_now(start + Duration::from_secs(secs));
+        let (state, _) = progressor.next().unwrap();
+        if let Some(state) = state {
+            generated.push(state.num_done());
+        }
+    }
+    assert_eq!(generated, vec![8, 16]);
+}
+
+#[test]
+fn adaptive_stride() {
+    let mut progressor = (0..1_000)
+        .fake_optional_progress(10)
+        .with_adaptive_stride(64);
+    assert_eq!(progressor.stride(), 1);
+    let mut generated = vec![];
+    let mut strides = vec![];
+    for _ in 0..400 {
+        let (state, _) = progressor.next().unwrap();
+        if let Some(state) = state {
+            generated.push(state.num_done());
+            strides.push(progressor.stride());
+        }
+    }
+    assert_eq!(
+        generated,
+        vec![1, 3, 7, 15, 31, 63, 127, 191, 255, 319, 383]
+    );
+    assert_eq!(strides, vec![2, 4, 8, 16, 32, 64, 64, 64, 64, 64, 64]);
+
+    // It's used for the rate since the previous record
+    let mut progressor = (0..100).fake_optional_progress(1).with_adaptive_stride(8);
+    let start = progressor.started_iterating;
+    let mut rates = vec![];
+    for i in 1..=7 {
+        progressor.set_fake_now(start + Duration::from_secs(i));
+        if let (Some(state), _) = progressor.next().unwrap() {
+            rates.push(state.rate_recent());
+        }
+    }
+    assert_eq!(rates, vec![None, Some(1.), Some(1.)]);
+}
+
+#[test]
+fn averages_with_stride() {
+    // 10 items per second, with a record every 5 items
+    let mut progressor = (0..100)
+        .fake_optional_progress(5)
+        .with_rolling_average(3)
+        .with_exp_average(0.5)
+        .with_weighted_rolling_average(3);
+    let start = progressor.started_iterating;
+    let mut records = vec![];
+    for i in 1..=20 {
+        progressor.set_fake_now(start + Duration::from_millis(100 * i));
+        if let (Some(state), _) = progressor.next().unwrap() {
+            records.push(state);
+        }
+    }
+    assert_eq!(records.len(), 4);
+    assert_eq!(records[0].rolling_average_rate(), None);
+    for state in &records[1..] {
+        assert!((state.rate() - 10.).abs() < 1e-9);
+        assert!((state.rolling_average_rate().unwrap() - 10.).abs() < 1e-6);
+        assert!((state.exp_average_rate().unwrap() - 10.).abs() < 1e-6);
+        assert!((state.weighted_rolling_rate().unwrap() - 10.).abs() < 1e-6);
+        assert_eq!(
+            *state.rolling_average_duration(),
+            Some(Duration::from_millis(100))
+        );
+    }
+
+    // With an adaptive stride too
+    let mut progressor = (0..100)
+        .fake_optional_progress(1)
+        .with_adaptive_stride(8)
+        .with_rolling_average(3);
+    let start = progressor.started_iterating;
+    let mut rates = vec![];
+    for i in 1..=15 {
+        progressor.set_fake_now(start + Duration::from_secs(i));
+        if let (Some(state), _) = progressor.next().unwrap() {
+            rates.push(state.rolling_average_rate());
+        }
+    }
+    assert_eq!(rates, vec![None, Some(1.), Some(1.), Some(1.)]);
+}
+
+#[test]
+fn into_optional() {
+    let mut progressor = (0..100).fake_progress().with_rolling_average(5);
+    let start = progressor.started_iterating;
+    for i in 1..=3 {
+        progressor.set_fake_now(start + Duration::from_secs(i));
+        let (state, _) = progressor.next().unwrap();
+        assert_eq!(state.num_done(), i as usize);
+    }
+
+    let mut progressor = progressor.into_optional(2);
+    assert_eq!(progressor.started_iterating, start);
+    progressor.set_fake_now(start + Duration::from_secs(4));
+    let (state, item) = progressor.next().unwrap();
+    assert_eq!(item, 3);
+    let state = state.unwrap();
+    assert_eq!(state.num_done(), 4);
+    assert_eq!(state.duration_since_start(), Duration::from_secs(4));
+    // The average still has the earlier gaps
+    assert_eq!(state.rolling_average_rate(), Some(1.));
+
+    progressor.set_fake_now(start + Duration::from_secs(5));
+    let (state, _) = progressor.next().unwrap();
+    assert!(state.is_none());
+    progressor.set_fake_now(start + Duration::from_secs(6));
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.unwrap().num_done(), 6);
+}
+
+#[test]
+fn snapshots() {
+    let snapshots: Vec<_> = (0..6).fake_progress().snapshots().collect();
+    assert_eq!(snapshots.len(), 6);
+    assert_eq!(
+        snapshots
+            .iter()
+            .map(|snapshot| (snapshot.num_done, snapshot.total))
+            .collect::<Vec<_>>(),
+        (1..=6).map(|n| (n, Some(6))).collect::<Vec<_>>()
+    );
+
+    let mut progressor = (0..10).fake_optional_progress(3);
+    let start = progressor.started_iterating;
+    progressor.set_fake_now(start + Duration::from_secs(1));
+    let snapshots: Vec<_> = progressor.snapshots().collect();
+    assert_eq!(snapshots.len(), 3);
+    assert_eq!(snapshots[0].num_done, 3);
+    assert_eq!(snapshots[2].num_done, 9);
+    assert_eq!(snapshots[2].fraction(), Some(0.9));
+    assert!(snapshots.iter().all(|s| s.started_iterating == start));
+
+    // All the items are taken
+    let mut taken = 0;
+    let progressor = std::iter::repeat_n((), 7)
+        .inspect(|_| taken += 1)
+        .fake_optional_progress(5);
+    assert_eq!(progressor.snapshots().count(), 1);
+    assert_eq!(taken, 7);
+}
+
+/// A `Write` which can be read afterwards
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedBuffer {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+#[test]
+fn output() {
+    let buffer = SharedBuffer::default();
+    let mut progressor = (0..3)
+        .fake_progress()
+        .with_output(Output::Writer(Box::new(buffer.clone())))
+        .with_start_message("Processing {total} items")
+        .with_summary_if_longer_than(Duration::ZERO);
+    let start = progressor.started_iterating;
+    while let Some((state, _)) = progressor.next() {
+        writeln!(progressor.output(), "{} done", state.num_done()).unwrap();
+        progressor.set_fake_now(start + Duration::from_secs(3));
+    }
+    assert_eq!(
+        buffer.contents(),
+        "Processing 3 items\n1 done\n2 done\n3 done\nFinished 3 items in 3.0 sec (1.0 per sec)\n"
+    );
+
+    // Nothing is written anywhere
+    let mut progressor = (0..3)
+        .fake_progress()
+        .with_output(Output::Sink)
+        .with_start_message("Processing {total} items")
+        .with_summary_if_longer_than(Duration::ZERO);
+    while let Some((state, _)) = progressor.next() {
+        writeln!(progressor.output(), "{} done", state.num_done()).unwrap();
+    }
+    assert_eq!(format!("{:?}", progressor.output()), "Sink");
+}
+
+#[test]
+fn on_finish() {
+    let (finished, finishes) = mpsc::channel();
+    let mut progressor = (0..5).fake_progress().on_finish(move |state| {
+        finished
+            .send((state.num_done(), state.duration_since_start()))
+            .unwrap();
+    });
+    let start = progressor.started_iterating;
+    for _ in 0..5 {
+        progressor.next().unwrap();
+        assert!(finishes.try_recv().is_err());
+    }
+    progressor.set_fake_now(start + Duration::from_secs(7));
+    assert!(progressor.next().is_none());
+    assert_eq!(finishes.try_recv(), Ok((5, Duration::from_secs(7))));
+    // Only once
+    assert!(progressor.next().is_none());
+    drop(progressor);
+    assert_eq!(finishes.try_recv(), Err(mpsc::TryRecvError::Disconnected));
+}
+
+#[test]
+fn progress_builders() {
+    // Configured straight on `.fake_progress()`, not via `.fake_optional_progress(1)`
+    let mut progressor = (0..)
+        .fake_progress()
+        .with_exp_average(0.001)
+        .with_rolling_average(10)
+        .assume_size(200);
+    let start = progressor.started_iterating;
+    let mut state = None;
+    for i in 1..=4 {
+        progressor.set_fake_now(start + Duration::from_millis(250 * i));
+        state = progressor.next().map(|(state, _)| state);
+    }
+    let state = state.unwrap();
+    assert_eq!(state.total(), Some(200));
+    assert_eq!(state.fraction(), Some(0.02));
+    assert!((state.exp_average_rate().unwrap() - 4.).abs() < 1e-9);
+    assert_eq!(state.rolling_average_rate(), Some(4.));
+}
+
+#[test]
+fn stalled() {
+    let mut progressor = (0..10).fake_progress();
+    let start = progressor.started_iterating;
+    let mut stalled = vec![];
+    for &millis in &[500, 1_000, 1_500, 3_500, 4_000, 5_000] {
+        progressor.set_fake_now(start + Duration::from_millis(millis));
+        let (state, _) = progressor.next().unwrap();
+        stalled.push(state.stalled(Duration::from_secs(1)));
+    }
+    // Exactly the threshold isn't stalled
+    assert_eq!(stalled, vec![false, false, false, true, false, false]);
+}
+
+#[test]
+fn rate_percentile() {
+    let mut progressor = (0..100)
+        .fake_progress()
+        .with_duration_history(5)
+        .with_rolling_average(5);
+    assert_eq!(progressor.rate_percentile(0.5), None);
+    let start = progressor.started_iterating;
+    // Items take 1s, 0.5s, 0.25s, 10s, 0.5s, 0.5s
+    let mut secs = 0.;
+    let mut state = None;
+    for &duration in &[1., 0.5, 0.25, 10., 0.5, 0.5] {
+        secs += duration;
+        progressor.set_fake_now(start + Duration::from_secs_f64(secs));
+        state = progressor.next().map(|(state, _)| state);
+    }
+    // The first has been forgotten, so the rates are 2, 4, 0.1, 2, 2
+    assert_eq!(progressor.rate_percentile(0.5), Some(2.));
+    assert_eq!(progressor.rate_percentile(0.), Some(0.1));
+    assert_eq!(progressor.rate_percentile(1.), Some(4.));
+    // Whereas the slow item pulls the rolling average right down, to 1 / 2.35s
+    assert!(state.unwrap().rolling_average_rate().unwrap() < 0.5);
+
+    assert_eq!((0..10).fake_progress().rate_percentile(0.5), None);
+}
+
+#[test]
+fn reset() {
+    let mut progressor = (0..100)
+        .fake_progress()
+        .with_rolling_average(3)
+        .with_exp_average(0.5);
+    let start = progressor.started_iterating;
+    for i in 1..=5 {
+        progressor.set_fake_now(start + Duration::from_secs(i));
+        progressor.next().unwrap();
+    }
+
+    progressor.reset();
+    let restart = progressor.started_iterating;
+    progressor.set_fake_now(restart + Duration::from_millis(500));
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.num_done(), 1);
+    assert_eq!(state.duration_since_start(), Duration::from_millis(500));
+    assert_eq!(state.previous_record_tm(), None);
+    assert_eq!(state.rolling_average_rate(), None);
+    assert_eq!(state.exp_average_rate(), None);
+    // Still set up
+    progressor.set_fake_now(restart + Duration::from_millis(1_000));
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.num_done(), 2);
+    assert_eq!(state.rolling_average_rate(), Some(2.));
+    assert_eq!(state.exp_average_rate(), Some(2.));
+
+    // The settings are kept
+    let mut progressor = (0..).fake_progress().assume_size(10);
+    progressor.nth(4);
+    progressor.reset();
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.fraction(), Some(0.1));
+}
+
+#[test]
+fn reset_cost_model_and_handle() {
+    let mut progressor = (0..4)
+        .fake_progress()
+        .with_cost_model(|i| (i + 1) as f64)
+        .with_handle();
+    let handle = progressor.handle().unwrap();
+    let start = progressor.started_iterating;
+    progressor.set_fake_now(start + Duration::from_secs(1));
+    progressor.next().unwrap();
+    progressor.set_fake_now(start + Duration::from_secs(3));
+    progressor.next().unwrap();
+
+    // The restart uses the fake clock, like the records
+    let restart = start + Duration::from_secs(10);
+    progressor.set_fake_now(restart);
+    progressor.reset();
+    assert_eq!(progressor.started_iterating, restart);
+    let snapshot = handle.snapshot();
+    assert_eq!(snapshot.num_done, 0);
+    assert_eq!(snapshot.started_iterating, restart);
+
+    // 2 items left, so cost 1 done in 1s, out of 1+2 = 3, so 2s to go. Without the reset, the
+    // stale cost done would give 0s
+    progressor.set_fake_now(restart + Duration::from_secs(1));
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.num_done(), 1);
+    assert_eq!(state.duration_since_start(), Duration::from_secs(1));
+    assert_eq!(state.eta(), Some(Duration::from_secs(2)));
+    assert_eq!(handle.snapshot().num_done, 1);
+}
+
+#[test]
+fn summary_if_longer_than() {
+    // Quick run, no summary
+    let mut progressor = (0..3)
+        .progress()
+        .with_summary_if_longer_than(Duration::from_secs(10));
+    let (state, _) = progressor.next().unwrap();
+    let start = state.started_iterating();
+    progressor.next().unwrap();
+    progressor.next().unwrap();
+    assert_eq!(progressor.summary(start + Duration::from_secs(2)), None);
+
+    // Long run, summary
+    let mut progressor = (0..3)
+        .progress()
+        .with_summary_if_longer_than(Duration::from_secs(10));
+    let (state, _) = progressor.next().unwrap();
+    let start = state.started_iterating();
+    progressor.next().unwrap();
+    progressor.next().unwrap();
+    assert_eq!(
+        progressor.summary(start + Duration::from_secs(20)),
+        Some("Finished 3 items in 20.0 sec (0.1 per sec)".to_string())
+    );
+    // Only once
+    assert_eq!(progressor.summary(start + Duration::from_secs(20)), None);
+}
+
+#[test]
+fn optional_do_every() {
+    use std::cell::Cell;
+
+    let mut progressor = (0..10).fake_optional_progress(5);
+    let start = progressor.started_iterating;
+    let items_called = Cell::new(Vec::new());
+    let secs_called = Cell::new(Vec::new());
+    let mut i = 0;
+    while let Some((_state, _)) = progressor.next() {
+        i += 1;
+        progressor.set_fake_now(start + Duration::from_millis(400 * i));
+        progressor.do_every_n_items(3, |state| {
+            let mut called = items_called.take();
+            called.push((state.num_done(), state.fraction()));
+            items_called.set(called);
+        });
+        progressor.do_every_n_sec(1., |state| {
+            let mut called = secs_called.take();
+            called.push(state.num_done());
+            secs_called.set(called);
+        });
+    }
+    assert_eq!(
+        items_called.take(),
+        vec![
+            (1, Some(0.1)),
+            (4, Some(0.4)),
+            (7, Some(0.7)),
+            (10, Some(1.0))
+        ]
+    );
+    // Every 1 sec, with items every 0.4 sec
+    assert_eq!(secs_called.take(), vec![3, 5, 8, 10]);
+}
+
+#[test]
+fn weighted_regression_eta() {
+    // Item i weighs i, and takes i * 10ms, so later items are slower
+    let total_weight = (1..=100).sum::<u64>() as f64;
+    let mut progressor = (1..=100u64)
+        .fake_progress()
+        .with_weights(total_weight, |i| *i as f64)
+        .with_eta_from_weighted_regression(10);
+    let start = progressor.started_iterating;
+    let mut elapsed = Duration::ZERO;
+    for i in 1..=50 {
+        elapsed += Duration::from_millis(10 * i);
+        progressor.set_fake_now(start + elapsed);
+        let (state, _) = progressor.next().unwrap();
+        if i < 10 {
+            // Not enough samples yet, but the naive ETA is available
+            assert!(state.model_eta.is_none());
+        }
+    }
+    elapsed += Duration::from_millis(510);
+    progressor.set_fake_now(start + elapsed);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.weight_done(), (1..=51).sum::<u64>() as f64);
+
+    let actual = Duration::from_millis((52..=100).map(|i| 10 * i).sum());
+    let naive = state
+        .duration_since_start()
+        .div_f64(state.fraction().unwrap())
+        - state.duration_since_start();
+    let regression = state.eta().unwrap();
+    let error = |eta: Duration| (eta.as_secs_f64() - actual.as_secs_f64()).abs();
+    assert!(error(regression) < error(naive));
+    assert!(error(regression) < 0.01);
+}
+
+#[test]
+fn count_predicate() {
+    let results: Vec<_> = (0..10)
+        .progress()
+        .assume_size(5)
+        .with_count_predicate(|i| i % 2 == 1)
+        .map(|(state, i)| (i, state.num_done(), state.fraction()))
+        .collect();
+
+    // All items are still returned
+    assert_eq!(results.len(), 10);
+    assert_eq!(results[0], (0, 0, Some(0.)));
+    assert_eq!(results[1], (1, 1, Some(0.2)));
+    assert_eq!(results[2], (2, 1, Some(0.2)));
+    assert_eq!(results[3], (3, 2, Some(0.4)));
+    assert_eq!(results[9], (9, 5, Some(1.)));
+
+    // The same things happen for each item as without the predicate
+    let output = SharedBuffer::default();
+    let mut progressor = (0..10)
+        .progress()
+        .assume_size(5)
+        .with_handle()
+        .with_timestamps()
+        .with_start_message("Counting {total}")
+        .with_output(Output::Writer(Box::new(output.clone())))
+        .with_count_predicate(|i| i % 2 == 1);
+    let handle = progressor.handle().unwrap();
+    for _ in 0..4 {
+        progressor.next().unwrap();
+    }
+    assert_eq!(output.contents(), "Counting 5\n");
+    assert_eq!(handle.snapshot().num_done, 2);
+    assert_eq!(handle.snapshot().total, Some(5));
+    assert_eq!(progressor.timestamps().len(), 4);
+
+    // The assumed size is only the matching half, but all the items are yielded, so it isn't
+    // the size hint
+    let progressor = (0..10)
+        .filter(|_| true)
+        .progress()
+        .assume_size(5)
+        .with_count_predicate(|i| i % 2 == 1);
+    assert_eq!(progressor.size_hint(), (0, Some(10)));
+    assert_eq!(progressor.count(), 10);
+}
+
+#[test]
+fn count_predicate_nothing_counted() {
+    // The first items don't match, so nothing has been counted for them
+    let mut progressor = (0..10)
+        .fake_progress()
+        .assume_size(5)
+        .with_count_predicate(|&i| i >= 3);
+    let start = progressor.started_iterating;
+    for i in 1..=3 {
+        progressor.set_fake_now(start + Duration::from_secs(i));
+        let (state, _) = progressor.next().unwrap();
+        assert_eq!(state.num_done(), 0);
+        assert_eq!(state.fraction(), Some(0.));
+        assert_eq!(state.eta(), None);
+        assert_eq!(state.estimated_total_time(), None);
+        assert_eq!(state.fields().eta, None);
+        assert!(state.to_json_line().contains("\"eta_secs\":null"));
+        assert_eq!(state.message("ETA {eta}"), "ETA ?");
+        assert!(!state.should_do_every_n_items(2));
+        assert_eq!(state.error_rate(), 0.);
+    }
+    progressor.set_fake_now(start + Duration::from_secs(4));
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.num_done(), 1);
+    assert!(state.should_do_every_n_items(2));
+    assert_eq!(state.eta(), Some(Duration::from_secs(16)));
+}
+
+#[test]
+fn bar_auto() {
+    let mut progressor = (0..4).fake_progress();
+    let (state, _) = progressor.next().unwrap();
+    let start = state.started_iterating();
+    progressor.set_fake_now(start + Duration::from_secs(2));
+    let (state, _) = progressor.next().unwrap();
+
+    let bar = state.bar_for_terminal_width(Some(40)).unwrap();
+    assert_eq!(bar, "[#########---------]  50.0% 1.0/s ETA 2s");
+    assert_eq!(bar.len(), 40);
+    // Not a terminal
+    assert_eq!(state.bar_for_terminal_width(None).unwrap().len(), 80);
+
+    let mut progressor = (0..).fake_progress();
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.bar_for_terminal_width(Some(40)), None);
+}
+
+#[test]
+fn percent_rounded() {
+    let mut progressor = (0..3).progress();
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.percent_rounded(0), Some(33.));
+    assert_eq!(state.percent_rounded(1), Some(33.3));
+    assert_eq!(state.percent_rounded(2), Some(33.33));
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.percent_rounded(0), Some(67.));
+    assert_eq!(state.percent_rounded(2), Some(66.67));
+
+    let mut progressor = (0..1_000).progress();
+    let (state, _) = progressor.nth(120).unwrap();
+    assert_eq!(state.percent_rounded(1), Some(12.1));
+
+    let (state, _) = (0..).progress().next().unwrap();
+    assert_eq!(state.percent_rounded(1), None);
+}
+
+#[test]
+fn bar() {
+    let mut progressor = (0..4).progress();
+    let (mut state, _) = progressor.next().unwrap();
+    assert_eq!(state.bar(8), Some("##------".to_string()));
+    assert_eq!(state.bar(0), Some("".to_string()));
+
+    state.assume_fraction(0.);
+    assert_eq!(state.bar(8), Some("--------".to_string()));
+    state.assume_fraction(0.5);
+    assert_eq!(state.bar(8), Some("####----".to_string()));
+    state.assume_fraction(1.);
+    assert_eq!(state.bar(8), Some("########".to_string()));
+    // Clamped
+    state.assume_fraction(1.5);
+    assert_eq!(state.bar(8), Some("########".to_string()));
+    state.assume_fraction(-0.5);
+    assert_eq!(state.bar(8), Some("--------".to_string()));
+
+    let (state, _) = (0..).progress().next().unwrap();
+    assert_eq!(state.bar(8), None);
+}
+
+#[test]
+fn ahead_of_schedule() {
+    let mut progressor = (0..10)
+        .fake_progress()
+        .with_time_budget(Duration::from_secs(100));
+    let start = progressor.started_iterating;
+
+    // 10% done after 5% of the budget
+    progressor.set_fake_now(start + Duration::from_secs(5));
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.time_fraction(), Some(0.05));
+    assert_eq!(state.schedule_skew(), Some(0.05));
+    assert_eq!(state.ahead_of_schedule(), Some(true));
+
+    // 20% done after 40% of the budget
+    progressor.set_fake_now(start + Duration::from_secs(40));
+    let (state, _) = progressor.next().unwrap();
+    assert!((state.schedule_skew().unwrap() + 0.2).abs() < 1e-9);
+    assert_eq!(state.ahead_of_schedule(), Some(false));
+
+    // No total
+    let mut progressor = (0..)
+        .fake_progress()
+        .with_time_budget(Duration::from_secs(100));
+    let (state, _) = progressor.next().unwrap();
+    assert!(state.time_fraction().is_some());
+    assert_eq!(state.ahead_of_schedule(), None);
+
+    // Zero budget
+    let mut progressor = (0..10).fake_progress().with_time_budget(Duration::ZERO);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.time_fraction(), None);
+}
+
+#[test]
+fn time_budget() {
+    let mut progressor = (0..10)
+        .fake_progress()
+        .with_time_budget(Duration::from_secs(10));
+    let start = progressor.started_iterating;
+
+    progressor.set_fake_now(start + Duration::from_secs(1));
+    let (state, _) = progressor.next().unwrap();
+    assert!(!state.over_budget());
+    assert_eq!(state.budget_remaining(), Some(Duration::from_secs(9)));
+    // 9 sec left, and 9 sec to go
+    assert_eq!(state.will_exceed_budget(), Some(false));
+
+    progressor.set_fake_now(start + Duration::from_secs(6));
+    let (state, _) = progressor.next().unwrap();
+    assert!(!state.over_budget());
+    assert_eq!(state.budget_remaining(), Some(Duration::from_secs(4)));
+    // Slowed down, 24 sec to go
+    assert_eq!(state.will_exceed_budget(), Some(true));
+
+    progressor.set_fake_now(start + Duration::from_secs(11));
+    let (state, _) = progressor.next().unwrap();
+    assert!(state.over_budget());
+    assert_eq!(state.budget_remaining(), Some(Duration::ZERO));
+
+    // No budget
+    let mut progressor = (0..10).fake_progress();
+    let (state, _) = progressor.next().unwrap();
+    assert!(!state.over_budget());
+    assert_eq!(state.budget_remaining(), None);
+    assert_eq!(state.will_exceed_budget(), None);
+}
+
+#[test]
+fn nth() {
+    let mut stepped = (0..100).progress();
+    for _ in 0..10 {
+        stepped.next().unwrap();
+    }
+    let (stepped_state, stepped_val) = stepped.next().unwrap();
+
+    let mut skipped = (0..100).progress();
+    let (skipped_state, skipped_val) = skipped.nth(10).unwrap();
+
+    assert_eq!(skipped_val, stepped_val);
+    assert_eq!(skipped_state.num_done(), stepped_state.num_done());
+    assert_eq!(skipped_state.fraction(), stepped_state.fraction());
+    // Only one record was generated
+    assert!(skipped_state.previous_record_tm().is_none());
+
+    // Carries on afterwards
+    let (state, val) = skipped.next().unwrap();
+    assert_eq!(val, 11);
+    assert_eq!(state.num_done(), 12);
+
+    assert!(skipped.nth(1_000).is_none());
+
+    let mut optional = (0..100).optional_progress(5);
+    let (state, val) = optional.nth(4).unwrap();
+    assert_eq!(val, 4);
+    assert_eq!(state.unwrap().num_done(), 5);
+}
+
+#[test]
+fn nth_past_the_end() {
+    let (finished, finishes) = mpsc::channel();
+    let mut progressor = (0..3).progress().on_finish(move |state| {
+        finished.send(state.num_done()).unwrap();
+    });
+    assert!(progressor.nth(10).is_none());
+    assert_eq!(finishes.try_recv(), Ok(3));
+
+    // Without an exact size hint
+    let (finished, finishes) = mpsc::channel();
+    let mut progressor = (0..8)
+        .filter(|i| i % 2 == 0)
+        .progress()
+        .on_finish(move |state| {
+            finished.send(state.num_done()).unwrap();
+        });
+    let (state, val) = progressor.nth(1).unwrap();
+    assert_eq!((state.num_done(), val), (2, 2));
+    assert!(progressor.nth(10).is_none());
+    assert_eq!(finishes.try_recv(), Ok(4));
+
+    let (finished, finishes) = mpsc::channel();
+    let mut progressor = (0..3).optional_progress(2).on_finish(move |state| {
+        finished.send(state.num_done()).unwrap();
+    });
+    assert!(progressor.nth(100).is_none());
+    assert_eq!(finishes.try_recv(), Ok(3));
+
+    // `.skip(...)` uses `.nth(...)`
+    let output = SharedBuffer::default();
+    let skipped: Vec<_> = (0..3)
+        .progress()
+        .with_output(Output::Writer(Box::new(output.clone())))
+        .with_summary_if_longer_than(Duration::ZERO)
+        .skip(100)
+        .collect();
+    assert!(skipped.is_empty());
+    assert!(
+        output.contents().contains("Finished 3 items"),
+        "{}",
+        output.contents()
+    );
+}
+
+#[test]
+fn display_total() {
+    let mut progressor = (0..9_876).progress().with_display_total(10_000);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.display_total(), Some(10_000));
+    assert_eq!(state.fraction(), Some(1. / 9_876.));
+
+    // Falls back to the actual total
+    let mut progressor = (0..9_876).progress();
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.display_total(), Some(9_876));
+
+    let mut progressor = (0..).progress();
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.display_total(), None);
+}
+
+#[test]
+fn aggregate_snapshots() {
+    let start = Instant::now();
+    let snapshot = |num_done, total, started_after, elapsed| ProgressSnapshot {
+        num_done,
+        total,
+        started_iterating: start + Duration::from_secs(started_after),
+        elapsed: Duration::from_secs(elapsed),
+    };
+    let snapshots = [
+        snapshot(100, Some(200), 0, 10),
+        snapshot(50, Some(100), 5, 5),
+        snapshot(30, Some(300), 10, 3),
+    ];
+
+    assert_eq!(aggregate_rate(&snapshots), 10. + 10. + 10.);
+
+    let combined = aggregate_progress(&snapshots).unwrap();
+    assert_eq!(combined.num_done, 180);
+    assert_eq!(combined.total, Some(600));
+    assert_eq!(combined.fraction(), Some(0.3));
+    assert_eq!(combined.started_iterating, start);
+    assert_eq!(combined.elapsed, Duration::from_secs(13));
+    assert_eq!(combined.rate(), 180. / 13.);
+
+    // Any unknown total makes the combined total unknown
+    let combined = aggregate_progress(&[snapshots[0], snapshot(1, None, 0, 1)]).unwrap();
+    assert_eq!(combined.total, None);
+
+    assert_eq!(aggregate_progress(&[]), None);
+}
+
+#[test]
+fn human_eta() {
+    // 1 of 2 items done after `elapsed`, so the ETA is `elapsed` too
+    let human_eta = |elapsed| {
+        let mut progressor = (0..2).fake_progress();
+        let start = progressor.started_iterating;
+        progressor.set_fake_now(start + elapsed);
+        let (state, _) = progressor.next().unwrap();
+        state.human_eta()
+    };
+    assert_eq!(human_eta(Duration::from_secs(45)), Some("45s".to_string()));
+    assert_eq!(
+        human_eta(Duration::from_secs(150)),
+        Some("2m 30s".to_string())
+    );
+    assert_eq!(
+        human_eta(Duration::from_secs(3723)),
+        Some("1h 2m 3s".to_string())
+    );
+    // No leading zero units, but the others are kept
+    assert_eq!(
+        human_eta(Duration::from_secs(3600)),
+        Some("1h 0m 0s".to_string())
+    );
+    assert_eq!(
+        human_eta(Duration::from_millis(500)),
+        Some("<1s".to_string())
+    );
+
+    let (state, _) = (0..).fake_progress().next().unwrap();
+    assert_eq!(state.human_eta(), None);
+}
+
+#[test]
+fn total() {
+    // Exact size hint
+    let mut progressor = (0..10).progress();
+    let (state, _) = progressor.nth(3).unwrap();
+    assert_eq!(state.total(), Some(10));
+    assert_eq!(state.fraction(), Some(0.4));
+
+    // The exact size hint wins over the assumed size
+    let mut progressor = (0..10).progress().assume_size(20);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.total(), Some(10));
+
+    // Inexact size hint
+    let mut progressor = (0..10).filter(|_| true).progress().assume_size(20);
+    let (state, _) = progressor.nth(4).unwrap();
+    assert_eq!(state.total(), Some(20));
+    assert_eq!(state.fraction(), Some(0.25));
+
+    let (state, _) = (0..10).filter(|_| true).progress().next().unwrap();
+    assert_eq!(state.total(), None);
+    assert_eq!(state.fraction(), None);
+}
+
+#[test]
+fn exact_size() {
+    let mut progressor = (0..10).progress();
+    assert_eq!(progressor.len(), 10);
+    progressor.next();
+    progressor.nth(2);
+    assert_eq!(progressor.len(), 6);
+    assert_eq!(progressor.by_ref().count(), 6);
+    assert_eq!(progressor.len(), 0);
+
+    let mut progressor = (0..10).optional_progress(3);
+    assert_eq!(progressor.len(), 10);
+    progressor.next();
+    assert_eq!(progressor.len(), 9);
+}
+
+#[test]
+fn double_ended() {
+    let results: Vec<_> = vec!['a', 'b', 'c', 'd']
+        .into_iter()
+        .progress()
+        .rev()
+        .map(|(state, c)| (c, state.num_done(), state.fraction()))
+        .collect();
+    assert_eq!(
+        results,
+        vec![
+            ('d', 1, Some(0.25)),
+            ('c', 2, Some(0.5)),
+            ('b', 3, Some(0.75)),
+            ('a', 4, Some(1.))
+        ]
+    );
+
+    // Both ends count the same
+    let mut progressor = (0..10).progress();
+    let (_, first) = progressor.next().unwrap();
+    let (state, last) = progressor.next_back().unwrap();
+    assert_eq!((first, last), (0, 9));
+    assert_eq!(state.num_done(), 2);
+    assert_eq!(state.fraction(), Some(0.2));
+}
+
+#[test]
+fn progress_cycles() {
+    let results: Vec<_> = (0..3)
+        .cycle()
+        .progress_cycles(3, 4)
+        .map(|(state, i)| (i, state.num_done(), state.fraction()))
+        .collect();
+    assert_eq!(results.len(), 12);
+    assert_eq!(results[0], (0, 1, Some(1. / 12.)));
+    // The 2nd pass
+    assert_eq!(results[3], (0, 4, Some(4. / 12.)));
+    assert_eq!(results[5], (2, 6, Some(0.5)));
+    assert_eq!(results[11], (2, 12, Some(1.)));
+}
+
+#[test]
+fn remaining() {
+    let remaining: Vec<_> = vec!['a', 'b', 'c', 'd', 'e']
+        .into_iter()
+        .progress()
+        .map(|(state, _)| state.remaining())
+        .collect();
+    assert_eq!(remaining, vec![Some(4), Some(3), Some(2), Some(1), Some(0)]);
+
+    // More done than assumed
+    let mut progressor = (0..).progress().assume_size(2);
+    let (state, _) = progressor.nth(4).unwrap();
+    assert_eq!(state.remaining(), Some(0));
+
+    let (state, _) = (0..).progress().next().unwrap();
+    assert_eq!(state.remaining(), None);
+}
+
+#[test]
+fn size_hint_from_assumed_size() {
+    let mut n = 0;
+    let generator = std::iter::from_fn(move || {
+        n += 1;
+        if n <= 5 {
+            Some(n)
+        } else {
+            None
+        }
+    });
+    let mut progressor = generator.progress().assume_size(4);
+    assert_eq!(progressor.size_hint(), (4, Some(4)));
+    progressor.next();
+    assert_eq!(progressor.size_hint(), (3, Some(3)));
+    progressor.nth(2);
+    assert_eq!(progressor.size_hint(), (0, Some(0)));
+    // More than assumed
+    progressor.next();
+    assert_eq!(progressor.size_hint(), (0, Some(0)));
+
+    // An inexact hint is replaced, but the assumed size is kept within it
+    let mut progressor = (0..10).filter(|_| true).progress().assume_size(4);
+    assert_eq!(progressor.size_hint(), (4, Some(4)));
+    progressor.next();
+    assert_eq!(progressor.size_hint(), (3, Some(3)));
+    let progressor = (0..10).filter(|_| true).progress().assume_size(20);
+    assert_eq!(progressor.size_hint(), (10, Some(10)));
+    // An infinite iterator's hint is `(usize::MAX, None)`, so the assumed size is used
+    let mut progressor = (0..).progress().assume_size(5);
+    assert_eq!(progressor.size_hint(), (5, Some(5)));
+    progressor.nth(1);
+    assert_eq!(progressor.size_hint(), (3, Some(3)));
+    let progressor = (0..10).chain(0..).progress().assume_size(4);
+    assert_eq!(progressor.size_hint(), (4, Some(4)));
+    // A lower bound is kept to
+    let progressor = (0..10).chain(std::iter::from_fn(|| Some(1))).progress();
+    assert_eq!(progressor.size_hint(), (10, None));
+    assert_eq!(progressor.assume_size(4).size_hint(), (10, Some(10)));
+
+    // The assumed size is in units, and the hint is in items
+    let mut progressor = (0..)
+        .progress()
+        .with_count_multiplier(100)
+        .assume_size(1_000);
+    assert_eq!(progressor.size_hint(), (10, Some(10)));
+    progressor.nth(2);
+    assert_eq!(progressor.size_hint(), (7, Some(7)));
+    let progressor = (0..).progress().with_count_multiplier(100).assume_size(950);
+    assert_eq!(progressor.size_hint(), (9, Some(9)));
+
+    // An exact hint is always used
+    let mut progressor = (0..10).progress().assume_size(4);
+    assert_eq!(progressor.size_hint(), (10, Some(10)));
+    progressor.next();
+    assert_eq!(progressor.size_hint(), (9, Some(9)));
+    let progressor = (0..10).progress().assume_size(20);
+    assert_eq!(progressor.size_hint(), (10, Some(10)));
+    // The record's `.size_hint()` is still the inner iterator's
+    let (state, _) = (0..10)
+        .filter(|_| true)
+        .progress()
+        .assume_size(4)
+        .next()
+        .unwrap();
+    assert_eq!(state.size_hint(), (0, Some(9)));
+
+    // Nothing assumed
+    let progressor = std::iter::from_fn(|| Some(1)).progress();
+    assert_eq!(progressor.size_hint(), (0, None));
+}
+
+#[test]
+fn elapsed_human() {
+    let elapsed_human = |elapsed| {
+        let mut progressor = (0..).fake_progress();
+        let start = progressor.started_iterating;
+        progressor.set_fake_now(start + elapsed);
+        let (state, _) = progressor.next().unwrap();
+        state.elapsed_human()
+    };
+    assert_eq!(elapsed_human(Duration::from_millis(300)), "<1s");
+    assert_eq!(elapsed_human(Duration::from_millis(45_900)), "45s");
+    assert_eq!(elapsed_human(Duration::from_secs(150)), "2m 30s");
+    assert_eq!(elapsed_human(Duration::from_secs(3723)), "1h 2m 3s");
+    assert_eq!(elapsed_human(Duration::from_secs(90_000)), "25h 0m 0s");
+}
+
+#[test]
+fn progress_bytes() {
+    let data: Vec<u8> = (0..100).collect();
+    let mut reader = (&data[..]).progress_bytes().assume_size(data.len() as u64);
+    let mut buf = [0; 30];
+    assert_eq!(reader.read(&mut buf).unwrap(), 30);
+    assert_eq!(reader.record().num_done(), 30);
+    assert_eq!(reader.read(&mut buf).unwrap(), 30);
+    assert_eq!(buf[0], 30);
+    let state = reader.record();
+    assert_eq!(state.num_done(), 60);
+    assert_eq!(state.total(), Some(100));
+    assert_eq!(state.fraction(), Some(0.6));
+
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest.len(), 40);
+    assert_eq!(reader.bytes_done(), 100);
+    assert_eq!(reader.record().fraction(), Some(1.));
+
+    // Totals over 4 GiB are kept as they are, even on 32-bit platforms
+    let total = 8 * 1024 * 1024 * 1024_u64;
+    let mut reader = (&data[..]).progress_bytes().assume_size(total);
+    reader.read_to_end(&mut Vec::new()).unwrap();
+    assert_eq!(reader.record().resolved_total(), Some(total));
+    assert_eq!(reader.record().fraction(), Some(100. / total as f64));
+}
+
+#[test]
+fn human_bytes_units() {
+    assert_eq!(human_bytes(0.), "0 B");
+    assert_eq!(human_bytes(1023.), "1023 B");
+    assert_eq!(human_bytes(1024.), "1.0 KiB");
+    assert_eq!(human_bytes(1536.), "1.5 KiB");
+    assert_eq!(human_bytes(1024. * 1024. - 1.), "1024.0 KiB");
+    assert_eq!(human_bytes(1024. * 1024.), "1.0 MiB");
+    assert_eq!(human_bytes(1024. * 1024. * 1024.), "1.0 GiB");
+    assert_eq!(human_bytes(5000. * 1024. * 1024. * 1024.), "5000.0 GiB");
+}
+
+#[test]
+fn bytes_human() {
+    let data = vec![0u8; 3 * 1024 * 1024];
+    let mut reader = (&data[..]).progress_bytes();
+    let start = reader.started_iterating;
+
+    let mut buf = vec![0; 512];
+    reader.read_exact(&mut buf).unwrap();
+    let state = reader.record_at(start + Duration::from_secs(1));
+    assert_eq!(state.bytes_done_human(), "512 B");
+    assert_eq!(state.bytes_rate_human(), "512 B/s");
+
+    let mut buf = vec![0; 1024 - 512];
+    reader.read_exact(&mut buf).unwrap();
+    let state = reader.record_at(start + Duration::from_secs(1));
+    assert_eq!(state.bytes_done_human(), "1.0 KiB");
+    assert_eq!(state.bytes_rate_human(), "1.0 KiB/s");
+
+    reader.read_to_end(&mut Vec::new()).unwrap();
+    let state = reader.record_at(start + Duration::from_secs(2));
+    assert_eq!(state.bytes_done_human(), "3.0 MiB");
+    assert_eq!(state.bytes_rate_human(), "1.5 MiB/s");
+}
+
+#[test]
+fn fraction_clamped() {
+    let fractions: Vec<_> = (0..)
+        .progress()
+        .assume_size(3)
+        .take(5)
+        .map(|(state, _)| {
+            (
+                state.fraction(),
+                state.fraction_clamped(),
+                state.percent_clamped(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        fractions[1],
+        (Some(2. / 3.), Some(2. / 3.), Some(2. / 3. * 100.))
+    );
+    assert_eq!(fractions[2], (Some(1.), Some(1.), Some(100.)));
+    assert_eq!(fractions[4], (Some(5. / 3.), Some(1.), Some(100.)));
+
+    let mut progressor = (0..).progress();
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.fraction_clamped(), None);
+    assert_eq!(state.percent_clamped(), None);
+}
+
+#[test]
+fn is_first_and_last() {
+    let flags: Vec<_> = vec!['a', 'b', 'c', 'd']
+        .into_iter()
+        .progress()
+        .map(|(state, _)| (state.is_first(), state.is_last()))
+        .collect();
+    assert_eq!(
+        flags,
+        vec![
+            (true, Some(false)),
+            (false, Some(false)),
+            (false, Some(false)),
+            (false, Some(true)),
+        ]
+    );
+
+    let mut progressor = (0..).progress();
+    let (state, _) = progressor.next().unwrap();
+    assert!(state.is_first());
+    assert_eq!(state.is_last(), None);
+    let (state, _) = progressor.next().unwrap();
+    assert!(!state.is_first());
+    assert_eq!(state.is_last(), None);
+}
+
+#[test]
+fn message_display_total_and_bytes() {
+    let lines = vec!["ab", "cde", "f"];
+    let mut progressor = lines
+        .into_iter()
+        .fake_progress()
+        .with_display_total(10)
+        .with_bytes(20, |line| line.len() as u64);
+    let start = progressor.started_iterating;
+    progressor.set_fake_now(start + Duration::from_secs(2));
+    progressor.next().unwrap();
+    progressor.set_fake_now(start + Duration::from_secs(4));
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(
+        state.message("{num}/{total}, {bytes}/{byte_total} bytes at {byte_rate}/s"),
+        "2/10, 5/20 bytes at 1.2/s"
+    );
+
+    // Bytes without a total
+    let (state, _) = vec!["ab"]
+        .into_iter()
+        .fake_progress()
+        .with_bytes(None, |line| line.len() as u64)
+        .next()
+        .unwrap();
+    assert_eq!(state.message("{bytes} of {byte_total}"), "2 of ?");
+}
+
+#[test]
+fn message() {
+    let mut progressor = (0..100).fake_progress();
+    let start = progressor.started_iterating;
+    progressor.set_fake_now(start + Duration::from_secs(50));
+    let (state, _) = progressor.nth(24).unwrap();
+    assert_eq!(
+        state.message("{percent}% ({num}/{total}) {rate}/s, ETA {eta}, {elapsed} so far"),
+        "25.0% (25/100) 0.5/s, ETA 2m 30s, 50s so far"
+    );
+    // Unknown placeholders, and other braces, are left alone
+    assert_eq!(
+        state.message("{num} {foo} {} {{num}} {num"),
+        "25 {foo} {} {25} {num"
+    );
+    assert_eq!(state.message(""), "");
+    // No bytes counted
+    assert_eq!(state.message("{bytes}/{byte_total} {byte_rate}"), "?/? ?");
+
+    // Unknown values
+    let mut progressor = (0..).fake_progress();
+    let start = progressor.started_iterating;
+    progressor.set_fake_now(start + Duration::from_secs(2));
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(
+        state.message("{percent}% {num}/{total} ETA {eta}"),
+        "?% 1/? ETA ?"
+    );
+}
+
+#[test]
+fn eta_display() {
+    let human_eta = |mode| {
+        let mut progressor = (0..4).fake_progress().with_eta_display(mode);
+        // Started at 10:00:00
+        progressor.started_wall = UNIX_EPOCH + Duration::from_secs(10 * 60 * 60);
+        let start = progressor.started_iterating;
+        progressor.set_fake_now(start + Duration::from_secs(90));
+        let (state, _) = progressor.next().unwrap();
+        state.human_eta().unwrap()
+    };
+    assert_eq!(human_eta(EtaDisplay::Remaining), "4m 30s");
+    assert_eq!(human_eta(EtaDisplay::FinishAt), "10:06:00");
+    assert_eq!(human_eta(EtaDisplay::Both), "4m 30s (10:06:00)");
+    assert_eq!(EtaDisplay::default(), EtaDisplay::Remaining);
+}
+
+#[test]
+fn reset_averages() {
+    let mut progressor = (0..)
+        .fake_optional_progress(1)
+        .with_rolling_average(3)
+        .with_exp_average(0.5);
+    let start = progressor.started_iterating;
+    let mut fake_now = start;
+    // Slow phase, 1 sec per item
+    for _ in 0..5 {
+        fake_now += Duration::from_secs(1);
+        progressor.set_fake_now(fake_now);
+        progressor.next().unwrap();
+    }
+    fake_now += Duration::from_secs(1);
+    progressor.set_fake_now(fake_now);
+    let (state, _) = progressor.next().unwrap();
+    let state = state.unwrap();
+    assert_eq!(state.rolling_average_rate(), Some(1.));
+    assert_eq!(state.exp_average_rate(), Some(1.));
+
+    progressor.reset_averages();
+
+    // Fast phase, 0.1 sec per item
+    fake_now += Duration::from_millis(100);
+    progressor.set_fake_now(fake_now);
+    let (state, _) = progressor.next().unwrap();
+    let state = state.unwrap();
+    // The count & start are kept
+    assert_eq!(state.num_done(), 7);
+    assert_eq!(state.started_iterating(), start);
+    // Not used for the averages, but still the previous record
+    assert_eq!(state.rolling_average_rate(), None);
+    assert_eq!(state.exp_average_rate(), None);
+    assert!(!state.is_first());
+    assert_eq!(
+        state.previous_record_tm(),
+        Some(fake_now - Duration::from_millis(100))
+    );
+    assert_eq!(state.rate_recent(), Some(10.));
+    assert!(state.stalled(Duration::from_millis(50)));
+
+    fake_now += Duration::from_millis(100);
+    progressor.set_fake_now(fake_now);
+    let (state, _) = progressor.next().unwrap();
+    let state = state.unwrap();
+    assert_eq!(state.num_done(), 8);
+    // Only the fast phase is used
+    assert_eq!(state.rolling_average_rate().map(f64::round), Some(10.));
+    assert_eq!(state.exp_average_rate().map(f64::round), Some(10.));
+}
+
+#[test]
+fn copy_with_progress1() {
+    let data: Vec<u8> = (0..20_000).map(|i| i as u8).collect();
+    let mut output = Vec::new();
+    let mut seen = Vec::new();
+    let copied = copy_with_progress(&data[..], &mut output, Some(20_000), |state| {
+        seen.push((state.num_done(), state.fraction()));
+    })
+    .unwrap();
+
+    assert_eq!(copied, 20_000);
+    assert_eq!(output, data);
+    assert!(seen.len() > 1);
+    assert!(seen.windows(2).all(|w| w[0].0 < w[1].0));
+    assert_eq!(*seen.last().unwrap(), (20_000, Some(1.)));
+}
+
+#[test]
+fn display_damping() {
+    let rates = |damping: Option<f64>| {
+        let mut progressor = (0..).fake_progress().with_display_damping(damping);
+        let mut fake_now = progressor.started_iterating;
+        let mut rates = Vec::new();
+        for i in 0..200 {
+            // alternate fast & slow items
+            fake_now += Duration::from_millis(if i % 2 == 0 { 10 } else { 190 });
+            progressor.set_fake_now(fake_now);
+            let (state, _) = progressor.next().unwrap();
+            rates.push(state.rate());
+        }
+        rates
+    };
+    let jumpiness = |rates: &[f64]| {
+        // Once it has settled down
+        rates[100..]
+            .windows(2)
+            .map(|w| (w[1] - w[0]).abs())
+            .sum::<f64>()
+    };
+
+    let raw = rates(None);
+    let damped = rates(Some(0.2));
+    assert!(jumpiness(&damped) < jumpiness(&raw) / 2.);
+    // Still about the same overall rate, 10 per sec
+    assert_eq!(raw.last().unwrap().round(), 10.);
+    assert_eq!(damped.last().unwrap().round(), 10.);
+}
+
+#[test]
+fn fields() {
+    let mut progressor = (0..10).fake_optional_progress(1).with_exp_average(0.5);
+    let start = progressor.started_iterating;
+    progressor.set_fake_now(start + Duration::from_secs(1));
+    progressor.next().unwrap();
+    progressor.set_fake_now(start + Duration::from_secs(3));
+    let (state, _) = progressor.next().unwrap();
+    let state = state.unwrap();
+
+    let fields = state.fields();
+    assert_eq!(fields.num_done, state.num_done_u64());
+    assert_eq!(fields.total, Some(10));
+    assert_eq!(fields.fraction, state.fraction());
+    assert_eq!(fields.percent, state.percent());
+    assert_eq!(fields.rate, state.rate());
+    assert_eq!(fields.smoothed_rate, state.exp_average_rate());
+    assert_eq!(fields.smoothed_rate, Some(0.5));
+    assert_eq!(fields.eta, state.eta());
+    assert_eq!(fields.elapsed, state.duration_since_start());
+}
+
+#[test]
+fn count_multiplier() {
+    let mut progressor = (0..5).fake_progress().with_count_multiplier(1_000);
+    let start = progressor.started_iterating;
+    let mut last = None;
+    for i in 1..=5 {
+        progressor.set_fake_now(start + Duration::from_secs(i));
+        let (state, _) = progressor.next().unwrap();
+        // Still every item, not every unit
+        assert!(state.should_do_every_n_items(1));
+        last = Some(state);
+    }
+    let state = last.unwrap();
+    assert_eq!(state.num_done(), 5_000);
+    assert_eq!(state.fraction(), Some(1.));
+    assert_eq!(state.rate(), 1_000.);
+
+    // assume_size is in units
+    let mut progressor = (0..)
+        .fake_progress()
+        .with_count_multiplier(1_000)
+        .assume_size(10_000);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.fraction(), Some(0.1));
+}
+
+#[test]
+fn timestamps() {
+    let schedule = [100, 250, 300, 1_000, 1_010];
+
+    let mut progressor = (0..5).fake_optional_progress(2).with_timestamps();
+    let start = progressor.started_iterating;
+    for ms in &schedule {
+        progressor.set_fake_now(start + Duration::from_millis(*ms));
+        progressor.next().unwrap();
+    }
+    let expected: Vec<_> = schedule
+        .iter()
+        .map(|ms| Duration::from_millis(*ms))
+        .collect();
+    assert_eq!(progressor.timestamps(), &expected[..]);
+
+    // Only keep the last 2
+    let mut progressor = (0..5).fake_progress().with_timestamps_cap(2);
+    let start = progressor.started_iterating;
+    for ms in &schedule {
+        progressor.set_fake_now(start + Duration::from_millis(*ms));
+        let (state, _) = progressor.next().unwrap();
+        // The record uses the same time
+        assert_eq!(state.duration_since_start(), Duration::from_millis(*ms));
+    }
+    assert_eq!(progressor.timestamps(), &expected[3..]);
+
+    // Not enabled
+    let mut progressor = (0..5).fake_progress();
+    progressor.next().unwrap();
+    assert!(progressor.timestamps().is_empty());
+}
+
+#[test]
+fn cost_model() {
+    // Item i takes (i + 1) * 10ms
+    let mut progressor = (0..100).fake_progress().with_cost_model(|i| (i + 1) as f64);
+    let start = progressor.started_iterating;
+    let mut elapsed = Duration::ZERO;
+    let mut last = None;
+    for i in 0..50 {
+        elapsed += Duration::from_millis(10 * (i + 1));
+        progressor.set_fake_now(start + elapsed);
+        last = Some(progressor.next().unwrap().0);
+    }
+    let state = last.unwrap();
+
+    let actual = Duration::from_millis((50..100).map(|i| 10 * (i + 1)).sum());
+    let eta = state.eta().unwrap();
+    assert!((eta.as_secs_f64() - actual.as_secs_f64()).abs() < 0.001);
+
+    // Naively assuming the same time per item would be wildly out
+    let naive = state
+        .duration_since_start()
+        .div_f64(state.fraction().unwrap())
+        - state.duration_since_start();
+    assert!(naive < actual / 2);
+}
+
+#[test]
+fn cost_model_growing_total() {
+    // The total grows by one every item, which shouldn't mean costing every item every time
+    let calls = Arc::new(AtomicU64::new(0));
+    let counter = calls.clone();
+    let mut progressor = (0..).fake_progress().with_cost_model(move |_| {
+        counter.fetch_add(1, Ordering::Relaxed);
+        1.
+    });
+    let start = progressor.started_iterating;
+    for i in 1..=100 {
+        progressor.add_discovered(if i == 1 { 2 } else { 1 });
+        progressor.set_fake_now(start + Duration::from_secs(i));
+        let (state, _) = progressor.next().unwrap();
+        assert_eq!(state.eta(), Some(Duration::from_secs(1)));
+    }
+    // 100 done, and 101 in the total
+    assert_eq!(calls.load(Ordering::Relaxed), 201);
+
+    // A smaller total only takes off the items which aren't in it now
+    progressor.0.assumed_size = Some(100);
+    progressor.set_fake_now(start + Duration::from_secs(101));
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.eta(), Some(Duration::ZERO));
+    assert_eq!(calls.load(Ordering::Relaxed), 203);
+}
+
+#[test]
+fn iterators_are_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+    assert_send_sync(&(0..10).progress());
+    assert_send_sync(&(0..10).optional_progress(5));
+}
+
+#[cfg(feature = "terminal")]
+#[test]
+fn draw_bar_line() {
+    let mut progressor = (0..4).fake_progress();
+    let (state, _) = progressor.next().unwrap();
+    let start = state.started_iterating();
+    progressor.set_fake_now(start + Duration::from_secs(2));
+    let (state, _) = progressor.next().unwrap();
+
+    let mut output = Vec::new();
+    state.draw_bar_line(&mut output, 40).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "\x1b7\x1b[999B\r\x1b[2K[#########---------]  50.0% 1.0/s ETA 2s\x1b8"
+    );
+
+    // Unknown fraction, nothing to draw
+    let (state, _) = (0..).fake_progress().next().unwrap();
+    let mut output = Vec::new();
+    state.draw_bar_line(&mut output, 40).unwrap();
+    assert!(output.is_empty());
+}
+
+#[test]
+fn idle_threshold() {
+    let mut progressor = (0..20)
+        .fake_progress()
+        .with_idle_threshold(Duration::from_secs(10));
+    let mut fake_now = progressor.started_iterating;
+    let mut last = None;
+    for i in 0..10 {
+        // 1 item per sec, with a minute's pause every 3 items
+        fake_now += Duration::from_secs(if i % 3 == 2 { 60 } else { 1 });
+        progressor.set_fake_now(fake_now);
+        last = Some(progressor.next().unwrap().0);
+    }
+    let state = last.unwrap();
+    assert_eq!(state.idle_time(), Duration::from_secs(3 * 60));
+    assert_eq!(
+        state.duration_since_start(),
+        Duration::from_secs(3 * 60 + 7)
+    );
+    assert_eq!(state.rate(), 10. / 7.);
+    // Half way through, in 7 active secs
+    assert_eq!(state.eta(), Some(Duration::from_secs(7)));
+}
+
+#[test]
+fn to_json_line() {
+    let mut progressor = (0..10).fake_progress();
+    let fake_now = progressor.started_iterating + Duration::from_secs(2);
+    progressor.set_fake_now(fake_now);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(
+        state.to_json_line(),
+        r#"{"done":1,"total":10,"pct":10,"rate":0.5,"eta_secs":18,"elapsed_secs":2}"#
+    );
+
+    let mut progressor = (0..).filter(|x| x % 2 == 0).fake_progress();
+    let fake_now = progressor.started_iterating + Duration::from_millis(500);
+    progressor.set_fake_now(fake_now);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(
+        state.to_json_line(),
+        r#"{"done":1,"total":null,"pct":null,"rate":2,"eta_secs":null,"elapsed_secs":0.5}"#
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serialize_record() {
+    let mut progressor = (0..10).fake_progress();
+    let fake_now = progressor.started_iterating + Duration::from_secs(2);
+    progressor.set_fake_now(fake_now);
+    let (state, _) = progressor.next().unwrap();
+    let json = serde_json::to_string(&state).unwrap();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["num"], 1);
+    assert_eq!(value["total"], 10);
+    assert_eq!(value["fraction"], 0.1);
+    assert_eq!(value["rate"], 0.5);
+    assert_eq!(value["eta_secs"], 18.);
+    assert_eq!(value["elapsed_secs"], 2.);
+
+    let (state, _) = (0..).filter(|x| x % 2 == 0).fake_progress().next().unwrap();
+    let value = serde_json::to_value(state).unwrap();
+    assert!(value["fraction"].is_null());
+    assert!(value["eta_secs"].is_null());
+}
+
+#[test]
+fn smoothing_bootstrap() {
+    let mut progressor = (0..20)
+        .fake_progress()
+        .with_rolling_average(4)
+        .with_smoothing_bootstrap();
+    let mut fake_now = progressor.started_iterating;
+    let mut rates = Vec::new();
+    for i in 0..8 {
+        // 1 sec for the first item, then 2 sec for each one after
+        fake_now += Duration::from_secs(if i == 0 { 1 } else { 2 });
+        progressor.set_fake_now(fake_now);
+        let (state, _) = progressor.next().unwrap();
+        assert!(state.smoothed_rate().is_some());
+        rates.push(state.smoothed_rate().unwrap());
+    }
+    // Nothing to average yet
+    assert_eq!(rates[0], 1.);
+    // 1 of 4 samples: ¼ of the 0.5/s average, ¾ of the 2 items in 3 sec
+    assert_eq!(rates[1], 0.5 * 0.25 + (2. / 3.) * 0.75);
+    // Once the window is full, it's only the average
+    assert_eq!(rates[4], 0.5);
+    assert_eq!(rates[7], 0.5);
+
+    // Without it, nothing at the start
+    let mut progressor = (0..20).fake_progress().with_rolling_average(4);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.smoothed_rate(), None);
+}
+
+#[test]
+fn weighted_rolling_average() {
+    let mut progressor = (0..20)
+        .fake_progress()
+        .with_rolling_average(10)
+        .with_weighted_rolling_average(10)
+        .with_exp_average(0.5);
+    let mut fake_now = progressor.started_iterating;
+    // 1 sec per item, then it speeds up to 0.1 sec per item
+    let durations = std::iter::repeat_n(1_000, 11).chain(std::iter::repeat_n(100, 3));
+    let mut state = None;
+    for millis in durations {
+        fake_now += Duration::from_millis(millis);
+        progressor.set_fake_now(fake_now);
+        state = Some(progressor.next().unwrap().0);
+    }
+    let state = state.unwrap();
+
+    // (7×1 + 3×0.1) / 10
+    assert!((state.rolling_average_duration().unwrap().as_secs_f64() - 0.73).abs() < 1e-9);
+    // (1+…+7)×1 + (8+9+10)×0.1 / (1+…+10)
+    assert!(
+        (state
+            .weighted_rolling_average_duration()
+            .unwrap()
+            .as_secs_f64()
+            - 30.7 / 55.)
+            .abs()
+            < 1e-9
+    );
+    // Quicker to react than the flat average, but slower than the exponential
+    let weighted = state.weighted_rolling_rate().unwrap();
+    assert!(weighted > state.rolling_average_rate().unwrap());
+    assert!(weighted < state.exp_average_rate().unwrap());
+
+    // Nothing for the first item, or if not enabled
+    let (state, _) = (0..20)
+        .fake_progress()
+        .with_weighted_rolling_average(10)
+        .next()
+        .unwrap();
+    assert_eq!(state.weighted_rolling_rate(), None);
+    let mut progressor = (0..20).fake_progress();
+    progressor.next();
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.weighted_rolling_rate(), None);
+}
+
+#[test]
+fn record_clone() {
+    let mut progressor = (0..10).progress();
+    let mut records = vec![];
+    for _ in 0..3 {
+        let (state, _) = progressor.next().unwrap();
+        records.push(state.clone());
+        // Still usable, since it's `Copy`
+        let copied = state;
+        assert_eq!(copied.num_done(), state.num_done());
+    }
+    assert_eq!(
+        records.iter().map(|r| r.num_done()).collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+}
+
+#[test]
+fn timestamp() {
+    let mut progressor = (0..10).fake_progress();
+    let start = progressor.started_iterating;
+    progressor.set_fake_now(start + Duration::from_secs(3));
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.timestamp(), start + Duration::from_secs(3));
+    progressor.set_fake_now(start + Duration::from_millis(3_250));
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(
+        state.timestamp() - state.previous_record_tm().unwrap(),
+        Duration::from_millis(250)
+    );
+}
+
+#[test]
+fn do_every_mut() {
+    let mut progressor = (0..20).fake_progress();
+    let start = progressor.started_iterating;
+    let mut by_time = vec![];
+    let mut by_items = vec![];
+    for i in 1..=20 {
+        // 1 item every 0.5 sec
+        progressor.set_fake_now(start + Duration::from_millis(500 * i));
+        let (state, _) = progressor.next().unwrap();
+        state.do_every_n_sec_mut(2., |state| by_time.push(state.num_done()));
+        state.do_every_n_items_mut(3, |state| by_items.push(state.num_done()));
+    }
+    assert_eq!(by_time, vec![4, 8, 12, 16, 20]);
+    assert_eq!(by_items, vec![1, 4, 7, 10, 13, 16, 19]);
+}
+
+#[test]
+fn num_per_sec_since_previous() {
+    let mut progressor = (0..10).fake_progress();
+    let start = progressor.started_iterating;
+    progressor.set_fake_now(start + Duration::from_secs(1));
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.num_per_sec_since_previous(), None);
+    progressor.set_fake_now(start + Duration::from_millis(1_250));
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(
+        state.timestamp() - state.previous_record_tm().unwrap(),
+        Duration::from_millis(250)
+    );
+    assert_eq!(state.num_per_sec_since_previous(), Some(4.));
+    assert_eq!(state.rate(), 1.6);
+
+    // No time passed
+    progressor.set_fake_now(start + Duration::from_millis(1_250));
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.num_per_sec_since_previous(), Some(f64::INFINITY));
+    assert_eq!(state.rate_recent(), None);
+}
+
+#[test]
+fn rate_recent() {
+    let mut progressor = (0..10).fake_progress();
+    let start = progressor.started_iterating;
+    progressor.set_fake_now(start + Duration::from_secs(10));
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.rate_recent(), None);
+    progressor.set_fake_now(start + Duration::from_millis(10_500));
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.rate_recent(), Some(2.));
+    // The overall rate is much lower
+    assert!(state.rate() < 0.2);
+    progressor.set_fake_now(start + Duration::from_millis(10_750));
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.rate_recent(), Some(4.));
+
+    // 5 items between records
+    let mut progressor = (0..20).fake_optional_progress(5);
+    let start = progressor.started_iterating;
+    let mut rates = vec![];
+    for i in 1..=10 {
+        progressor.set_fake_now(start + Duration::from_millis(100 * i));
+        if let (Some(state), _) = progressor.next().unwrap() {
+            rates.push(state.rate_recent());
+        }
+    }
+    assert_eq!(rates, vec![None, Some(10.)]);
+
+    // 1 item per sec, with a record every 5 sec
+    let mut progressor = (0..20)
+        .fake_optional_progress(1)
+        .with_min_update_interval(Duration::from_secs(5));
+    let start = progressor.started_iterating;
+    let mut rates = vec![];
+    for secs in 1..=12 {
+        progressor.set_fake_now(start + Duration::from_secs(secs));
+        if let (Some(state), _) = progressor.next().unwrap() {
+            rates.push(state.rate_recent());
+        }
+    }
+    assert_eq!(rates, vec![None, Some(1.)]);
+
+    // The items skipped by `.nth(...)` are counted
+    let mut progressor = (0..100).fake_progress();
+    let start = progressor.started_iterating;
+    progressor.set_fake_now(start + Duration::from_secs(1));
+    progressor.next().unwrap();
+    progressor.set_fake_now(start + Duration::from_secs(3));
+    let (state, _) = progressor.nth(9).unwrap();
+    assert_eq!(state.num_done(), 11);
+    assert_eq!(state.rate_recent(), Some(5.));
+}
+
+#[test]
+fn items_in() {
+    let mut progressor = (0..1_000).fake_progress().with_rolling_average(2);
+    let start = progressor.started_iterating;
+    // 10 items in 5 sec, 2 per sec
+    progressor.set_fake_now(start + Duration::from_secs(5));
+    progressor.nth(9).unwrap();
+    // Then 2 items in 0.5 sec, 4 per sec
+    progressor.set_fake_now(start + Duration::from_millis(5_250));
+    progressor.next().unwrap();
+    progressor.set_fake_now(start + Duration::from_millis(5_500));
+    let (state, _) = progressor.next().unwrap();
+
+    // 12 items in 5.5 sec
+    let in_5_min = state.items_in(Duration::from_secs(300));
+    assert!((in_5_min - 12. / 5.5 * 300.).abs() < 1e-9);
+    assert_eq!(state.items_in(Duration::ZERO), 0.);
+    assert_eq!(
+        state.items_in_smoothed(Duration::from_secs(300)),
+        Some(1_200.)
+    );
+
+    let (state, _) = (0..10).fake_progress().next().unwrap();
+    assert_eq!(state.items_in_smoothed(Duration::from_secs(300)), None);
+}
+
+#[test]
+fn rate_ci() {
+    let mut progressor = (0..1_000).fake_progress();
+    let start = progressor.started_iterating;
+    progressor.set_fake_now(start + Duration::from_secs(10));
+    // 100 items in 10 sec, so 10 ± 2 × 10 / 10
+    let (state, _) = progressor.nth(99).unwrap();
+    assert_eq!(state.rate_ci(2.), Some((8., 12.)));
+    assert_eq!(state.rate_ci(0.), Some((10., 10.)));
+
+    // Lower bound clamped at 0: 4 items in 2 sec, so 2 ± 3 × 2 / 2
+    let mut progressor = (0..1_000).fake_progress();
+    let start = progressor.started_iterating;
+    progressor.set_fake_now(start + Duration::from_secs(2));
+    let (state, _) = progressor.nth(3).unwrap();
+    assert_eq!(state.rate_ci(3.), Some((0., 5.)));
+
+    // No time has passed
+    let mut progressor = (0..1_000).fake_progress();
+    let start = progressor.started_iterating;
+    progressor.set_fake_now(start);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.rate_ci(2.), None);
+}
+
+#[test]
+fn throughput_trend() {
+    let trend = |durations: &[u64]| {
+        let mut progressor = (0..100).fake_progress().with_rolling_average(3);
+        let mut fake_now = progressor.started_iterating;
+        let mut state = None;
+        for &millis in durations {
+            fake_now += Duration::from_millis(millis);
+            progressor.set_fake_now(fake_now);
+            state = Some(progressor.next().unwrap().0);
+        }
+        state.unwrap().throughput_trend(0.1)
+    };
+    // Speeding up, 5/s recently, but ~1.4/s overall
+    assert_eq!(
+        trend(&[1_000, 1_000, 1_000, 1_000, 1_000, 200, 200, 200]),
+        Some(Trend::Increasing)
+    );
+    // Slowing down, 0.5/s recently, but ~1.1/s overall
+    assert_eq!(
+        trend(&[200, 200, 200, 200, 200, 2_000, 2_000, 2_000]),
+        Some(Trend::Decreasing)
+    );
+    assert_eq!(trend(&[1_000; 8]), Some(Trend::Steady));
+    // Within the tolerance
+    assert_eq!(
+        trend(&[1_000, 1_000, 1_000, 1_000, 1_000, 950, 950, 950]),
+        Some(Trend::Steady)
+    );
+
+    // No recent rate
+    let (state, _) = (0..100).fake_progress().next().unwrap();
+    assert_eq!(state.throughput_trend(0.1), None);
+}
+
+#[test]
+fn windowed_rate() {
+    let mut progressor = (0..100)
+        .fake_progress()
+        .with_windowed_rate(Duration::from_secs(2));
+    let mut fake_now = progressor.started_iterating;
+    let mut states = vec![];
+    // 1 per sec for 10 sec, then 4 per sec
+    let durations = std::iter::repeat_n(1_000, 10).chain(std::iter::repeat_n(250, 12));
+    for millis in durations {
+        fake_now += Duration::from_millis(millis);
+        progressor.set_fake_now(fake_now);
+        states.push(progressor.next().unwrap().0);
+    }
+    let rates: Vec<_> = states.iter().map(|s| s.windowed_rate()).collect();
+    assert_eq!(rates[0], None);
+    assert_eq!(rates[9], Some(1.));
+    // Part way through the change, 3 items in the 1.5 sec since the oldest in the window
+    assert_eq!(rates[11], Some(2.));
+    // Only the recent segment
+    assert_eq!(rates[21], Some(4.));
+    assert!(states[21].rate() < 2.);
+    // Old samples are forgotten
+    assert!(progressor.windowed_rate.as_ref().unwrap().1.len() <= 10);
+
+    let (state, _) = (0..100).fake_progress().next().unwrap();
+    assert_eq!(state.windowed_rate(), None);
+}
+
+#[test]
+fn progress_handle() {
+    let progressor = (0..1_000).progress().with_handle();
+    let handle = progressor.handle().unwrap();
+    assert_eq!(handle.snapshot().num_done, 0);
+    assert_eq!(handle.snapshot().total, Some(1_000));
+
+    let (polled, done) = mpsc::channel();
+    let poller = thread::spawn(move || {
+        let mut last = 0;
+        // Keep polling until the worker is done
+        while done.try_recv().is_err() {
+            let snapshot = handle.snapshot();
+            assert!(snapshot.num_done >= last);
+            // The count & total are from the same item
+            assert!(snapshot.fraction().unwrap() <= 1.);
+            last = snapshot.num_done;
+        }
+        handle.snapshot()
+    });
+    let worker = thread::spawn(move || {
+        for _ in progressor {}
+        polled.send(()).unwrap();
+    });
+    worker.join().unwrap();
+    let snapshot = poller.join().unwrap();
+    assert_eq!(snapshot.num_done, 1_000);
+    assert_eq!(snapshot.total, Some(1_000));
+    assert_eq!(snapshot.fraction(), Some(1.));
+
+    let progressor = (0..).progress();
+    assert!(progressor.handle().is_none());
+}
+
+#[test]
+fn fraction_map() {
+    // A quick setup phase, the first half of the items, shown as 0–20%, then the main phase as
+    // 20–100%
+    let two_phase = |f: f64| {
+        if f < 0.5 {
+            f * 0.4
+        } else {
+            0.2 + (f - 0.5) * 1.6
+        }
+    };
+    let mut progressor = (0..10).progress().with_fraction_map(two_phase);
+    let fractions: Vec<_> = progressor
+        .by_ref()
+        .map(|(state, _)| state.fraction().unwrap())
+        .collect();
+    assert!((fractions[0] - 0.04).abs() < 1e-9);
+    assert!((fractions[4] - 0.2).abs() < 1e-9);
+    assert!((fractions[6] - 0.52).abs() < 1e-9);
+    assert_eq!(fractions[9], 1.);
+
+    // Clamped
+    let mut progressor = (0..10).progress().with_fraction_map(|f| f * 2. - 0.5);
+    let fractions: Vec<_> = progressor
+        .by_ref()
+        .map(|(state, _)| state.fraction().unwrap())
+        .collect();
+    assert_eq!(fractions[0], 0.);
+    assert_eq!(fractions[9], 1.);
+
+    // Nothing to map if the total isn't known
+    let mut progressor = (0..).progress().with_fraction_map(|_| 0.5);
+    assert_eq!(progressor.next().unwrap().0.fraction(), None);
+}
+
+#[test]
+fn error_counting() {
+    let results: Vec<Result<u32, String>> = (0..100)
+        .map(|i| {
+            if i % 8 == 3 {
+                Err(format!("bad {}", i))
+            } else {
+                Ok(i)
+            }
+        })
+        .collect();
+    let mut progressor = results.into_iter().progress().with_error_counting();
+
+    let (state, item) = progressor.next().unwrap();
+    assert_eq!(item, Ok(0));
+    assert_eq!(state.num_errors(), 0);
+    assert_eq!(state.error_rate(), 0.);
+
+    let states: Vec<_> = progressor.by_ref().map(|(state, _)| state).collect();
+    // 4th item
+    assert_eq!(states[2].num_done(), 4);
+    assert_eq!(states[2].num_errors(), 1);
+    assert_eq!(states[2].error_rate(), 0.25);
+
+    let last = states.last().unwrap();
+    assert_eq!(last.num_done(), 100);
+    assert_eq!(last.num_errors(), 13);
+    assert_eq!(last.error_rate(), 0.13);
+    assert_eq!(last.fraction(), Some(1.));
+}
+
+#[test]
+fn next_item_eta() {
+    let mut progressor = (0..20).fake_progress().with_exp_average(0.5);
+    assert_eq!(progressor.next_item_eta(), None);
+    let mut fake_now = progressor.started_iterating;
+    for _ in 0..5 {
+        fake_now += Duration::from_millis(250);
+        progressor.set_fake_now(fake_now);
+        progressor.next().unwrap();
+    }
+    // 4 per sec
+    assert_eq!(progressor.next_item_eta(), Some(Duration::from_millis(250)));
+
+    fake_now += Duration::from_millis(250);
+    progressor.set_fake_now(fake_now);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(
+        progressor.next_item_eta(),
+        Some(Duration::from_secs_f64(1. / state.smoothed_rate().unwrap()))
+    );
+
+    // No smoothing, no ETA
+    let mut progressor = (0..20).fake_progress();
+    progressor.next();
+    progressor.next();
+    assert_eq!(progressor.next_item_eta(), None);
+}
+
+#[test]
+fn completion_log() {
+    let path = std::env::temp_dir().join(format!(
+        "iter-progress-completion-log-{}.log",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+
+    for _ in 0..2 {
+        let mut progressor = (0..100).fake_progress().with_completion_log(&path);
+        let fake_now = progressor.started_iterating + Duration::from_secs(2);
+        for _ in 0..100 {
+            progressor.next().unwrap();
+        }
+        progressor.set_fake_now(fake_now);
+        assert!(progressor.next().is_none());
+        // Only written once
+        assert!(progressor.next().is_none());
+    }
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let lines: Vec<_> = log.lines().collect();
+    assert_eq!(lines.len(), 2);
+    let fields: Vec<(&str, &str)> = lines[1]
+        .split(' ')
+        .map(|field| {
+            let mut parts = field.splitn(2, '=');
+            (parts.next().unwrap(), parts.next().unwrap())
+        })
+        .collect();
+    assert_eq!(fields[0].0, "finished_at");
+    assert!(fields[0].1.parse::<u64>().unwrap() > 1_600_000_000);
+    assert_eq!(
+        &fields[1..],
+        &[
+            ("items", "100"),
+            ("elapsed_secs", "2.000"),
+            ("rate", "50.000")
+        ]
+    );
+
+    // Can't be written, but iteration still works
+    let path = std::env::temp_dir()
+        .join("iter-progress-no-such-dir")
+        .join("completion.log");
+    let output = SharedBuffer::default();
+    let mut num = 0;
+    let progressor = (0..10)
+        .fake_progress()
+        .with_completion_log(&path)
+        .with_output(Output::Writer(Box::new(output.clone())));
+    for _ in progressor {
+        num += 1;
+    }
+    assert_eq!(num, 10);
+    // The error goes to the output
+    assert!(output.contents().starts_with(&format!(
+        "Unable to write completion log to {}: ",
+        path.display()
+    )));
+}
+
+#[test]
+fn progress_exact() {
+    let items: Vec<u32> = (0..8).collect();
+    let fractions: Vec<_> = items
+        .into_iter()
+        .progress_exact()
+        .map(|(state, _)| state.fraction())
+        .collect();
+    assert_eq!(
+        fractions,
+        [0.125, 0.25, 0.375, 0.5, 0.625, 0.75, 0.875, 1.]
+            .iter()
+            .map(|&f| Some(f))
+            .collect::<Vec<_>>()
+    );
+
+    let empty: Vec<u32> = Vec::new();
+    assert!(empty.into_iter().progress_exact().next().is_none());
+}
+
+#[test]
+fn progress_with_config() {
+    let config = ProgressConfig {
+        rolling_average: Some(2),
+        exp_average: Some(0.5),
+        assumed_size: Some(10),
+        min_update_interval: Some(Duration::from_secs(5)),
+    };
+    let progressor = (0..).progress_with_config(config);
+    assert_eq!(progressor.rolling_average, Some((2, VecDeque::new())));
+    assert_eq!(progressor.exp_average, Some((0.5, None)));
+    assert_eq!(progressor.assumed_size, Some(10));
+    assert_eq!(progressor.min_update_interval, Some(Duration::from_secs(5)));
+    let mut progressor = (0..)
+        .fake_optional_progress(1)
+        .with_rolling_average(config.rolling_average)
+        .with_exp_average(config.exp_average)
+        .assume_size(config.assumed_size)
+        .with_min_update_interval(config.min_update_interval);
+    assert_eq!(progressor.rolling_average, Some((2, VecDeque::new())));
+    assert_eq!(progressor.exp_average, Some((0.5, None)));
+    assert_eq!(progressor.assumed_size, Some(10));
+    assert_eq!(progressor.min_update_interval, Some(Duration::from_secs(5)));
+    let start = progressor.started_iterating;
+    let mut generated = vec![];
+    for secs in 1..=10 {
+        progressor.set_fake_now(start + Duration::from_secs(secs));
+        if let (Some(state), _) = progressor.next().unwrap() {
+            generated.push((state.num_done(), state.fraction()));
+        }
+    }
+    assert_eq!(generated, vec![(5, Some(0.5)), (10, Some(1.))]);
+
+    // The same config for another iterator
+    let other = "abc".chars().progress_with_config(config);
+    assert_eq!(other.assumed_size, Some(10));
+
+    // Same as `.fake_progress()`
+    let mut progressor = (0..).progress_with_config(ProgressConfig::default());
+    let plain = (0..).fake_progress();
+    assert_eq!(progressor.rolling_average, plain.rolling_average);
+    assert_eq!(progressor.exp_average, plain.exp_average);
+    assert_eq!(progressor.assumed_size, plain.assumed_size);
+    assert_eq!(progressor.min_update_interval, plain.min_update_interval);
+    assert!((0..3).all(|_| progressor.next().unwrap().0.is_some()));
+}
+
+#[test]
+fn progress_with_size() {
+    let mut progressor = (0..).step_by(3).progress_with_size(10);
+    let (state, value) = progressor.next().unwrap();
+    assert_eq!(value, 0);
+    assert_eq!(state.fraction(), Some(0.1));
+    assert_eq!(state.total(), Some(10));
+    let (state, value) = progressor.nth(3).unwrap();
+    assert_eq!(value, 12);
+    assert_eq!(state.percent(), Some(50.));
+}
+
+#[test]
+fn throttle() {
+    let mut progressor = (0..).fake_progress();
+    let mut every_sec = Throttle::every(Duration::from_secs(1));
+    let mut every_3_sec = Throttle::every(Duration::from_secs(3));
+    let mut fake_now = progressor.started_iterating;
+    let mut fired = Vec::new();
+    for i in 1..=20 {
+        // 2 items per sec
+        fake_now += Duration::from_millis(500);
+        progressor.set_fake_now(fake_now);
+        let (state, _) = progressor.next().unwrap();
+        if every_sec.should_fire(&state) {
+            fired.push(("sec", i));
+        }
+        if every_3_sec.should_fire(&state) {
+            fired.push(("3 sec", i));
+        }
+    }
+    assert_eq!(
+        fired,
+        [
+            ("sec", 2),
+            ("sec", 4),
+            ("sec", 6),
+            ("3 sec", 6),
+            ("sec", 8),
+            ("sec", 10),
+            ("sec", 12),
+            ("3 sec", 12),
+            ("sec", 14),
+            ("sec", 16),
+            ("sec", 18),
+            ("3 sec", 18),
+            ("sec", 20),
+        ]
+    );
+}
+
+#[test]
+fn progress_windows() {
+    let mut progressor = (0..10).progress_windows(4);
+    assert_eq!(progressor.size_hint(), (7, Some(7)));
+    let windows: Vec<_> = progressor.by_ref().collect();
+    assert_eq!(windows.len(), 7);
+    assert_eq!(windows[0].1, vec![0, 1, 2, 3]);
+    assert_eq!(windows[0].0.num_done(), 1);
+    assert_eq!(windows[0].0.fraction(), Some(1. / 7.));
+    assert_eq!(windows[6].1, vec![6, 7, 8, 9]);
+    assert_eq!(windows[6].0.num_done(), 7);
+    assert_eq!(windows[6].0.fraction(), Some(1.));
+
+    // Exactly one window
+    assert_eq!((0..3).progress_windows(3).count(), 1);
+
+    // Too few items for any windows
+    let mut progressor = (0..3).progress_windows(4);
+    assert_eq!(progressor.size_hint(), (0, Some(0)));
+    assert!(progressor.next().is_none());
+}
+
+/// For the tests which use the process-wide `INTERRUPTED` flag
+static INTERRUPTED_LOCK: Mutex<()> = Mutex::new(());
+
+#[test]
+fn interrupted_summary() {
+    let _lock = INTERRUPTED_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut progressor = (0..20).fake_progress();
+    // What `.with_interrupt_summary()` sets, without catching SIGINT for the test process
+    progressor.0.interrupt_summary = true;
+    let fake_now = progressor.started_iterating + Duration::from_secs(2);
+    for _ in 0..5 {
+        progressor.next().unwrap();
+    }
+    progressor.set_fake_now(fake_now);
+    assert_eq!(progressor.0.interrupted_summary(), None);
+
+    // What the signal handler does
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    assert_eq!(
+        progressor.0.interrupted_summary(),
+        Some("Interrupted after 5 items in 2.0 sec (2.5 per sec), 25.0% done".to_string())
+    );
+    // The flag is reset
+    assert_eq!(progressor.0.interrupted_summary(), None);
+}
+
+#[cfg(all(feature = "interrupt", unix))]
+#[test]
+fn interrupt_handler_restored() {
+    let _lock = INTERRUPTED_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let handler = on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // The handler now, put back straight away
+    let current = || unsafe {
+        let current = libc::signal(libc::SIGINT, libc::SIG_IGN);
+        libc::signal(libc::SIGINT, current);
+        current
+    };
+    let original = unsafe { libc::signal(libc::SIGINT, libc::SIG_IGN) };
+
+    let progressor = (0..10).progress().with_interrupt_summary();
+    assert_eq!(current(), handler);
+    drop(progressor);
+    assert_eq!(current(), libc::SIG_IGN);
+
+    // Put back when finished, and only once all are done
+    let mut progressor = (0..2).progress().with_interrupt_summary();
+    let other = (0..2).progress().with_interrupt_summary();
+    while progressor.next().is_some() {}
+    assert_eq!(current(), handler);
+    drop(other);
+    assert_eq!(current(), libc::SIG_IGN);
+    drop(progressor);
+    assert_eq!(current(), libc::SIG_IGN);
+
+    // After the summary, the SIGINT is passed on to the handler from before (here, ignored),
+    // and then caught again
+    let output = SharedBuffer::default();
+    let mut progressor = (0..10)
+        .progress()
+        .with_output(Output::Writer(Box::new(output.clone())))
+        .with_interrupt_summary();
+    progressor.next().unwrap();
+    unsafe {
+        libc::raise(libc::SIGINT);
+    }
+    progressor.next().unwrap();
+    assert!(output.contents().starts_with("Interrupted after 2 items"));
+    assert_eq!(current(), handler);
+    drop(progressor);
+
+    unsafe {
+        libc::signal(libc::SIGINT, original);
+    }
+}
+
+#[test]
+fn progress_shared() {
+    let mut progressor = (0..10).fake_progress().shared();
+    let fake_now = progressor.started_iterating + Duration::from_secs(1);
+    progressor.set_fake_now(fake_now);
+    let (state, item) = progressor.next().unwrap();
+    assert_eq!(item, 0);
+
+    let (sender, receiver) = mpsc::channel();
+    sender.send(Arc::clone(&state)).unwrap();
+    let for_ui = Arc::clone(&state);
+    let sent = receiver.recv().unwrap();
+    assert!(Arc::ptr_eq(&sent, &for_ui));
+    assert_eq!(Arc::strong_count(&state), 3);
+    assert_eq!(sent.num_done(), 1);
+    assert_eq!(for_ui.num_done(), 1);
+    assert_eq!(sent.fraction(), for_ui.fraction());
+    assert_eq!(sent.rate(), 1.);
+    assert_eq!(sent.rate(), for_ui.rate());
+
+    let (state, _) = progressor.last().unwrap();
+    assert_eq!(state.num_done(), 10);
+}
+
+#[test]
+fn rate_unit_choose() {
+    use RateUnit::*;
+    let rates = [1.2, 0.9, 1.1, 0.8, 0.6, 0.45, 0.6, 0.9, 1.5, 2.1, 1.5];
+    let mut previous = None;
+    let units: Vec<_> = rates
+        .iter()
+        .map(|&rate| {
+            previous = Some(RateUnit::choose(rate, previous));
+            previous.unwrap()
+        })
+        .collect();
+    assert_eq!(
+        units,
+        [
+            PerSecond, PerSecond, PerSecond, PerSecond, PerSecond, PerMinute, PerMinute, PerMinute,
+            PerMinute, PerSecond, PerSecond
+        ]
+    );
+
+    // Without a previous unit, it switches right at the boundary
+    let units: Vec<_> = rates
+        .iter()
+        .map(|&rate| RateUnit::choose(rate, None))
+        .collect();
+    assert_eq!(
+        units,
+        [
+            PerSecond, PerMinute, PerSecond, PerMinute, PerMinute, PerMinute, PerMinute, PerMinute,
+            PerSecond, PerSecond, PerSecond
+        ]
+    );
+
+    assert_eq!(RateUnit::choose(1. / 3600., None), PerHour);
+    assert_eq!(RateUnit::choose(1. / 60., Some(PerHour)), PerHour);
+    assert_eq!(RateUnit::choose(2. / 60., Some(PerHour)), PerMinute);
+}
+
+#[test]
+fn auto_rate_unit() {
+    let mut progressor = (0..).fake_progress().with_auto_rate_unit();
+    let mut fake_now = progressor.started_iterating;
+    let mut units = Vec::new();
+    // Quick at first, 2 per sec
+    for _ in 0..10 {
+        fake_now += Duration::from_millis(500);
+        progressor.set_fake_now(fake_now);
+        units.push(progressor.next().unwrap().0.rate_best_unit().1);
+    }
+    // Then 1 every 10 sec, so the rate since the start slowly falls below 1 per sec
+    for _ in 0..10 {
+        fake_now += Duration::from_secs(10);
+        progressor.set_fake_now(fake_now);
+        units.push(progressor.next().unwrap().0.rate_best_unit().1);
+    }
+    // 0.73/s is kept as per sec, and only at 0.48/s does it change
+    let changes = units.windows(2).filter(|w| w[0] != w[1]).count();
+    assert_eq!(changes, 1);
+    assert_eq!(units[10], RateUnit::PerSecond);
+    assert_eq!(units[11], RateUnit::PerMinute);
+    fake_now += Duration::from_secs(10);
+    progressor.set_fake_now(fake_now);
+    let (state, _) = progressor.next().unwrap();
+    let (rate, unit) = state.rate_best_unit();
+    assert_eq!(unit, RateUnit::PerMinute);
+    assert!((rate - state.rate() * 60.).abs() < 1e-9);
+}
+
+#[test]
+fn do_every_n_sec_independent() {
+    let mut progressor = (0..40).fake_optional_progress(1_000);
+    let mut fake_now = progressor.started_iterating;
+    let every_sec = Cell::new(Vec::new());
+    let every_5_sec = Cell::new(Vec::new());
+    let push = |cell: &Cell<Vec<u64>>, num| {
+        let mut nums = cell.take();
+        nums.push(num);
+        cell.set(nums);
+    };
+    for _ in 0..20 {
+        // 2 items per sec
+        fake_now += Duration::from_millis(500);
+        progressor.set_fake_now(fake_now);
+        assert!(progressor.next().unwrap().0.is_none());
+        // `.next()` uses the fake time, so set it again for the checks
+        progressor.set_fake_now(fake_now);
+        progressor.do_every_n_sec(1., |state| push(&every_sec, state.num_done_u64()));
+        progressor.do_every_n_sec(5., |state| push(&every_5_sec, state.num_done_u64()));
+    }
+    assert_eq!(every_sec.take(), [2, 4, 6, 8, 10, 12, 14, 16, 18, 20]);
+    assert_eq!(every_5_sec.take(), [10, 20]);
+
+    // The same with records
+    let mut progressor = (0..40).fake_progress();
+    let mut fake_now = progressor.started_iterating;
+    let mut fired = Vec::new();
+    for _ in 0..20 {
+        fake_now += Duration::from_millis(500);
+        progressor.set_fake_now(fake_now);
+        let (state, _) = progressor.next().unwrap();
+        if state.should_do_every_n_sec(1.) {
+            fired.push((1, state.num_done()));
+        }
+        if state.should_do_every_n_sec(5.) {
+            fired.push((5, state.num_done()));
+        }
+    }
+    assert_eq!(fired.iter().filter(|(n, _)| *n == 1).count(), 10);
+    assert_eq!(
+        fired.iter().filter(|(n, _)| *n == 5).collect::<Vec<_>>(),
+        [&(5, 10), &(5, 20)]
+    );
+}
+
+#[test]
+fn fraction_delta_since() {
+    let mut progressor = (0..8).progress();
+    let (first, _) = progressor.next().unwrap();
+    let (fourth, _) = progressor.nth(2).unwrap();
+    assert_eq!(first.fraction(), Some(0.125));
+    assert_eq!(fourth.fraction(), Some(0.5));
+    assert_eq!(fourth.fraction_delta_since(&first), Some(0.375));
+    assert_eq!(first.fraction_delta_since(&fourth), Some(-0.375));
+    assert_eq!(fourth.fraction_delta_since(&fourth), Some(0.));
+
+    let (unknown, _) = (0..).progress().next().unwrap();
+    assert_eq!(fourth.fraction_delta_since(&unknown), None);
+    assert_eq!(unknown.fraction_delta_since(&fourth), None);
+}
+
+#[test]
+fn safe_math() {
+    // No time has passed
+    let mut progressor = (0..10).fake_progress().with_safe_math();
+    let fake_now = progressor.started_iterating;
+    progressor.set_fake_now(fake_now);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.rate(), 0.);
+    assert_eq!(state.fraction(), Some(0.1));
+    assert_eq!(state.eta(), Some(Duration::ZERO));
+    // Without it
+    let mut progressor = (0..10).fake_progress();
+    progressor.set_fake_now(fake_now);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.rate(), f64::INFINITY);
+
+    // A total of 0
+    let mut progressor = (0..).fake_progress().assume_size(0).with_safe_math();
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.fraction(), None);
+    assert_eq!(state.percent(), None);
+    assert_eq!(state.eta(), None);
+    assert_eq!(state.estimated_total_time(), None);
+    let mut progressor = (0..).fake_progress().assume_size(0);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.fraction(), Some(f64::INFINITY));
+
+    // A NaN position
+    let mut progressor = (0..).fake_optional_progress(1).with_safe_math();
+    progressor.set_position(f64::NAN, 1.);
+    let (state, _) = progressor.next().unwrap();
+    let state = state.unwrap();
+    assert_eq!(state.fraction(), None);
+    assert_eq!(state.eta(), None);
+
+    // A position of 0
+    let mut progressor = (0..).fake_optional_progress(1).with_safe_math();
+    progressor.set_position(0., 1.);
+    let (state, _) = progressor.next().unwrap();
+    let state = state.unwrap();
+    assert_eq!(state.fraction(), Some(0.));
+    assert_eq!(state.eta(), None);
+    assert_eq!(state.estimated_total_time(), None);
+
+    // No time between items for the averages
+    let mut progressor = (0..10)
+        .fake_progress()
+        .with_rolling_average(3)
+        .with_exp_average(0.5)
+        .with_safe_math();
+    let fake_now = progressor.started_iterating + Duration::from_secs(1);
+    for _ in 0..3 {
+        progressor.set_fake_now(fake_now);
+        let (state, _) = progressor.next().unwrap();
+        assert_eq!(state.rolling_average_rate(), None);
+        assert_eq!(state.exp_average_rate(), None);
+        assert_eq!(state.smoothed_rate(), None);
+        assert!(state.rate().is_finite());
+    }
+}
+
+#[test]
+fn add_discovered() {
+    // A tree where each node at depth d has 3 - d children
+    let mut to_visit = vec![0];
+    let mut progressor = (0..).progress();
+    progressor.add_discovered(1);
+    let mut fractions = Vec::new();
+    while let Some(depth) = to_visit.pop() {
+        let (state, _) = progressor.next().unwrap();
+        fractions.push(state.fraction().unwrap());
+        let children = 3_usize.saturating_sub(depth);
+        progressor.add_discovered(children);
+        to_visit.extend(std::iter::repeat_n(depth + 1, children));
+    }
+    // 1 + 3 + 3×2 + 3×2×1
+    assert_eq!(fractions.len(), 16);
+    assert_eq!(progressor.current_record().fraction(), Some(1.));
+    assert_eq!(fractions[0], 1.);
+    // Root, then the last child, which has 2 children
+    assert_eq!(fractions[1], 2. / 4.);
+    assert_eq!(fractions[2], 3. / 6.);
+    assert_eq!(fractions[15], 1.);
+    // It goes down when more are discovered
+    assert!(fractions.windows(2).any(|w| w[1] < w[0]));
+}
+
+#[test]
+fn monotonic_eta_countdown() {
+    let etas = |monotonic: bool| {
+        let mut progressor = (0..100).fake_progress();
+        if monotonic {
+            progressor = progressor.with_monotonic_eta_countdown();
+        }
+        let mut fake_now = progressor.started_iterating;
+        let mut etas = Vec::new();
+        for i in 0..60 {
+            // About 1 per sec, with some jitter, then slows down a lot
+            fake_now += if i >= 50 {
+                Duration::from_secs(10)
+            } else if i % 2 == 0 {
+                Duration::from_millis(800)
+            } else {
+                Duration::from_millis(1_200)
+            };
+            progressor.set_fake_now(fake_now);
+            let (state, _) = progressor.next().unwrap();
+            etas.push(state.eta().unwrap());
+        }
+        etas
+    };
+    // Ignoring the first few items, which are never representative, and the slowdown
+    let increases = |etas: &[Duration]| {
+        etas.windows(2)
+            .skip(2)
+            .take(47)
+            .filter(|w| w[1] > w[0])
+            .count()
+    };
+
+    // Without it, the jitter makes it go up & down
+    let raw = etas(false);
+    assert!(increases(&raw) > 5);
+
+    let smooth = etas(true);
+    assert_eq!(increases(&smooth), 0);
+    // It counts down by the time since the last item
+    assert_eq!(smooth[10] - smooth[11], Duration::from_millis(1_200));
+    assert_eq!(smooth[11] - smooth[12], Duration::from_millis(800));
+    // Once it really slows down, it goes up
+    assert!(smooth[59] > smooth[49]);
+}
+
+#[test]
+fn buffer_fraction() {
+    let mut progressor = (0..10).progress();
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.buffer_fraction(), None);
+
+    progressor.set_buffer_depth(3, 4);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.buffer_fraction(), Some(0.75));
+    // Kept until changed
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.buffer_fraction(), Some(0.75));
+    assert_eq!(progressor.current_record().buffer_fraction(), Some(0.75));
+
+    progressor.set_buffer_depth(0, 4);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.buffer_fraction(), Some(0.));
+
+    progressor.set_buffer_depth(0, 0);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.buffer_fraction(), None);
+}
+
+#[test]
+fn bytes_and_items() {
+    let lines: Vec<String> = (0..5_000).map(|i| "x".repeat(i % 10)).collect();
+    let byte_total: u64 = lines.iter().map(|line| line.len() as u64 + 1).sum();
+    assert_eq!(byte_total, 27_500);
+    let mut progressor = lines
+        .into_iter()
+        .fake_progress()
+        .with_bytes(byte_total, |line| line.len() as u64 + 1);
+    let start = progressor.started_iterating;
+    for _ in 0..1_233 {
+        progressor.next().unwrap();
+    }
+    progressor.set_fake_now(start + Duration::from_secs(2));
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.num_done(), 1_234);
+    assert_eq!(state.fraction(), Some(1_234. / 5_000.));
+    assert_eq!(state.rate(), 617.);
+    // 123 lines of 1 to 10 bytes, plus 4 of 1 to 4 bytes
+    assert_eq!(state.bytes_done(), 6_775);
+    assert_eq!(state.byte_total(), Some(27_500));
+    assert_eq!(state.byte_fraction(), Some(6_775. / 27_500.));
+    assert_eq!(state.byte_rate(), 3_387.5);
+
+    for _ in 0..3_765 {
+        progressor.next().unwrap();
+    }
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.fraction(), Some(1.));
+    assert_eq!(state.byte_fraction(), Some(1.));
+    assert_eq!(state.bytes_done(), 27_500);
+
+    // Without the total
+    let mut progressor = (0..).fake_progress().with_bytes(None, |_| 100);
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.bytes_done(), 100);
+    assert_eq!(state.byte_fraction(), None);
+}
+
+#[test]
+fn print_status_every_n_sec() {
+    let mut output = Vec::new();
+    let mut progressor = (0..10).fake_progress();
+    let start = progressor.started_iterating;
+    for (secs, status) in [
+        (1, "a long status"),
+        (1, "skipped"),
+        (2, "short"),
+        (3, "longer again"),
+    ] {
+        progressor.set_fake_now(start + Duration::from_millis(secs * 1_000 + 500));
+        progressor
+            .print_status_every_n_sec(&mut output, 1., status)
+            .unwrap();
+    }
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "\ra long status\rshort        \rlonger again"
+    );
+}
+
+#[test]
+fn print_status_every_n_sec_invalid() {
+    for n in [-1., f32::NAN, f32::INFINITY] {
+        let mut output = Vec::new();
+        let mut progressor = (0..10).fake_progress();
+        let start = progressor.started_iterating;
+        progressor.set_fake_now(start + Duration::from_secs(1_000));
+        progressor
+            .print_status_every_n_sec(&mut output, n, "status")
+            .unwrap();
+        assert!(output.is_empty());
+    }
+}
+
+#[test]
+fn print_every_n_to_output() {
+    let output = SharedBuffer::default();
+    let mut progressor = (0..10)
+        .fake_optional_progress(100)
+        .with_output(Output::Writer(Box::new(output.clone())));
+    let start = progressor.started_iterating;
+    for secs in 0..4 {
+        let fake_now = start + Duration::from_millis(secs * 600);
+        progressor.set_fake_now(fake_now);
+        let (_, i) = progressor.next().unwrap();
+        // `.next()` uses the fake time, so set it again for the checks
+        progressor.set_fake_now(fake_now);
+        progressor.print_every_n_sec(1., format!("sec {} ", i));
+        progressor.print_every_n_items(2, format!("item {} ", i));
+    }
+    assert_eq!(output.contents(), "item 0 sec 2 item 2 ");
+}
+
+#[test]
+fn eta_as_instant() {
+    let mut progressor = (0..10).fake_progress();
+    let start = progressor.started_iterating;
+    progressor.set_fake_now(start + Duration::from_secs(3));
+    let (state, _) = progressor.next().unwrap();
+    assert_eq!(state.estimated_total_time(), Some(Duration::from_secs(30)));
+    assert_eq!(
+        state.eta_as_instant(),
+        Some(start + Duration::from_secs(30))
+    );
+
+    let (state, _) = (0..).fake_progress().next().unwrap();
+    assert_eq!(state.eta_as_instant(), None);
+}
+
+#[test]
+fn eta_band() {
+    let mut progressor = (0..100).fake_progress().with_timestamps();
+    assert_eq!(progressor.eta_band(10., 90.), None);
+    let mut fake_now = progressor.started_iterating;
+    // Durations of 1 to 10 sec, in a jumbled order, twice, and then 1 more item
+    for i in 0..21 {
+        fake_now += Duration::from_secs((i * 7) % 10 + 1);
+        progressor.set_fake_now(fake_now);
+        progressor.next().unwrap();
+    }
+    // 79 items left. The first duration isn't used, so there are 2 durations of each of 1 to 10
+    // sec.
+    assert_eq!(
+        progressor.eta_band(0., 100.),
+        Some((Duration::from_secs(79), Duration::from_secs(790)))
+    );
+    // Between the 5 & 6 sec
+    assert_eq!(
+        progressor.eta_band(50., 50.),
+        Some((
+            Duration::from_millis(434_500),
+            Duration::from_millis(434_500)
+        ))
+    );
+    let (lo, hi) = progressor.eta_band(10., 90.).unwrap();
+    assert!((lo.as_secs_f64() - 1.9 * 79.).abs() < 1e-6);
+    assert!((hi.as_secs_f64() - 9.1 * 79.).abs() < 1e-6);
+
+    // No total
+    let mut progressor = (0..).fake_progress().with_timestamps();
+    progressor.next();
+    progressor.next();
+    assert_eq!(progressor.eta_band(10., 90.), None);
+}
+
+#[test]
+fn phase_progress() {
+    let mut phases = PhaseProgress::new();
+    assert_eq!(phases.fraction(), 0.);
+    let mut fractions = vec![];
+    for &(weight, len) in &[(0.5, 10), (0.2, 4), (0.3, 6)] {
+        let phase = phases.phase(weight);
+        for (state, _) in phase.progress(0..len) {
+            fractions.push(state.fraction().unwrap());
+            assert!((phases.fraction() - state.fraction().unwrap()).abs() < 1e-9);
+        }
+    }
+    assert!((phases.fraction() - 1.).abs() < 1e-9);
+
+    // 20 items, each step 0.05 of the whole
+    assert_eq!(fractions.len(), 20);
+    for (i, fraction) in fractions.iter().enumerate() {
+        assert!(
+            (fraction - (i + 1) as f64 * 0.05).abs() < 1e-9,
+            "{} {}",
+            i,
+            fraction
+        );
+    }
+
+    // Part way through a phase
+    let mut phases = PhaseProgress::new();
+    drop(phases.phase(0.25));
+    let phase = phases.phase(0.5);
+    let mut progressor = phase.progress(0..4);
+    progressor.next();
+    assert!((phases.fraction() - 0.375).abs() < 1e-9);
+    drop(progressor);
+    drop(phase);
+    assert!((phases.fraction() - 0.75).abs() < 1e-9);
+}
+
+/// The level of an event, and the names & values of its fields
+#[cfg(feature = "tracing")]
+type CapturedEvent = (tracing::Level, Vec<(String, String)>);
+
+/// A `tracing` subscriber which keeps the fields of each event
+#[cfg(feature = "tracing")]
+#[derive(Clone, Default)]
+struct CapturedEvents(Arc<std::sync::Mutex<Vec<CapturedEvent>>>);
+
+#[cfg(feature = "tracing")]
+impl tracing::Subscriber for CapturedEvents {
+    fn enabled(&self, _metadata: &tracing::Metadata) -> bool {
+        true
+    }
+    fn new_span(&self, _span: &tracing::span::Attributes) -> tracing::span::Id {
+        tracing::span::Id::from_u64(1)
+    }
+    fn record(&self, _span: &tracing::span::Id, _values: &tracing::span::Record) {}
+    fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+    fn event(&self, event: &tracing::Event) {
+        struct Fields(Vec<(String, String)>);
+        impl tracing::field::Visit for Fields {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                self.0
+                    .push((field.name().to_string(), format!("{:?}", value)));
+            }
+        }
+        let mut fields = Fields(vec![]);
+        event.record(&mut fields);
+        self.0
+            .lock()
+            .unwrap()
+            .push((*event.metadata().level(), fields.0));
+    }
+    fn enter(&self, _span: &tracing::span::Id) {}
+    fn exit(&self, _span: &tracing::span::Id) {}
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn trace_every_n_sec() {
+    let events = CapturedEvents::default();
+    tracing::subscriber::with_default(events.clone(), || {
+        let mut progressor = (0..10)
+            .fake_progress()
+            .trace_every_n_sec(1., tracing::Level::INFO);
+        let start = progressor.started_iterating;
+        // One item every 0.4 sec, so events at 1.2 sec & 2 sec
+        for i in 1..=5 {
+            progressor.set_fake_now(start + Duration::from_millis(i * 400));
+            progressor.next().unwrap();
+        }
+    });
+    let events = events.0.lock().unwrap();
+    assert_eq!(events.len(), 2);
+    let (level, fields) = &events[0];
+    assert_eq!(*level, tracing::Level::INFO);
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.clone())
+    };
+    assert_eq!(field("num"), Some("3".to_string()));
+    assert_eq!(field("percent"), Some("30.0".to_string()));
+    assert_eq!(field("rate"), Some("2.5".to_string()));
+    assert_eq!(field("eta_secs"), Some("2.8".to_string()));
+}
+
+/// A `log` backend which keeps the messages
+#[cfg(feature = "log")]
+struct CapturedLogs(std::sync::Mutex<Vec<(log::Level, String)>>);
+
+#[cfg(feature = "log")]
+impl log::Log for CapturedLogs {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+    fn log(&self, record: &log::Record) {
+        self.0
+            .lock()
+            .unwrap()
+            .push((record.level(), record.args().to_string()));
+    }
+    fn flush(&self) {}
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn log_every_n_sec() {
+    static LOGS: CapturedLogs = CapturedLogs(std::sync::Mutex::new(Vec::new()));
+    log::set_logger(&LOGS).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let mut progressor = (0..10)
+        .fake_progress()
+        .log_every_n_sec(1., log::Level::Warn);
+    let start = progressor.started_iterating;
+    // One item every 0.4 sec, so lines at 1.2 sec & 2 sec
+    for i in 1..=5 {
+        progressor.set_fake_now(start + Duration::from_millis(i * 400));
+        progressor.next().unwrap();
+    }
+    let mut unknown = (0..)
+        .filter(|x| x % 2 == 0)
+        .fake_progress()
+        .log_every_n_sec(1., log::Level::Info);
+    let start = unknown.started_iterating;
+    unknown.set_fake_now(start + Duration::from_secs(2));
+    unknown.next().unwrap();
+
+    assert_eq!(
+        *LOGS.0.lock().unwrap(),
+        vec![
+            (
+                log::Level::Warn,
+                "30.0% done, 2.5 per sec, ETA 2s".to_string()
+            ),
+            (
+                log::Level::Warn,
+                "50.0% done, 2.5 per sec, ETA 2s".to_string()
+            ),
+            (log::Level::Info, "?% done, 0.5 per sec, ETA ?".to_string()),
+        ]
+    );
+}